@@ -3,11 +3,34 @@ import init, * as wasm from 'rust-renderer';
 import { AppBar, Toolbar, Button, Menu, MenuItem, Dialog, DialogTitle, DialogContent, DialogActions } from '@mui/material';
 import { SketchPicker } from 'react-color'; // npm install react-color
 
+// Saves `contents` (a string or bytes) as a file through a temporary link
+function downloadFile(name, contents) {
+  const url = URL.createObjectURL(new Blob([contents]));
+  const link = document.createElement('a');
+  link.href = url;
+  link.download = name;
+  link.click();
+  URL.revokeObjectURL(url);
+}
+
 function DropdownAppBar({ onModelColorClick }) {
 
   const fileInputRef = useRef(null);
+  // Whether the picked model replaces the scene or is added to it
+  const replaceSceneRef = useRef(true);
+  const textureInputRef = useRef(null);
   const [fileMenuAnchorEl, setFileMenuAnchorEl] = React.useState(null);
   const [drawMenuAnchorEl, setDrawMenuAnchorEl] = React.useState(null);
+  const [transformMenuAnchorEl, setTransformMenuAnchorEl] = React.useState(null);
+  const [editMenuAnchorEl, setEditMenuAnchorEl] = React.useState(null);
+  // What can be undone and redone, as told by the renderer's history-change events
+  const [history, setHistory] = useState({ canUndo: false, canRedo: false, undoLabel: null, redoLabel: null });
+
+  useEffect(() => {
+    const handleHistoryChange = (event) => setHistory(event.detail);
+    window.addEventListener('history-change', handleHistoryChange);
+    return () => window.removeEventListener('history-change', handleHistoryChange);
+  }, []);
 
   const handleFileMenuClick = (event) => {
     setFileMenuAnchorEl(event.currentTarget);
@@ -17,6 +40,14 @@ function DropdownAppBar({ onModelColorClick }) {
     setFileMenuAnchorEl(null);
   };
 
+  const handleEditMenuClick = (event) => {
+    setEditMenuAnchorEl(event.currentTarget);
+  };
+
+  const handleEditMenuClose = () => {
+    setEditMenuAnchorEl(null);
+  };
+
   const handleDrawMenuClick = (event) => {
     setDrawMenuAnchorEl(event.currentTarget);
   };
@@ -25,26 +56,171 @@ function DropdownAppBar({ onModelColorClick }) {
     setDrawMenuAnchorEl(null);
   };
 
+  const handleTransformMenuClick = (event) => {
+    setTransformMenuAnchorEl(event.currentTarget);
+  };
+
+  const handleTransformMenuClose = () => {
+    setTransformMenuAnchorEl(null);
+  };
+
   const handleFileOpen = () => {
+    replaceSceneRef.current = true;
     fileInputRef.current.click();
     handleFileMenuClose();
   };
 
-  const handleFileChange = (event) => {
+  const handleFileImport = () => {
+    replaceSceneRef.current = false;
+    fileInputRef.current.click();
+    handleFileMenuClose();
+  };
+
+  const handleFileChange = async (event) => {
+    const files = Array.from(event.target.files);
+    // With several files picked, the model is told apart from its .mtl, .bin and textures by name;
+    // the renderer detects the actual format from the contents
+    const modelFile = files.length === 1
+      ? files[0]
+      : files.find((file) => /\.(obj|stl|ply|gltf|glb)$/i.test(file.name));
+    if (modelFile) {
+      if (window.wasm && window.wasm.process_file_bytes) {
+        try {
+          window.wasm.clear_assets();
+          for (const file of files.filter((file) => file !== modelFile)) {
+            window.wasm.add_asset(file.name, new Uint8Array(await file.arrayBuffer()));
+          }
+          // Old nodes go only once the new model has loaded, so a failed open keeps the scene
+          const info = window.wasm.process_file_bytes(
+            new Uint8Array(await modelFile.arrayBuffer()),
+            modelFile.name,
+            replaceSceneRef.current,
+          );
+          console.log(`Loaded ${info.vertex_count} vertices, ${info.triangle_count} triangles`);
+          // Attach the gizmo to what was just loaded
+          window.wasm.select_node(info.node_id);
+          info.free();
+        } catch (error) {
+          console.error(error);
+          alert(`Could not open ${modelFile.name}: ${error.message}`);
+        }
+      } else {
+        console.error("Rust WebAssembly function not found!");
+      }
+    }
+    // Allow the same files to be picked again
+    event.target.value = '';
+  };
+
+  const handleTextureOpen = () => {
+    textureInputRef.current.click();
+    handleFileMenuClose();
+  };
+
+  const handleTextureChange = (event) => {
     const file = event.target.files[0];
     if (file) {
       const reader = new FileReader();
       reader.onload = (e) => {
-        const fileContent = e.target.result;
-
-        if (window.wasm && window.wasm.process_file_content) {
-          window.wasm.process_file_content(fileContent);
+        if (window.wasm && window.wasm.set_model_texture) {
+          try {
+            window.wasm.set_model_texture(new Uint8Array(e.target.result));
+          } catch (error) {
+            console.error(error);
+            alert(`Could not open ${file.name}: ${error}`);
+          }
         } else {
-          console.error("Rust WebAssembly function not found!");
+          console.error("Rust WebAssembly function 'set_model_texture' not found!");
         }
       };
-      reader.readAsText(file);
+      reader.readAsArrayBuffer(file);
+    }
+    // Allow the same image to be picked again
+    event.target.value = '';
+  };
+
+  const handleClearTexture = () => {
+    if (window.wasm && window.wasm.clear_model_texture) {
+      window.wasm.clear_model_texture();
+    } else {
+      console.error("Rust WebAssembly function 'clear_model_texture' not found!");
+    }
+    handleFileMenuClose();
+  };
+
+  const handleExportObj = () => {
+    if (window.wasm && window.wasm.export_obj) {
+      try {
+        downloadFile('model.obj', window.wasm.export_obj());
+        const mtl = window.wasm.export_mtl();
+        if (mtl !== undefined) {
+          downloadFile('model.mtl', mtl);
+        }
+      } catch (error) {
+        console.error(error);
+        alert(`Could not export the model: ${error}`);
+      }
+    } else {
+      console.error("Rust WebAssembly function 'export_obj' not found!");
+    }
+    handleFileMenuClose();
+  };
+
+  // Binary STL or PLY, in the model's own units
+  const handleExportMesh = (format) => {
+    const exporter = window.wasm && window.wasm[`export_${format}`];
+    if (exporter) {
+      try {
+        downloadFile(`model.${format}`, exporter(true));
+      } catch (error) {
+        console.error(error);
+        alert(`Could not export the model: ${error}`);
+      }
+    } else {
+      console.error(`Rust WebAssembly function 'export_${format}' not found!`);
+    }
+    handleFileMenuClose();
+  };
+
+  const handleExportGlb = () => {
+    if (window.wasm && window.wasm.export_glb) {
+      try {
+        downloadFile('model.glb', window.wasm.export_glb());
+      } catch (error) {
+        console.error(error);
+        alert(`Could not export the model: ${error}`);
+      }
+    } else {
+      console.error("Rust WebAssembly function 'export_glb' not found!");
+    }
+    handleFileMenuClose();
+  };
+
+  const handleFrameModel = () => {
+    if (window.wasm && window.wasm.frame_model) {
+      window.wasm.frame_model();
+    } else {
+      console.error("Rust WebAssembly function 'frame_model' not found!");
     }
+    handleDrawMenuClose();
+  };
+
+  const handleNormalMode = (mode) => {
+    if (window.wasm && window.wasm.set_normal_mode) {
+      window.wasm.set_normal_mode(mode, 30.0);
+    } else {
+      console.error("Rust WebAssembly function 'set_normal_mode' not found!");
+    }
+    handleDrawMenuClose();
+  };
+
+  const handlePointSize = (size) => {
+    if (window.wasm && window.wasm.set_point_size) {
+      window.wasm.set_point_size(size);
+    } else {
+      console.error("Rust WebAssembly function 'set_point_size' not found!");
+    }
+    handleDrawMenuClose();
   };
 
   const handleToggleWireframe = () => {
@@ -56,6 +232,30 @@ function DropdownAppBar({ onModelColorClick }) {
     handleDrawMenuClose();
   };
 
+  // Calls `undo` or `redo`
+  const handleHistory = (name) => {
+    if (window.wasm && window.wasm[name]) {
+      try {
+        window.wasm[name]();
+      } catch (error) {
+        console.error(`Could not ${name}:`, error);
+      }
+    } else {
+      console.error(`Rust WebAssembly function '${name}' not found!`);
+    }
+    handleEditMenuClose();
+  };
+
+  // Calls a gizmo setting such as set_gizmo_mode with `value`
+  const handleGizmo = (name, value) => {
+    if (window.wasm && window.wasm[name]) {
+      window.wasm[name](value);
+    } else {
+      console.error(`Rust WebAssembly function '${name}' not found!`);
+    }
+    handleTransformMenuClose();
+  };
+
   return (
         <>
     <AppBar position="sticky" style={{width: 'calc(100vw - 16px)', backgroundColor: '#262626', margin: 0, padding: 0, boxSizing: 'border-box'}}>
@@ -72,6 +272,31 @@ function DropdownAppBar({ onModelColorClick }) {
           onClose={handleFileMenuClose}
         >
           <MenuItem onClick={handleFileOpen}>Open</MenuItem>
+          <MenuItem onClick={handleFileImport}>Import</MenuItem>
+          <MenuItem onClick={handleTextureOpen}>Load Texture</MenuItem>
+          <MenuItem onClick={handleClearTexture}>Clear Texture</MenuItem>
+          <MenuItem onClick={handleExportObj}>Export OBJ</MenuItem>
+          <MenuItem onClick={() => handleExportMesh('stl')}>Export STL</MenuItem>
+          <MenuItem onClick={() => handleExportMesh('ply')}>Export PLY</MenuItem>
+          <MenuItem onClick={handleExportGlb}>Export GLB</MenuItem>
+        </Menu>
+        <Button
+          color="inherit"
+          onClick={handleEditMenuClick}
+        >
+          Edit
+        </Button>
+        <Menu
+          anchorEl={editMenuAnchorEl}
+          open={Boolean(editMenuAnchorEl)}
+          onClose={handleEditMenuClose}
+        >
+          <MenuItem disabled={!history.canUndo} onClick={() => handleHistory('undo')}>
+            {history.undoLabel ? `Undo ${history.undoLabel}` : 'Undo'}
+          </MenuItem>
+          <MenuItem disabled={!history.canRedo} onClick={() => handleHistory('redo')}>
+            {history.redoLabel ? `Redo ${history.redoLabel}` : 'Redo'}
+          </MenuItem>
         </Menu>
         <Button
           color="inherit"
@@ -84,7 +309,13 @@ function DropdownAppBar({ onModelColorClick }) {
           open={Boolean(drawMenuAnchorEl)}
           onClose={handleDrawMenuClose}
         >
+          <MenuItem onClick={handleFrameModel}>Frame Model</MenuItem>
           <MenuItem onClick={handleToggleWireframe}>Toggle Wireframe</MenuItem>
+          <MenuItem onClick={() => handleNormalMode('flat')}>Flat Normals</MenuItem>
+          <MenuItem onClick={() => handleNormalMode('smooth')}>Smooth Normals</MenuItem>
+          <MenuItem onClick={() => handleNormalMode('crease')}>Creased Normals</MenuItem>
+          <MenuItem onClick={() => handlePointSize(2.0)}>Small Points</MenuItem>
+          <MenuItem onClick={() => handlePointSize(5.0)}>Large Points</MenuItem>
           <MenuItem
             onClick={() => {
               onModelColorClick();
@@ -94,16 +325,42 @@ function DropdownAppBar({ onModelColorClick }) {
             Model Color
           </MenuItem>
         </Menu>
+        <Button
+          color="inherit"
+          onClick={handleTransformMenuClick}
+        >
+          Transform
+        </Button>
+        <Menu
+          anchorEl={transformMenuAnchorEl}
+          open={Boolean(transformMenuAnchorEl)}
+          onClose={handleTransformMenuClose}
+        >
+          <MenuItem onClick={() => handleGizmo('set_gizmo_mode', 'translate')}>Move</MenuItem>
+          <MenuItem onClick={() => handleGizmo('set_gizmo_mode', 'rotate')}>Rotate</MenuItem>
+          <MenuItem onClick={() => handleGizmo('set_gizmo_mode', 'scale')}>Scale</MenuItem>
+          <MenuItem onClick={() => handleGizmo('set_gizmo_space', 'world')}>World Axes</MenuItem>
+          <MenuItem onClick={() => handleGizmo('set_gizmo_space', 'local')}>Local Axes</MenuItem>
+          <MenuItem onClick={() => handleGizmo('select_node', undefined)}>Hide Gizmo</MenuItem>
+        </Menu>
       </Toolbar>
     </AppBar>
 
     {/* Hidden file input */}
     <input
       type="file"
+      multiple
       ref={fileInputRef}
       style={{ display: 'none' }}
       onChange={handleFileChange}
     />
+    <input
+      type="file"
+      accept="image/png,image/jpeg"
+      ref={textureInputRef}
+      style={{ display: 'none' }}
+      onChange={handleTextureChange}
+    />
     </>
   );
 }
@@ -121,8 +378,16 @@ function App() {
     const resizeCanvas = () => {
       if (canvas) {
         const windowBorder = 15;
-        canvas.width = window.innerWidth;
-        canvas.height = window.innerHeight - windowBorder;
+        const width = window.innerWidth;
+        const height = window.innerHeight - windowBorder;
+
+        // Once the renderer is running it owns the drawing buffer size, viewport and projection
+        if (window.wasm && window.wasm.resize) {
+          window.wasm.resize(width, height);
+        } else {
+          canvas.width = width;
+          canvas.height = height;
+        }
       }
     };
 
@@ -165,6 +430,13 @@ function App() {
     }
   };
 
+  // Lets the next color change undo separately from the one just picked
+  const handleColorChangeComplete = () => {
+    if (window.wasm && window.wasm.end_edit) {
+      window.wasm.end_edit();
+    }
+  };
+
   return (
     <div style={{height: 'calc(100vh - 16px)'}}>
       <DropdownAppBar onModelColorClick={handleModelColorClick} />
@@ -172,7 +444,11 @@ function App() {
       <Dialog open={colorDialogOpen} onClose={() => setColorDialogOpen(false)}>
         <DialogTitle>Pick Model Color</DialogTitle>
         <DialogContent>
-          <SketchPicker color={modelColor} onChange={handleColorChange} />
+          <SketchPicker
+            color={modelColor}
+            onChange={handleColorChange}
+            onChangeComplete={handleColorChangeComplete}
+          />
         </DialogContent>
         <DialogActions>
           <Button onClick={() => setColorDialogOpen(false)} color="primary">
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+/// Files an imported model refers to by name, such as the material libraries and textures of an
+/// OBJ.
+///
+/// Files are matched by their base name, ignoring case and any directories in the reference.
+#[derive(Debug, Clone, Default)]
+pub struct Assets {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, bytes: Vec<u8>) {
+        self.files.insert(asset_key(name), bytes);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(&asset_key(name)).map(Vec::as_slice)
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+// Base name in lower case; references use either path separator
+fn asset_key(name: &str) -> String {
+    name.trim()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
@@ -0,0 +1,299 @@
+//! Converts models between the formats the web renderer reads and writes, and reports on them.
+//!
+//! Uses the same importers and exporters as the web renderer, so a build pipeline sees models the
+//! way the viewer does. Run with `--help` for the options.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use renderer_core::assets::Assets;
+use renderer_core::bounds::BoundingSphere;
+use renderer_core::camera::Camera;
+use renderer_core::export::{world_meshes, write_obj, MaterialLibrary};
+use renderer_core::geometry::NormalMode;
+use renderer_core::gltf_export::write_glb;
+use renderer_core::import::{
+    all_nodes, decode_texture_maps, parse_model, texture_map_files, ImportedMesh, ImportedScene,
+};
+use renderer_core::mtl::write_mtl;
+use renderer_core::ply::write_ply;
+use renderer_core::render::{render, RenderOptions};
+use renderer_core::stats::MeshStats;
+use renderer_core::stl::write_stl;
+use renderer_core::texture::TextureImage;
+
+const USAGE: &str = "\
+usage: model-tool <input> [options]
+
+Prints statistics about a model and optionally converts it or renders a preview.
+
+options:
+  -o, --output <file>       write the model as .obj, .stl, .ply or .glb
+      --ascii               write STL or PLY as text instead of binary
+      --preview <file.png>  render the model to a PNG image
+      --size <WxH>          preview size in pixels (default 512x512)
+      --wireframe           render the preview as wireframe
+      --normals <mode>      flat, smooth or crease[=ANGLE] for models without normals
+      --assets <dir>        also look for material libraries and textures in <dir>
+  -h, --help                show this help";
+
+// File types a model may refer to, read from the asset directories
+const ASSET_EXTENSIONS: [&str; 5] = ["mtl", "png", "jpg", "jpeg", "bin"];
+
+#[derive(Debug, Default)]
+struct Options {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    ascii: bool,
+    preview: Option<PathBuf>,
+    size: Option<(u32, u32)>,
+    wireframe: bool,
+    normals: NormalMode,
+    asset_dirs: Vec<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("model-tool: {}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("model-tool: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// `None` when help was asked for
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut input = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => options.output = Some(value(&arg)?.into()),
+            "--ascii" => options.ascii = true,
+            "--preview" => options.preview = Some(value(&arg)?.into()),
+            "--size" => options.size = Some(parse_size(&value(&arg)?)?),
+            "--wireframe" => options.wireframe = true,
+            "--normals" => options.normals = parse_normals(&value(&arg)?)?,
+            "--assets" => options.asset_dirs.push(value(&arg)?.into()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if input.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+    options.input = input.ok_or("no input file given")?;
+    Ok(Some(options))
+}
+
+fn parse_size(text: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("size '{}' is not WIDTHxHEIGHT", text);
+    let (width, height) = text.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+fn parse_normals(text: &str) -> Result<NormalMode, String> {
+    match text.split_once('=') {
+        None if text == "flat" => Ok(NormalMode::Flat),
+        None if text == "smooth" => Ok(NormalMode::Smooth),
+        None if text == "crease" => Ok(NormalMode::default()),
+        Some(("crease", angle)) => angle
+            .parse()
+            .map(NormalMode::Crease)
+            .map_err(|_| format!("crease angle '{}' is not a number", angle)),
+        _ => Err(format!("unknown normal mode '{}'", text)),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let input = &options.input;
+    let bytes = std::fs::read(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    // Files in the --assets directories take precedence over those beside the model
+    let mut assets = Assets::new();
+    let model_dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    for dir in &options.asset_dirs {
+        read_assets(dir, &mut assets)?;
+    }
+    if !options.asset_dirs.iter().any(|d| d == model_dir) {
+        read_assets(model_dir, &mut assets)?;
+    }
+
+    let scene = parse_model(&bytes, options.normals, &assets)
+        .map_err(|e| format!("{}: {}", input.display(), e))?;
+    let meshes: Vec<&ImportedMesh> = all_nodes(&scene.nodes)
+        .into_iter()
+        .filter_map(|n| n.mesh.as_ref())
+        .collect();
+    let mut warnings: Vec<String> = meshes
+        .iter()
+        .flat_map(|m| m.warnings.iter().cloned())
+        .collect();
+    warnings.extend(scene.warnings.iter().cloned());
+
+    print_stats(&MeshStats::new(&scene.nodes));
+
+    if let Some(ref output) = options.output {
+        let files = texture_map_files(&meshes, &scene.images, &assets, &mut warnings);
+        let images = files
+            .into_iter()
+            .map(|(name, bytes)| (name, bytes.to_vec()))
+            .collect();
+        write_model(&scene, images, output, options.ascii)?;
+    }
+    if let Some(ref preview) = options.preview {
+        let images = decode_texture_maps(&meshes, &scene.images, &assets, &mut warnings);
+        write_preview(&scene, &images, preview, options)?;
+    }
+
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+    Ok(())
+}
+
+// Adds the files in `dir` a model may refer to, keeping any already added under the same name
+fn read_assets(dir: &Path, assets: &mut Assets) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if !path.is_file()
+            || !ASSET_EXTENSIONS.contains(&extension.as_str())
+            || assets.get(name).is_some()
+        {
+            continue;
+        }
+        let bytes = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        assets.insert(name, bytes);
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &MeshStats) {
+    println!("nodes:              {}", stats.nodes);
+    println!("meshes:             {}", stats.meshes);
+    println!("vertices:           {}", stats.vertices);
+    println!("triangles:          {}", stats.triangles);
+    if stats.points > 0 {
+        println!("points:             {}", stats.points);
+    }
+    match stats.bounds {
+        Some(bounds) => {
+            let size = bounds.max - bounds.min;
+            println!(
+                "bounds:             ({}, {}, {}) to ({}, {}, {})",
+                bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
+            );
+            println!("size:               {} x {} x {}", size.x, size.y, size.z);
+        }
+        None => println!("bounds:             none"),
+    }
+    if stats.materials.is_empty() {
+        println!("materials:          none");
+    } else {
+        println!("materials:          {}", stats.materials.join(", "));
+    }
+    println!("boundary edges:     {}", stats.boundary_edges);
+    println!("non-manifold edges: {}", stats.non_manifold_edges);
+}
+
+// Writes in the format named by the extension of `path`; OBJ gets its material library beside it
+fn write_model(
+    scene: &ImportedScene,
+    images: HashMap<String, Vec<u8>>,
+    path: &Path,
+    ascii: bool,
+) -> Result<(), String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let meshes = world_meshes(&scene.nodes);
+    if meshes.is_empty() {
+        return Err("the model has no meshes to write".to_string());
+    }
+    let bytes = match extension.as_str() {
+        "obj" => {
+            let library = MaterialLibrary::new(&meshes);
+            let mtl_path = path.with_extension("mtl");
+            let mtl_file = mtl_path.file_name().and_then(|n| n.to_str());
+            if library.is_empty() {
+                write_obj(&meshes, None).into_bytes()
+            } else {
+                write_file(&mtl_path, write_mtl(&library.materials).as_bytes())?;
+                write_obj(&meshes, mtl_file).into_bytes()
+            }
+        }
+        "stl" => write_stl(&meshes, !ascii),
+        "ply" => write_ply(&meshes, !ascii),
+        "glb" => write_glb(&ImportedScene {
+            nodes: scene.nodes.clone(),
+            images,
+            warnings: vec![],
+        }),
+        "gltf" => return Err("glTF is written as a single binary .glb file".to_string()),
+        _ => {
+            return Err(format!(
+                "{}: output format must be .obj, .stl, .ply or .glb",
+                path.display()
+            ))
+        }
+    };
+    write_file(path, &bytes)
+}
+
+// Frames the whole model, looking from the web renderer's default angle
+fn write_preview(
+    scene: &ImportedScene,
+    images: &HashMap<String, TextureImage>,
+    path: &Path,
+    options: &Options,
+) -> Result<(), String> {
+    let (width, height) = options.size.unwrap_or((512, 512));
+    let render_options = RenderOptions {
+        width,
+        height,
+        wireframe: options.wireframe,
+        ..RenderOptions::default()
+    };
+    let mut camera = Camera::new(width as f32 / height as f32);
+    if let Some(bounds) = MeshStats::new(&scene.nodes).bounds {
+        let sphere = BoundingSphere::from_box(&bounds);
+        camera.frame(sphere.center, sphere.radius);
+    }
+    let image = render(&scene.nodes, images, &camera, &render_options);
+    write_file(path, &image.encode_png()?)
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,101 @@
+use cgmath::{EuclideanSpace, Matrix4, MetricSpace, Point3, Transform};
+
+use crate::vertex::Vertex;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl BoundingBox {
+    /// Returns `None` when there are no vertices.
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        let mut iter = vertices.iter().map(|v| Point3::from(v.0));
+        let first = iter.next()?;
+        Some(iter.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bounds, p| Self {
+                min: Point3::new(
+                    bounds.min.x.min(p.x),
+                    bounds.min.y.min(p.y),
+                    bounds.min.z.min(p.z),
+                ),
+                max: Point3::new(
+                    bounds.max.x.max(p.x),
+                    bounds.max.y.max(p.y),
+                    bounds.max.z.max(p.z),
+                ),
+            },
+        ))
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Axis-aligned box around the eight transformed corners.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let corner = |i: usize| {
+            transform.transform_point(Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            ))
+        };
+        let first = corner(0);
+        (1..8).map(corner).fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bounds, p| bounds.union(&Self { min: p, max: p }),
+        )
+    }
+}
+
+/// Sphere enclosing every vertex, centered on the bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere through the corners of `bounds`.
+    pub fn from_box(bounds: &BoundingBox) -> Self {
+        Self {
+            center: bounds.center(),
+            radius: bounds.center().distance(bounds.max),
+        }
+    }
+
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        let center = BoundingBox::from_vertices(vertices)?.center();
+        let radius = vertices
+            .iter()
+            .map(|v| center.distance(Point3::from(v.0)))
+            .fold(0.0, f32::max);
+        Some(Self { center, radius })
+    }
+}
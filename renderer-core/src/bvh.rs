@@ -0,0 +1,270 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::bounds::BoundingBox;
+use crate::vertex::Vertex;
+
+// Most triangles a leaf holds before it is split
+const LEAF_SIZE: usize = 4;
+
+/// Where a ray met a triangle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhHit {
+    /// Index of the triangle in the mesh's index list, counted in triangles.
+    pub triangle: u32,
+    /// Ray parameter of the hit, in units of the ray direction's length.
+    pub distance: f32,
+    /// Weights of the triangle's three corners at the hit.
+    pub barycentrics: [f32; 3],
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh, for casting rays against it.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    // Triangle corners, in the order the leaves refer to them
+    triangles: Vec<[Point3<f32>; 3]>,
+    // Index of each of `triangles` in the mesh
+    ids: Vec<u32>,
+}
+
+// A leaf holds `count` triangles from `first`, an inner node has its two children at `first` and
+// `first + 1`
+struct BvhNode {
+    bounds: BoundingBox,
+    first: usize,
+    count: usize,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over a triangle list, splitting at the median of the longest axis.
+    pub fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let corner = |i: u32| Point3::from(vertices[i as usize].0);
+        let triangles: Vec<[Point3<f32>; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| [corner(t[0]), corner(t[1]), corner(t[2])])
+            .collect();
+        let mut ids: Vec<u32> = (0..triangles.len() as u32).collect();
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            nodes.push(BvhNode {
+                bounds: bounds_of(&triangles, &ids),
+                first: 0,
+                count: ids.len(),
+            });
+            split(&mut nodes, 0, &triangles, &mut ids);
+        }
+        let triangles = ids.iter().map(|&id| triangles[id as usize]).collect();
+        Self {
+            nodes,
+            triangles,
+            ids,
+        }
+    }
+
+    /// The closest triangle in front of `origin` along `direction`, hit from either side.
+    pub fn intersect(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<BvhHit> {
+        let inverse = Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let mut closest: Option<BvhHit> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            let limit = closest.map_or(f32::INFINITY, |hit| hit.distance);
+            if !hits_box(&node.bounds, origin, inverse, limit) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend_from_slice(&[node.first, node.first + 1]);
+                continue;
+            }
+            for i in node.first..node.first + node.count {
+                if let Some((distance, barycentrics)) =
+                    intersect_triangle(&self.triangles[i], origin, direction)
+                {
+                    if distance < closest.map_or(f32::INFINITY, |hit| hit.distance) {
+                        closest = Some(BvhHit {
+                            triangle: self.ids[i],
+                            distance,
+                            barycentrics,
+                        });
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+// Splits the leaf at `index` until its leaves are small enough, reordering `ids` so each node's
+// triangles are contiguous
+fn split(nodes: &mut Vec<BvhNode>, index: usize, triangles: &[[Point3<f32>; 3]], ids: &mut [u32]) {
+    let (first, count) = (nodes[index].first, nodes[index].count);
+    if count <= LEAF_SIZE {
+        return;
+    }
+    let range = &mut ids[first..first + count];
+    let centroid = |id: &u32| {
+        let [a, b, c] = triangles[*id as usize];
+        (a.to_vec() + b.to_vec() + c.to_vec()) / 3.0
+    };
+    let centroids = range.iter().fold(None, |bounds: Option<BoundingBox>, id| {
+        let p = Point3::from_vec(centroid(id));
+        let point = BoundingBox { min: p, max: p };
+        Some(bounds.map_or(point, |b| b.union(&point)))
+    });
+    let extent = centroids.map_or(Vector3::new(0.0, 0.0, 0.0), |b| b.max - b.min);
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let half = count / 2;
+    range.select_nth_unstable_by(half, |a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+
+    let left = nodes.len();
+    for (start, len) in [(first, half), (first + half, count - half)] {
+        nodes.push(BvhNode {
+            bounds: bounds_of(triangles, &ids[start..start + len]),
+            first: start,
+            count: len,
+        });
+    }
+    nodes[index].first = left;
+    nodes[index].count = 0;
+    split(nodes, left, triangles, ids);
+    split(nodes, left + 1, triangles, ids);
+}
+
+fn bounds_of(triangles: &[[Point3<f32>; 3]], ids: &[u32]) -> BoundingBox {
+    let first = triangles[ids[0] as usize][0];
+    ids.iter().flat_map(|id| triangles[*id as usize]).fold(
+        BoundingBox {
+            min: first,
+            max: first,
+        },
+        |bounds, p| bounds.union(&BoundingBox { min: p, max: p }),
+    )
+}
+
+// Slab test, true when the ray enters the box before `limit`
+fn hits_box(bounds: &BoundingBox, origin: Point3<f32>, inverse: Vector3<f32>, limit: f32) -> bool {
+    let mut near = 0.0f32;
+    let mut far = limit;
+    for axis in 0..3 {
+        let t0 = (bounds.min[axis] - origin[axis]) * inverse[axis];
+        let t1 = (bounds.max[axis] - origin[axis]) * inverse[axis];
+        // NaN from a flat box seen edge on counts as inside the slab
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    near <= far
+}
+
+// Möller–Trumbore, returning the ray parameter and barycentrics of the hit
+fn intersect_triangle(
+    triangle: &[Point3<f32>; 3],
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+) -> Option<(f32, [f32; 3])> {
+    let [a, b, c] = *triangle;
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    // Rays running along the triangle's plane miss it
+    if determinant.abs()
+        <= f32::EPSILON * edge1.magnitude() * edge2.magnitude() * direction.magnitude()
+    {
+        return None;
+    }
+    let s = origin - a;
+    let u = s.dot(p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) / determinant;
+    (t > 0.0).then_some((t, [1.0 - u - v, u, v]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::WHITE;
+
+    fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex([x, y, z], [0.0; 3], [0.0; 2], WHITE)
+    }
+
+    // A row of unit squares along X, two triangles each, at z = 0
+    fn strip(count: usize) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for i in 0..count {
+            let x = i as f32;
+            let first = vertices.len() as u32;
+            vertices.extend([
+                vertex(x, 0.0, 0.0),
+                vertex(x + 1.0, 0.0, 0.0),
+                vertex(x + 1.0, 1.0, 0.0),
+                vertex(x, 1.0, 0.0),
+            ]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn finds_the_triangle_under_the_ray() {
+        let (vertices, indices) = strip(50);
+        let bvh = Bvh::new(&vertices, &indices);
+
+        // Square 37, below its diagonal, from the front and from behind
+        for (z, dz) in [(5.0, -1.0), (-5.0, 1.0)] {
+            let hit = bvh
+                .intersect(Point3::new(37.75, 0.25, z), Vector3::new(0.0, 0.0, dz))
+                .unwrap();
+            assert_eq!(hit.triangle, 74);
+            assert!((hit.distance - 5.0).abs() < 1e-5);
+            let [w0, w1, w2] = hit.barycentrics;
+            assert!(
+                (w0 - 0.25).abs() < 1e-5 && (w1 - 0.5).abs() < 1e-5 && (w2 - 0.25).abs() < 1e-5
+            );
+        }
+
+        assert_eq!(
+            bvh.intersect(Point3::new(60.0, 0.5, 5.0), Vector3::new(0.0, 0.0, -1.0)),
+            None
+        );
+        assert_eq!(
+            bvh.intersect(Point3::new(10.5, 0.5, 5.0), Vector3::new(0.0, 0.0, 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn returns_the_closest_of_stacked_triangles() {
+        let (mut vertices, mut indices) = strip(20);
+        // The same strip again, one unit closer to the viewer
+        let offset = vertices.len() as u32;
+        let (near, near_indices) = strip(20);
+        vertices.extend(
+            near.into_iter()
+                .map(|v| vertex({ v.0 }[0], { v.0 }[1], 1.0)),
+        );
+        indices.extend(near_indices.iter().map(|i| i + offset));
+
+        let bvh = Bvh::new(&vertices, &indices);
+        let hit = bvh
+            .intersect(Point3::new(3.5, 0.75, 10.0), Vector3::new(0.0, 0.0, -2.0))
+            .unwrap();
+        assert_eq!(hit.triangle, 40 + 7);
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+    }
+}
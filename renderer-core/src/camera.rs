@@ -0,0 +1,419 @@
+use cgmath::{
+    ortho, perspective, Deg, EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix,
+    Vector3, Vector4,
+};
+
+// Vertical field of view of the perspective projection
+pub const FOV_Y_DEGREES: f32 = 45.0;
+
+// Clip planes used while nothing has been registered with `set_scene_bounds` or framed
+const DEFAULT_NEAR: f32 = 0.1;
+const DEFAULT_FAR: f32 = 100.0;
+
+// Extra room left around a framed model
+const FRAME_MARGIN: f32 = 1.05;
+
+// Smallest near plane as a fraction of the far plane, keeps depth precision usable
+const MIN_NEAR_RATIO: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// Orbit camera looking at `target` from `distance` away.
+///
+/// The orientation is stored as the two angles the viewer has always used: `pitch` rotates the
+/// scene about the X axis and `yaw` about the Y axis, both in degrees.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub aspect: f32,
+    pub projection: Projection,
+    min_distance: f32,
+    max_distance: f32,
+    home_target: Point3<f32>,
+    home_distance: f32,
+    scene_bounds: Option<(Point3<f32>, f32)>,
+    // The sphere last framed, enclosed by the clip planes along with `scene_bounds`
+    framed_bounds: Option<(Point3<f32>, f32)>,
+}
+
+impl Camera {
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            target: Point3::new(0.0, 0.0, 0.0),
+            distance: 15.0,
+            pitch: 35.264,
+            yaw: -45.0,
+            aspect,
+            projection: Projection::Perspective,
+            min_distance: 1.0,
+            max_distance: 100.0,
+            home_target: Point3::new(0.0, 0.0, 0.0),
+            home_distance: 15.0,
+            scene_bounds: None,
+            framed_bounds: None,
+        }
+    }
+
+    /// Restores the home target and distance and looks from the given angles.
+    pub fn reset(&mut self, pitch: f32, yaw: f32) {
+        self.pitch = pitch;
+        self.yaw = yaw;
+        self.target = self.home_target;
+        self.distance = self.home_distance;
+    }
+
+    pub fn orbit(&mut self, delta_pitch: f32, delta_yaw: f32) {
+        self.pitch += delta_pitch;
+        self.yaw += delta_yaw;
+    }
+
+    /// Moves the scene by (`dx`, `dy`) world units across the view plane.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let offset = self.view_to_world(Vector3::new(dx, dy, 0.0));
+        self.target -= offset;
+    }
+
+    /// Scales the distance to the target by `factor`.
+    pub fn dolly(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Dollies toward the point under the cursor, given in normalized device coordinates, keeping
+    /// that point fixed on screen.
+    pub fn dolly_toward(&mut self, factor: f32, ndc_x: f32, ndc_y: f32) {
+        let old_distance = self.distance;
+        let half_height = self.view_height() / 2.0;
+        self.dolly(factor);
+        let ratio = self.distance / old_distance;
+
+        let cursor = Vector3::new(ndc_x * half_height * self.aspect, ndc_y * half_height, 0.0);
+        self.target += self.view_to_world(cursor * (1.0 - ratio));
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        };
+    }
+
+    /// Centers the view on a bounding sphere and moves back until the whole sphere fits.
+    ///
+    /// The framed position becomes the new home for the preset views, and the dolly limits are
+    /// scaled to the size of the sphere. The clip planes enclose the sphere together with the
+    /// scene bounds, so a small model does not cut off the grid around it.
+    pub fn frame(&mut self, center: Point3<f32>, radius: f32) {
+        let radius = radius.max(f32::EPSILON);
+        let half_fov_y = FOV_Y_DEGREES.to_radians() / 2.0;
+        let half_fov_x = (half_fov_y.tan() * self.aspect).atan();
+        let distance = radius / half_fov_y.min(half_fov_x).sin() * FRAME_MARGIN;
+
+        self.min_distance = radius * 0.01;
+        self.max_distance = radius * 100.0;
+        self.target = center;
+        self.distance = distance;
+        self.home_target = center;
+        self.home_distance = distance;
+        self.framed_bounds = Some((center, radius));
+    }
+
+    /// Registers the bounding sphere of what is always drawn, such as the ground grid, so the
+    /// clip planes can enclose it.
+    pub fn set_scene_bounds(&mut self, center: Point3<f32>, radius: f32) {
+        self.scene_bounds = Some((center, radius));
+    }
+
+    /// Height of the visible area on the plane through the target.
+    pub fn view_height(&self) -> f32 {
+        2.0 * self.distance * (FOV_Y_DEGREES.to_radians() / 2.0).tan()
+    }
+
+    /// Height of the visible area on the plane through `point` facing the viewer.
+    pub fn view_height_at(&self, point: Point3<f32>) -> f32 {
+        match self.projection {
+            Projection::Perspective => {
+                let depth = -(self.view() * point.to_homogeneous()).z;
+                2.0 * depth.max(f32::EPSILON) * (FOV_Y_DEGREES.to_radians() / 2.0).tan()
+            }
+            Projection::Orthographic => self.view_height(),
+        }
+    }
+
+    pub fn rotation(&self) -> Matrix4<f32> {
+        Matrix4::from_angle_x(Deg(self.pitch)) * Matrix4::from_angle_y(Deg(self.yaw))
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.target + self.view_to_world(Vector3::new(0.0, 0.0, self.distance))
+    }
+
+    /// Position of the light, fixed above and behind the viewer so the visible side is lit.
+    pub fn light_position(&self) -> Point3<f32> {
+        self.target + self.view_to_world(Vector3::new(0.0, 1.0, 1.0) * (self.distance / 3.0))
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(Vector3::new(0.0, 0.0, -self.distance))
+            * self.rotation()
+            * Matrix4::from_translation(-self.target.to_vec())
+    }
+
+    /// Near and far planes enclosing the registered scene bounds.
+    pub fn clip_planes(&self) -> (f32, f32) {
+        let (center, radius) = match (self.scene_bounds, self.framed_bounds) {
+            (Some(scene), Some(framed)) => sphere_union(scene, framed),
+            (Some(bounds), None) | (None, Some(bounds)) => bounds,
+            (None, None) => return (DEFAULT_NEAR, DEFAULT_FAR),
+        };
+
+        let center_depth = (self.view() * center.to_homogeneous()).z;
+        let radius = radius.max(f32::EPSILON) * 1.01;
+        let far = (-center_depth + radius).max(self.distance * 1.01);
+        let near = match self.projection {
+            Projection::Perspective => (-center_depth - radius).max(far * MIN_NEAR_RATIO),
+            Projection::Orthographic => -center_depth - radius,
+        };
+        (near, far)
+    }
+
+    /// Ray through the point under the cursor, given in normalized device coordinates, as a start
+    /// on the near plane and a unit direction away from the viewer.
+    pub fn ray(&self, ndc_x: f32, ndc_y: f32) -> (Point3<f32>, Vector3<f32>) {
+        let inverse = (self.projection_matrix() * self.view())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject =
+            |z: f32| Point3::from_homogeneous(inverse * Vector4::new(ndc_x, ndc_y, z, 1.0));
+        let near = unproject(-1.0);
+        (near, (unproject(1.0) - near).normalize())
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let (near, far) = self.clip_planes();
+        match self.projection {
+            Projection::Perspective => perspective(Deg(FOV_Y_DEGREES), self.aspect, near, far),
+            Projection::Orthographic => {
+                let half_height = self.view_height() / 2.0;
+                let half_width = half_height * self.aspect;
+                ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+
+    // Rotates a vector from view space into world space
+    fn view_to_world(&self, v: Vector3<f32>) -> Vector3<f32> {
+        (self.rotation().transpose() * Vector4::new(v.x, v.y, v.z, 0.0)).truncate()
+    }
+}
+
+// Smallest sphere enclosing both spheres
+fn sphere_union(a: (Point3<f32>, f32), b: (Point3<f32>, f32)) -> (Point3<f32>, f32) {
+    let (a_center, a_radius) = a;
+    let (b_center, b_radius) = b;
+    let offset = b_center - a_center;
+    let distance = offset.magnitude();
+    if distance + b_radius <= a_radius {
+        return a;
+    }
+    if distance + a_radius <= b_radius {
+        return b;
+    }
+    let radius = (distance + a_radius + b_radius) / 2.0;
+    (a_center + offset * ((radius - a_radius) / distance), radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Transform, Vector2};
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn assert_matrix_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let a: &[f32; 16] = a.as_ref();
+        let b: &[f32; 16] = b.as_ref();
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert_close(*x, *y);
+        }
+    }
+
+    fn project(camera: &Camera, p: Point3<f32>) -> Vector2<f32> {
+        let clip = camera.projection_matrix() * camera.view() * p.to_homogeneous();
+        Vector2::new(clip.x / clip.w, clip.y / clip.w)
+    }
+
+    #[test]
+    fn view_places_target_in_front_of_eye() {
+        let camera = Camera::new(1.5);
+        let target = camera.view().transform_point(camera.target);
+        assert_close(target.x, 0.0);
+        assert_close(target.y, 0.0);
+        assert_close(target.z, -15.0);
+
+        let eye = camera.view().transform_point(camera.eye());
+        assert_close(eye.x, 0.0);
+        assert_close(eye.y, 0.0);
+        assert_close(eye.z, 0.0);
+    }
+
+    #[test]
+    fn front_view_matches_look_at() {
+        let mut camera = Camera::new(1.0);
+        camera.reset(0.0, 0.0);
+        let expected = Matrix4::look_at_rh(
+            Point3::new(0.0, 0.0, 15.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::unit_y(),
+        );
+        assert_matrix_close(camera.view(), expected);
+    }
+
+    #[test]
+    fn top_view_looks_down() {
+        let mut camera = Camera::new(1.0);
+        camera.reset(90.0, 0.0);
+        let eye = camera.eye();
+        assert_close(eye.x, 0.0);
+        assert_close(eye.y, 15.0);
+        assert_close(eye.z, 0.0);
+    }
+
+    #[test]
+    fn perspective_uses_default_planes_without_bounds() {
+        let camera = Camera::new(2.0);
+        let expected = perspective(Deg(FOV_Y_DEGREES), 2.0, DEFAULT_NEAR, DEFAULT_FAR);
+        assert_matrix_close(camera.projection_matrix(), expected);
+    }
+
+    #[test]
+    fn orthographic_maps_view_plane_to_unit_square() {
+        let mut camera = Camera::new(2.0);
+        camera.reset(0.0, 0.0);
+        camera.toggle_projection();
+        assert_eq!(camera.projection, Projection::Orthographic);
+
+        let half_height = camera.view_height() / 2.0;
+        let corner = Point3::new(half_height * 2.0, half_height, 0.0);
+        let ndc = project(&camera, corner);
+        assert_close(ndc.x, 1.0);
+        assert_close(ndc.y, 1.0);
+    }
+
+    #[test]
+    fn clip_planes_enclose_scene_bounds() {
+        let mut camera = Camera::new(1.0);
+        camera.set_scene_bounds(Point3::new(0.0, 0.0, 0.0), 2.0);
+        let (near, far) = camera.clip_planes();
+        assert!(near > 0.0 && near <= 13.0);
+        assert!(far >= 17.0);
+
+        // A sphere surrounding the eye still gets a positive near plane
+        camera.set_scene_bounds(Point3::new(0.0, 0.0, 0.0), 500.0);
+        let (near, far) = camera.clip_planes();
+        assert!(near > 0.0);
+        assert!(far >= 515.0);
+    }
+
+    #[test]
+    fn framing_keeps_scene_bounds_within_clip_planes() {
+        let mut camera = Camera::new(1.0);
+        camera.set_scene_bounds(Point3::new(0.0, 0.0, 0.0), 100.0);
+        camera.frame(Point3::new(0.0, 1.0, 0.0), 1.5);
+        assert!(camera.distance < 10.0);
+
+        // The far plane still reaches the edge of the grid behind the model
+        let (_, far) = camera.clip_planes();
+        assert!(far >= camera.distance + 100.0);
+    }
+
+    #[test]
+    fn dolly_toward_keeps_cursor_point_fixed() {
+        let mut camera = Camera::new(1.5);
+        camera.reset(0.0, 0.0);
+        let cursor = Vector2::new(0.5, -0.25);
+        let half_height = camera.view_height() / 2.0;
+        let point = Point3::new(cursor.x * half_height * 1.5, cursor.y * half_height, 0.0);
+
+        camera.dolly_toward(0.5, cursor.x, cursor.y);
+        assert_close(camera.distance, 7.5);
+        let ndc = project(&camera, point);
+        assert_close(ndc.x, cursor.x);
+        assert_close(ndc.y, cursor.y);
+    }
+
+    #[test]
+    fn pan_moves_scene_across_view() {
+        let mut camera = Camera::new(1.0);
+        camera.reset(0.0, 90.0);
+        camera.pan(1.0, 0.0);
+        let origin = camera.view().transform_point(Point3::new(0.0, 0.0, 0.0));
+        assert_close(origin.x, 1.0);
+        assert_close(origin.y, 0.0);
+    }
+
+    #[test]
+    fn frame_fits_sphere_in_view() {
+        let mut camera = Camera::new(0.5);
+        camera.reset(0.0, 0.0);
+        let center = Point3::new(1000.0, 0.0, 0.0);
+        camera.frame(center, 500.0);
+        assert_eq!(camera.target, center);
+
+        // The sphere's extreme points stay inside the narrower horizontal extent
+        let left = project(&camera, Point3::new(500.0, 0.0, 0.0));
+        let right = project(&camera, Point3::new(1500.0, 0.0, 0.0));
+        assert!(left.x > -1.0 && right.x < 1.0);
+        assert!(right.x > 0.8);
+
+        let (near, far) = camera.clip_planes();
+        assert!(near < camera.distance - 500.0 && far > camera.distance + 500.0);
+
+        // Preset views return to the framed position
+        camera.pan(10.0, 10.0);
+        camera.reset(90.0, 0.0);
+        assert_eq!(camera.target, center);
+    }
+
+    #[test]
+    fn ray_passes_through_cursor_point() {
+        for toggle in [false, true] {
+            let mut camera = Camera::new(1.5);
+            if toggle {
+                camera.toggle_projection();
+            }
+            let (start, direction) = camera.ray(0.0, 0.0);
+            let to_target = camera.target - start;
+            assert_close(to_target.normalize().dot(direction), 1.0);
+
+            let (start, direction) = camera.ray(0.5, -0.25);
+            let ndc = project(&camera, start + direction * 10.0);
+            assert_close(ndc.x, 0.5);
+            assert_close(ndc.y, -0.25);
+        }
+    }
+
+    #[test]
+    fn dolly_is_clamped() {
+        let mut camera = Camera::new(1.0);
+        camera.dolly(1000.0);
+        assert_close(camera.distance, 100.0);
+        camera.dolly(0.0);
+        assert_close(camera.distance, 1.0);
+    }
+}
@@ -0,0 +1,398 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector3, Vector4};
+use std::fmt::Write;
+
+use crate::import::{ImportedMesh, ImportedNode};
+use crate::material::Material;
+use crate::vertex::Vertex;
+
+/// File name the exported OBJ uses to refer to its material library.
+pub const MTL_FILE_NAME: &str = "model.mtl";
+
+/// A mesh with its node's world transform applied, for formats without a hierarchy.
+pub struct WorldMesh {
+    pub name: String,
+    pub mesh: ImportedMesh,
+}
+
+/// The meshes of `nodes` and all their descendants, in world space.
+pub fn world_meshes(nodes: &[ImportedNode]) -> Vec<WorldMesh> {
+    let mut meshes = vec![];
+    collect_meshes(nodes, &Matrix4::identity(), &mut meshes);
+    meshes
+}
+
+fn collect_meshes(nodes: &[ImportedNode], parent: &Matrix4<f32>, meshes: &mut Vec<WorldMesh>) {
+    for node in nodes {
+        let world = parent * node.transform;
+        if let Some(ref mesh) = node.mesh {
+            meshes.push(WorldMesh {
+                name: node.name.clone(),
+                mesh: transform_mesh(mesh, &world),
+            });
+        }
+        collect_meshes(&node.children, &world, meshes);
+    }
+}
+
+/// Moves positions and normals by `transform`, keeping mirrored triangles facing outward.
+pub fn transform_mesh(mesh: &ImportedMesh, transform: &Matrix4<f32>) -> ImportedMesh {
+    let normal_matrix = transform
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or_else(Matrix4::identity);
+    let vertices = mesh
+        .vertices
+        .iter()
+        .map(|v| {
+            let Vertex(pos, norm, uv, color) = *v;
+            let pos = transform * Vector4::new(pos[0], pos[1], pos[2], 1.0);
+            let norm = (normal_matrix * Vector4::new(norm[0], norm[1], norm[2], 0.0)).truncate();
+            let norm = if norm.magnitude2() > 0.0 {
+                norm.normalize()
+            } else {
+                Vector3::new(0.0, 0.0, 0.0)
+            };
+            Vertex(pos.truncate().into(), norm.into(), uv, color)
+        })
+        .collect();
+
+    let mut indices = mesh.indices.clone();
+    if transform.determinant() < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    ImportedMesh {
+        vertices,
+        indices,
+        ..mesh.clone()
+    }
+}
+
+/// Placement and units for the mesh exporters, applied on top of the model's own transforms.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Baked into the exported positions before scaling.
+    pub transform: Matrix4<f32>,
+    /// From the model's units to the file's.
+    pub scale: f32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            transform: Matrix4::identity(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Converts from the units the model was made in to the ones the file should use.
+    ///
+    /// Units are "mm", "cm", "m", "in" or "ft".
+    pub fn set_units(&mut self, model_units: &str, output_units: &str) -> Result<(), String> {
+        let unit = |name: &str| unit_length(name).ok_or_else(|| format!("unknown unit '{}'", name));
+        self.scale = (unit(model_units)? / unit(output_units)?) as f32;
+        Ok(())
+    }
+
+    /// Moves `meshes` by the transform, then scales them to the output units.
+    pub fn apply(&self, meshes: Vec<WorldMesh>) -> Vec<WorldMesh> {
+        let transform = Matrix4::from_scale(self.scale) * self.transform;
+        if transform == Matrix4::identity() {
+            return meshes;
+        }
+        meshes
+            .into_iter()
+            .map(|WorldMesh { name, mesh }| WorldMesh {
+                name,
+                mesh: transform_mesh(&mesh, &transform),
+            })
+            .collect()
+    }
+}
+
+// Length of a unit in meters
+fn unit_length(name: &str) -> Option<f64> {
+    Some(match name {
+        "mm" => 0.001,
+        "cm" => 0.01,
+        "m" => 1.0,
+        "in" => 0.0254,
+        "ft" => 0.3048,
+        _ => return None,
+    })
+}
+
+/// The materials a set of meshes use, renamed where needed so no two share a name.
+pub struct MaterialLibrary {
+    pub materials: Vec<Material>,
+    // The material each entry of `materials` was made from
+    sources: Vec<Material>,
+}
+
+impl MaterialLibrary {
+    /// Collects the materials of `meshes`; empty when nothing has a material.
+    ///
+    /// Triangles without a material get a default one when any others have one, since OBJ cannot
+    /// go back to having no material after a `usemtl`.
+    pub fn new(meshes: &[WorldMesh]) -> Self {
+        let mut library = Self {
+            materials: vec![],
+            sources: vec![],
+        };
+        let groups = || meshes.iter().flat_map(|m| m.mesh.groups.iter());
+        if groups().all(|g| g.material.is_none()) {
+            return library;
+        }
+
+        let default = default_material();
+        let ungrouped = meshes.iter().any(|m| m.mesh.groups.is_empty());
+        let all = groups()
+            .map(|g| g.material.as_ref().unwrap_or(&default))
+            .chain(ungrouped.then_some(&default));
+        for material in all {
+            if library.sources.contains(material) {
+                continue;
+            }
+            let base = if material.name.is_empty() {
+                "material".to_string()
+            } else {
+                material.name.replace(char::is_whitespace, "_")
+            };
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while library.materials.iter().any(|m| m.name == name) {
+                name = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+            library.materials.push(Material {
+                name,
+                ..material.clone()
+            });
+            library.sources.push(material.clone());
+        }
+        library
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// The name `material` goes by in the library, `None` standing for the default material.
+    pub fn name_of(&self, material: Option<&Material>) -> Option<&str> {
+        let default = default_material();
+        let material = material.unwrap_or(&default);
+        let index = self.sources.iter().position(|m| m == material)?;
+        Some(&self.materials[index].name)
+    }
+}
+
+fn default_material() -> Material {
+    Material {
+        name: "default".to_string(),
+        ..Material::default()
+    }
+}
+
+/// Writes `meshes` as OBJ text, with a `mtllib` statement for `mtl_file` when given.
+///
+/// Each vertex is written once with its texture coordinate and normal, which are left out when
+/// the whole mesh has none. Point clouds are written as bare vertices.
+pub fn write_obj(meshes: &[WorldMesh], mtl_file: Option<&str>) -> String {
+    let library = MaterialLibrary::new(meshes);
+
+    let mut obj = String::new();
+    writeln!(obj, "# Wavefront OBJ exported by rust-renderer").unwrap();
+    if let Some(mtl_file) = mtl_file {
+        writeln!(obj, "mtllib {}", mtl_file).unwrap();
+    }
+
+    let mut offset = 1;
+    for (i, WorldMesh { name, mesh }) in meshes.iter().enumerate() {
+        let name = if name.is_empty() {
+            format!("object_{}", i + 1)
+        } else {
+            name.replace(char::is_whitespace, "_")
+        };
+        writeln!(obj, "o {}", name).unwrap();
+
+        let has_uvs = mesh.vertices.iter().any(|v| { v.2 } != [0.0, 0.0]);
+        let has_normals = mesh.vertices.iter().any(|v| { v.1 } != [0.0, 0.0, 0.0]);
+        for [x, y, z] in mesh.vertices.iter().map(|v| v.0) {
+            writeln!(obj, "v {} {} {}", x, y, z).unwrap();
+        }
+        if has_uvs {
+            for [u, v] in mesh.vertices.iter().map(|v| v.2) {
+                writeln!(obj, "vt {} {}", u, v).unwrap();
+            }
+        }
+        if has_normals {
+            for [x, y, z] in mesh.vertices.iter().map(|v| v.1) {
+                writeln!(obj, "vn {} {} {}", x, y, z).unwrap();
+            }
+        }
+
+        // Vertices carry their own texture coordinate and normal, so the indices all match
+        let corner = |index: u32| {
+            let i = offset + index as usize;
+            match (has_uvs, has_normals) {
+                (true, true) => format!("{}/{}/{}", i, i, i),
+                (true, false) => format!("{}/{}", i, i),
+                (false, true) => format!("{}//{}", i, i),
+                (false, false) => i.to_string(),
+            }
+        };
+        let whole = [(0, mesh.indices.len(), None)];
+        let groups: Vec<(usize, usize, Option<&Material>)> = if mesh.groups.is_empty() {
+            whole.to_vec()
+        } else {
+            mesh.groups
+                .iter()
+                .map(|g| (g.start, g.count, g.material.as_ref()))
+                .collect()
+        };
+        for (start, count, material) in groups {
+            if let Some(name) = library.name_of(material) {
+                writeln!(obj, "usemtl {}", name).unwrap();
+            }
+            for triangle in mesh.indices[start..start + count].chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(corner);
+                writeln!(obj, "f {} {} {}", a, b, c).unwrap();
+            }
+        }
+
+        offset += mesh.vertices.len();
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::geometry::NormalMode;
+    use crate::import::MeshGroup;
+    use crate::mtl::write_mtl;
+    use crate::obj::parse_obj;
+    use crate::vertex::WHITE;
+
+    fn quad() -> ImportedMesh {
+        let vertex = |x: f32, y: f32| Vertex([x, y, 0.0], [0.0, 0.0, 1.0], [x, y], WHITE);
+        ImportedMesh {
+            vertices: vec![
+                vertex(0.0, 0.0),
+                vertex(1.0, 0.0),
+                vertex(1.0, 1.0),
+                vertex(0.0, 1.0),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            groups: vec![],
+            generated_normals: vec![],
+            vertex_colors: false,
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn obj_round_trip_in_world_space() {
+        let red = Material {
+            name: "red paint".to_string(),
+            diffuse: [1.0, 0.0, 0.0],
+            ..Material::default()
+        };
+        let mut painted = quad();
+        painted.groups = vec![
+            MeshGroup {
+                start: 0,
+                count: 3,
+                material: None,
+            },
+            MeshGroup {
+                start: 3,
+                count: 3,
+                material: Some(red.clone()),
+            },
+        ];
+        let nodes = vec![ImportedNode {
+            name: "parent".to_string(),
+            transform: Matrix4::from_translation(Vector3::new(0.0, 0.0, 5.0)),
+            mesh: Some(quad()),
+            children: vec![ImportedNode {
+                name: "painted child".to_string(),
+                transform: Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0)),
+                mesh: Some(painted),
+                children: vec![],
+            }],
+        }];
+
+        let meshes = world_meshes(&nodes);
+        let library = MaterialLibrary::new(&meshes);
+        let names: Vec<&str> = library.materials.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["default", "red_paint"]);
+
+        let obj = write_obj(&meshes, Some(MTL_FILE_NAME));
+        assert!(obj.contains("o painted_child\n"));
+        let mut assets = Assets::new();
+        assets.insert(MTL_FILE_NAME, write_mtl(&library.materials).into_bytes());
+        let mesh = parse_obj(&obj, NormalMode::default(), &assets).unwrap();
+        assert!(mesh.warnings.is_empty(), "{:?}", mesh.warnings);
+        assert_eq!(mesh.indices.len(), 12);
+
+        let corner = mesh
+            .vertices
+            .iter()
+            .map(|v| v.0)
+            .fold([f32::MIN; 3], |a, b| {
+                [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])]
+            });
+        assert_eq!(corner, [11.0, 1.0, 5.0]);
+        let materials: Vec<Option<&str>> = mesh
+            .groups
+            .iter()
+            .map(|g| g.material.as_ref().map(|m| m.name.as_str()))
+            .collect();
+        assert!(materials.contains(&Some("red_paint")));
+    }
+
+    #[test]
+    fn mirrored_transform_keeps_faces_outward() {
+        let nodes = vec![ImportedNode {
+            name: String::new(),
+            transform: Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0),
+            mesh: Some(quad()),
+            children: vec![],
+        }];
+        let meshes = world_meshes(&nodes);
+        let mesh = &meshes[0].mesh;
+        assert_eq!(mesh.indices[..3], [0, 2, 1]);
+        let normal = mesh.vertices[0].1;
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+        assert!(write_obj(&meshes, None)
+            .starts_with("# Wavefront OBJ exported by rust-renderer\no object_1\n"));
+    }
+
+    #[test]
+    fn options_bake_transform_and_units() {
+        let nodes = vec![ImportedNode {
+            name: String::new(),
+            transform: Matrix4::identity(),
+            mesh: Some(quad()),
+            children: vec![],
+        }];
+        let mut options = ExportOptions {
+            transform: Matrix4::from_translation(Vector3::new(0.0, 0.0, 2.0)),
+            ..ExportOptions::default()
+        };
+        options.set_units("cm", "mm").unwrap();
+        assert!(options.set_units("cm", "furlong").is_err());
+
+        let meshes = options.apply(world_meshes(&nodes));
+        let position = meshes[0].mesh.vertices[2].0;
+        assert_eq!(position, [10.0, 10.0, 20.0]);
+        let normal = meshes[0].mesh.vertices[2].1;
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+}
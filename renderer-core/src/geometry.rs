@@ -0,0 +1,545 @@
+use cgmath::{InnerSpace, Vector3};
+use std::collections::HashMap;
+
+use crate::vertex::{Norm, Pos, Vertex};
+
+/// How normals are generated for geometry that does not provide them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMode {
+    /// Every triangle gets its own face normal.
+    Flat,
+    /// Normals are averaged across all triangles sharing a position.
+    Smooth,
+    /// Normals are averaged only across triangles meeting at less than the given angle, in
+    /// degrees, so hard edges stay sharp.
+    Crease(f32),
+}
+
+impl Default for NormalMode {
+    fn default() -> Self {
+        NormalMode::Crease(30.0)
+    }
+}
+
+/// Computes a normal for every triangle corner.
+///
+/// `corners` holds three indices into `positions` per triangle. Smoothed normals are weighted by
+/// both the area of each triangle and its angle at the shared corner, so dense tessellation on one
+/// side of a vertex does not pull the normal toward it. Degenerate triangles contribute nothing
+/// and get a zero normal when flat shaded.
+pub fn corner_normals(positions: &[Pos], corners: &[u32], mode: NormalMode) -> Vec<Norm> {
+    let triangles: Vec<[Vector3<f32>; 3]> = corners
+        .chunks_exact(3)
+        .map(|t| {
+            [
+                Vector3::from(positions[t[0] as usize]),
+                Vector3::from(positions[t[1] as usize]),
+                Vector3::from(positions[t[2] as usize]),
+            ]
+        })
+        .collect();
+
+    // Unit face normal per triangle
+    let face_normals: Vec<Vector3<f32>> = triangles
+        .iter()
+        .map(|[a, b, c]| normalize_or_zero((b - a).cross(c - a)))
+        .collect();
+
+    if mode == NormalMode::Flat {
+        return face_normals
+            .iter()
+            .flat_map(|n| [(*n).into(), (*n).into(), (*n).into()])
+            .collect();
+    }
+
+    // Area and angle weighted contribution of each corner
+    let weighted: Vec<Vector3<f32>> = triangles
+        .iter()
+        .flat_map(|[a, b, c]| {
+            let area_normal = (b - a).cross(c - a);
+            [
+                area_normal * corner_angle(*a, *b, *c),
+                area_normal * corner_angle(*b, *c, *a),
+                area_normal * corner_angle(*c, *a, *b),
+            ]
+        })
+        .collect();
+
+    // Corners sharing each position
+    let mut incident: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (corner, position) in corners.iter().enumerate().take(weighted.len()) {
+        incident.entry(*position).or_default().push(corner);
+    }
+
+    let min_cos = match mode {
+        NormalMode::Crease(angle) => angle.to_radians().cos(),
+        _ => -1.0,
+    };
+
+    (0..weighted.len())
+        .map(|corner| {
+            let face = face_normals[corner / 3];
+            let sum = incident[&corners[corner]]
+                .iter()
+                .filter(|other| {
+                    // A degenerate triangle can't decide a crease, so it follows its neighbors
+                    let other_face = face_normals[**other / 3];
+                    face.dot(other_face) >= min_cos || face == Vector3::new(0.0, 0.0, 0.0)
+                })
+                .fold(Vector3::new(0.0, 0.0, 0.0), |sum, other| {
+                    sum + weighted[*other]
+                });
+            normalize_or_zero(sum).into()
+        })
+        .collect()
+}
+
+// Welded position index, then the texture coordinate, color and normal keys
+type VertexKey = (u32, [u32; 2], [u32; 3], [u32; 3]);
+
+/// Replaces the normals of an indexed triangle list with generated ones.
+///
+/// Vertices are welded by position first so normals can be shared across seams, then split
+/// again wherever the generated normals, texture coordinates or colors differ.
+pub fn regenerate_normals(
+    vertices: &[Vertex],
+    indices: &[u32],
+    mode: NormalMode,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let generated = vec![true; vertices.len()];
+    let (vertices, indices, _) = regenerate_marked_normals(vertices, indices, &generated, mode);
+    (vertices, indices)
+}
+
+/// Like `regenerate_normals`, but only replaces the normals of the vertices marked in
+/// `generated`, keeping the others as they are.
+///
+/// Returns the marks for the new vertices too, so the normals can be regenerated again later.
+pub fn regenerate_marked_normals(
+    vertices: &[Vertex],
+    indices: &[u32],
+    generated: &[bool],
+    mode: NormalMode,
+) -> (Vec<Vertex>, Vec<u32>, Vec<bool>) {
+    let mut positions: Vec<Pos> = vec![];
+    let mut position_lookup: HashMap<[u32; 3], u32> = HashMap::new();
+    let welded: Vec<u32> = vertices
+        .iter()
+        .map(|v| {
+            let pos = v.0;
+            *position_lookup.entry(weld_key(pos)).or_insert_with(|| {
+                positions.push(pos);
+                positions.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    let corners: Vec<u32> = indices.iter().map(|i| welded[*i as usize]).collect();
+    let normals = corner_normals(&positions, &corners, mode);
+
+    let mut new_vertices: Vec<Vertex> = vec![];
+    let mut new_indices: Vec<u32> = Vec::with_capacity(indices.len());
+    let mut new_generated: Vec<bool> = vec![];
+    let mut vertex_lookup: HashMap<(VertexKey, bool), u32> = HashMap::new();
+    for (index, normal) in indices.iter().zip(normals) {
+        let index = *index as usize;
+        let Vertex(_, old_normal, uv, color) = vertices[index];
+        let generated = generated[index];
+        let normal = if generated { normal } else { old_normal };
+        let key = (
+            (
+                welded[index],
+                weld_key(uv),
+                weld_key(color),
+                weld_key(normal),
+            ),
+            generated,
+        );
+        let new_index = *vertex_lookup.entry(key).or_insert_with(|| {
+            let mut vertex = vertices[index].clone();
+            vertex.1 = normal;
+            new_vertices.push(vertex);
+            new_generated.push(generated);
+            new_vertices.len() as u32 - 1
+        });
+        new_indices.push(new_index);
+    }
+    (new_vertices, new_indices, new_generated)
+}
+
+/// Splits a planar polygon into triangles by ear clipping.
+///
+/// The polygon is projected onto its best-fit plane, so slightly warped faces are handled, and it
+/// may be concave but must not have holes. Each triangle indexes into `points` and keeps the
+/// polygon's winding. Collinear vertices become zero-area triangles so neighbouring faces still
+/// share them, and input that can't be clipped, such as self-intersecting or fully degenerate
+/// polygons, falls back to a fan.
+pub fn triangulate(points: &[Pos]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method gives a plane normal that follows the polygon's winding
+    let normal = (0..n).fold(Vector3::new(0.0, 0.0, 0.0), |sum, i| {
+        let a = Vector3::from(points[i]);
+        let b = Vector3::from(points[(i + 1) % n]);
+        sum + Vector3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        )
+    });
+    if normal.magnitude2() == 0.0 {
+        return fan(&(0..n).collect::<Vec<_>>());
+    }
+
+    // Project onto the plane with a basis that makes the polygon counter-clockwise
+    let normal = normal.normalize();
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = normal.cross(helper).normalize();
+    let v = normal.cross(u);
+    let projected: Vec<[f32; 2]> = points
+        .iter()
+        .map(|p| {
+            let p = Vector3::from(*p);
+            [p.dot(u), p.dot(v)]
+        })
+        .collect();
+
+    // Tolerance for treating a corner as straight, relative to the polygon size
+    let extent = projected.iter().fold(0.0f32, |extent, p| {
+        extent
+            .max((p[0] - projected[0][0]).abs())
+            .max((p[1] - projected[0][1]).abs())
+    });
+    let epsilon = extent * extent * 1e-6;
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |i: usize| {
+            [
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            ]
+        };
+
+        let ear = (0..m).find(|&i| {
+            let [a, b, c] = corner(i);
+            let (pa, pb, pc) = (projected[a], projected[b], projected[c]);
+            if cross_2d(pa, pb, pc) <= epsilon {
+                return false;
+            }
+
+            // No other vertex may lie inside the candidate ear
+            remaining.iter().all(|&p| {
+                let pp = projected[p];
+                p == a
+                    || p == b
+                    || p == c
+                    || pp == pa
+                    || pp == pb
+                    || pp == pc
+                    || cross_2d(pa, pb, pp) < 0.0
+                    || cross_2d(pb, pc, pp) < 0.0
+                    || cross_2d(pc, pa, pp) < 0.0
+            })
+        });
+
+        // Without an ear, drop a straight corner, or give up and fan what's left
+        let clip = ear.or_else(|| {
+            (0..m).find(|&i| {
+                let [a, b, c] = corner(i);
+                cross_2d(projected[a], projected[b], projected[c]).abs() <= epsilon
+            })
+        });
+        match clip {
+            Some(i) => {
+                triangles.push(corner(i));
+                remaining.remove(i);
+            }
+            None => {
+                triangles.extend(fan(&remaining));
+                return triangles;
+            }
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+// Fan triangulation: create triangles from v0, vi, vi+1
+fn fan(polygon: &[usize]) -> Vec<[usize; 3]> {
+    (1..polygon.len() - 1)
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+// Twice the signed area of the 2D triangle (a, b, c), positive when counter-clockwise
+fn cross_2d(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Hashable key for welding vertex attributes; -0.0 and 0.0 map to the same key.
+pub fn weld_key<const N: usize>(v: [f32; N]) -> [u32; N] {
+    v.map(|x| (x + 0.0).to_bits())
+}
+
+// Interior angle at `a` of the triangle (a, b, c)
+fn corner_angle(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> f32 {
+    let ab = b - a;
+    let ac = c - a;
+    if ab.magnitude2() == 0.0 || ac.magnitude2() == 0.0 {
+        return 0.0;
+    }
+    ab.angle(ac).0
+}
+
+fn normalize_or_zero(v: Vector3<f32>) -> Vector3<f32> {
+    let length = v.magnitude();
+    if length > 0.0 {
+        v / length
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::obj::parse_obj;
+    use crate::vertex::WHITE;
+
+    // Unit cube with eight shared positions and two triangles per side
+    fn cube() -> (Vec<Pos>, Vec<u32>) {
+        let positions = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ];
+        let quads = [
+            [0, 3, 2, 1],
+            [4, 5, 6, 7],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [0, 4, 7, 3],
+            [1, 2, 6, 5],
+        ];
+        let corners = quads
+            .iter()
+            .flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]])
+            .collect();
+        (positions, corners)
+    }
+
+    fn assert_normal(n: Norm, expected: [f32; 3]) {
+        for (a, b) in n.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", n, expected);
+        }
+    }
+
+    #[test]
+    fn flat_normals_follow_faces() {
+        let (positions, corners) = cube();
+        let normals = corner_normals(&positions, &corners, NormalMode::Flat);
+        assert_eq!(normals.len(), corners.len());
+        for n in &normals[0..6] {
+            assert_normal(*n, [0.0, 0.0, -1.0]);
+        }
+        for n in &normals[6..12] {
+            assert_normal(*n, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_point_out_of_cube_corners() {
+        let (positions, corners) = cube();
+        let normals = corner_normals(&positions, &corners, NormalMode::Smooth);
+        let d = 1.0 / 3.0f32.sqrt();
+        // Position 6 is the (1, 1, 1) corner; the uneven triangle split does not skew it
+        let corner = corners.iter().position(|c| *c == 6).unwrap();
+        assert_normal(normals[corner], [d, d, d]);
+    }
+
+    #[test]
+    fn crease_keeps_hard_edges_and_smooths_shallow_ones() {
+        let (positions, corners) = cube();
+        let flat = corner_normals(&positions, &corners, NormalMode::Flat);
+        let creased = corner_normals(&positions, &corners, NormalMode::Crease(30.0));
+        for (a, b) in flat.iter().zip(creased.iter()) {
+            assert_normal(*b, *a);
+        }
+
+        // Two triangles folded by 20 degrees are smoothed together
+        let fold = 20.0f32.to_radians();
+        let positions = vec![
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [-1.0, 0.0, 0.0],
+            [fold.cos(), fold.sin(), 0.0],
+        ];
+        let corners = vec![0, 1, 2, 0, 3, 1];
+        let normals = corner_normals(&positions, &corners, NormalMode::Crease(30.0));
+        assert_normal(normals[0], normals[3]);
+        assert!(normals[0][0] > 0.0 && normals[0][1] < 0.0);
+    }
+
+    // Signed area of each triangle measured along `normal`
+    fn triangle_areas(points: &[Pos], triangles: &[[usize; 3]], normal: Vector3<f32>) -> Vec<f32> {
+        triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| Vector3::from(points[i]));
+                (b - a).cross(c - a).dot(normal) / 2.0
+            })
+            .collect()
+    }
+
+    fn assert_covers(points: &[Pos], normal: Vector3<f32>, count: usize, area: f32) {
+        let triangles = triangulate(points);
+        assert_eq!(triangles.len(), count);
+        let areas = triangle_areas(points, &triangles, normal);
+        assert!(
+            areas.iter().all(|a| *a >= -1e-6),
+            "inverted triangle in {:?}",
+            areas
+        );
+        assert!((areas.iter().sum::<f32>() - area).abs() < 1e-4);
+    }
+
+    #[test]
+    fn triangulate_convex_polygon() {
+        let square = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        assert_covers(&square, Vector3::unit_z(), 2, 1.0);
+    }
+
+    #[test]
+    fn triangulate_concave_l_shape() {
+        // The reflex corner at (1, 1) breaks a fan from the first vertex
+        let l_shape = [
+            [2.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 2.0, 0.0],
+            [0.0, 2.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ];
+        assert_covers(&l_shape, Vector3::unit_z(), 4, 3.0);
+
+        // Same shape wound the other way, tilted out of the XY plane
+        let rotation = cgmath::Matrix3::from_angle_x(cgmath::Deg(60.0));
+        let tilted: Vec<Pos> = l_shape
+            .iter()
+            .rev()
+            .map(|p| (rotation * Vector3::from(*p)).into())
+            .collect();
+        assert_covers(&tilted, rotation * -Vector3::unit_z(), 4, 3.0);
+    }
+
+    #[test]
+    fn triangulate_collinear_vertices() {
+        // Square with an extra vertex in the middle of every side
+        let square = [
+            [0.0, 0.0, 0.0],
+            [0.5, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.5, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.5, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.5, 0.0],
+        ];
+        let triangles = triangulate(&square);
+        assert_covers(&square, Vector3::unit_z(), 6, 1.0);
+
+        // Every vertex is still used so neighbouring faces stay connected
+        for i in 0..square.len() {
+            assert!(triangles.iter().any(|t| t.contains(&i)));
+        }
+    }
+
+    #[test]
+    fn triangulate_degenerate_polygon_falls_back_to_fan() {
+        let line = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [3.0, 0.0, 0.0],
+        ];
+        assert_eq!(triangulate(&line), vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(triangulate(&line[..2]).is_empty());
+    }
+
+    #[test]
+    fn regenerate_welds_and_splits_vertices() {
+        let (positions, corners) = cube();
+        let vertices: Vec<Vertex> = corners
+            .iter()
+            .map(|c| Vertex(positions[*c as usize], [0.0, 0.0, 0.0], [0.0, 0.0], WHITE))
+            .collect();
+        let indices: Vec<u32> = (0..vertices.len() as u32).collect();
+
+        // Smooth normals share one vertex per cube corner
+        let (smooth_vertices, smooth_indices) =
+            regenerate_normals(&vertices, &indices, NormalMode::Smooth);
+        assert_eq!(smooth_vertices.len(), 8);
+        assert_eq!(smooth_indices.len(), 36);
+
+        // Flat normals split each corner into one vertex per side
+        let (flat_vertices, flat_indices) =
+            regenerate_normals(&vertices, &indices, NormalMode::Flat);
+        assert_eq!(flat_vertices.len(), 24);
+        for triangle in flat_indices.chunks_exact(3) {
+            let normal = flat_vertices[triangle[0] as usize].1;
+            assert_normal(flat_vertices[triangle[1] as usize].1, normal);
+            assert_normal(flat_vertices[triangle[2] as usize].1, normal);
+        }
+    }
+
+    #[test]
+    fn regenerating_keeps_imported_normals() {
+        // A triangle with its normals given, folded against one without
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nvn 0 0.6 0.8\nf 1//1 2//1 3//1\nf 1 2 4\n";
+        let mesh = parse_obj(obj, NormalMode::Flat, &Assets::new()).unwrap();
+        assert_eq!(
+            mesh.generated_normals,
+            [false, false, false, true, true, true]
+        );
+
+        let (vertices, indices, generated) = regenerate_marked_normals(
+            &mesh.vertices,
+            &mesh.indices,
+            &mesh.generated_normals,
+            NormalMode::Smooth,
+        );
+        assert_eq!(generated, mesh.generated_normals);
+        for index in &indices[..3] {
+            assert_normal(vertices[*index as usize].1, [0.0, 0.6, 0.8]);
+        }
+        // The generated normals on the fold are smoothed, the one off it stays flat
+        let fold = vertices[indices[3] as usize].1;
+        assert!(fold[2] > 0.5, "{:?} is not smoothed", fold);
+        assert_normal(vertices[indices[5] as usize].1, [0.0, -1.0, 0.0]);
+    }
+}
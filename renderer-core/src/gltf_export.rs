@@ -0,0 +1,431 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::import::{ImportedMesh, ImportedNode, ImportedScene};
+use crate::material::Material;
+
+// glTF enums
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const POINTS: u32 = 0;
+const TRIANGLES: u32 = 4;
+const LINEAR: u32 = 9729;
+const LINEAR_MIPMAP_LINEAR: u32 = 9987;
+const REPEAT: u32 = 10497;
+
+/// Writes `scene` as a binary glTF 2.0 (GLB) file with all buffers and images embedded.
+///
+/// The node hierarchy and transforms are kept. Phong materials become metallic-roughness ones
+/// with no metalness, with the diffuse map as the base color texture when its image is in
+/// `scene.images` as PNG or JPEG. Bump maps have no glTF equivalent and are left out.
+pub fn write_glb(scene: &ImportedScene) -> Vec<u8> {
+    let mut writer = GltfWriter::new(&scene.images);
+    let roots: Vec<usize> = scene.nodes.iter().map(|n| writer.add_node(n)).collect();
+    let (json, bin) = writer.finish(roots);
+
+    let mut json = serde_json::to_vec(&json).unwrap();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut glb = Vec::with_capacity(12 + 8 + json.len() + 8 + bin.len());
+    let length = 12 + 8 + json.len() + if bin.is_empty() { 0 } else { 8 + bin.len() };
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(length as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    if !bin.is_empty() {
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+    }
+    glb
+}
+
+// Collects the glTF arrays while the nodes are walked, with every buffer view in one buffer
+struct GltfWriter<'a> {
+    images: &'a HashMap<String, Vec<u8>>,
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    // The source of each entry in `materials`
+    material_sources: Vec<Material>,
+    textures: Vec<Value>,
+    // Texture index by image name, `None` when the image could not be embedded
+    texture_lookup: HashMap<String, Option<usize>>,
+    gltf_images: Vec<Value>,
+}
+
+impl<'a> GltfWriter<'a> {
+    fn new(images: &'a HashMap<String, Vec<u8>>) -> Self {
+        Self {
+            images,
+            bin: vec![],
+            buffer_views: vec![],
+            accessors: vec![],
+            nodes: vec![],
+            meshes: vec![],
+            materials: vec![],
+            material_sources: vec![],
+            textures: vec![],
+            texture_lookup: HashMap::new(),
+            gltf_images: vec![],
+        }
+    }
+
+    fn finish(self, roots: Vec<usize>) -> (Value, Vec<u8>) {
+        let mut root = Map::new();
+        root.insert(
+            "asset".into(),
+            json!({ "version": "2.0", "generator": "rust-renderer" }),
+        );
+        root.insert("scene".into(), json!(0));
+        root.insert("scenes".into(), json!([{ "nodes": roots }]));
+        if !self.textures.is_empty() {
+            root.insert(
+                "samplers".into(),
+                json!([{
+                    "magFilter": LINEAR,
+                    "minFilter": LINEAR_MIPMAP_LINEAR,
+                    "wrapS": REPEAT,
+                    "wrapT": REPEAT,
+                }]),
+            );
+        }
+        if !self.bin.is_empty() {
+            root.insert("buffers".into(), json!([{ "byteLength": self.bin.len() }]));
+        }
+        // Empty arrays are not allowed, so they are left out
+        for (name, values) in [
+            ("nodes", self.nodes),
+            ("meshes", self.meshes),
+            ("materials", self.materials),
+            ("textures", self.textures),
+            ("images", self.gltf_images),
+            ("accessors", self.accessors),
+            ("bufferViews", self.buffer_views),
+        ] {
+            if !values.is_empty() {
+                root.insert(name.into(), Value::Array(values));
+            }
+        }
+        (Value::Object(root), self.bin)
+    }
+
+    // Children are added before their parent, which glTF does not mind
+    fn add_node(&mut self, node: &ImportedNode) -> usize {
+        let mut json = Map::new();
+        if !node.name.is_empty() {
+            json.insert("name".into(), json!(node.name));
+        }
+        if node.transform != Matrix4::identity() {
+            let matrix: &[f32; 16] = node.transform.as_ref();
+            json.insert("matrix".into(), json!(matrix));
+        }
+        if let Some(ref mesh) = node.mesh {
+            if let Some(mesh) = self.add_mesh(mesh, &node.name) {
+                json.insert("mesh".into(), json!(mesh));
+            }
+        }
+        let children: Vec<usize> = node.children.iter().map(|c| self.add_node(c)).collect();
+        if !children.is_empty() {
+            json.insert("children".into(), json!(children));
+        }
+        self.nodes.push(Value::Object(json));
+        self.nodes.len() - 1
+    }
+
+    fn add_mesh(&mut self, mesh: &ImportedMesh, name: &str) -> Option<usize> {
+        if mesh.vertices.is_empty() {
+            return None;
+        }
+
+        let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|v| v.0).collect();
+        let min = positions.iter().fold([f32::MAX; 3], |a, p| {
+            [a[0].min(p[0]), a[1].min(p[1]), a[2].min(p[2])]
+        });
+        let max = positions.iter().fold([f32::MIN; 3], |a, p| {
+            [a[0].max(p[0]), a[1].max(p[1]), a[2].max(p[2])]
+        });
+        let mut attributes = Map::new();
+        let position = self.add_accessor(&positions.concat(), "VEC3", ARRAY_BUFFER);
+        self.accessors[position]["min"] = json!(min);
+        self.accessors[position]["max"] = json!(max);
+        attributes.insert("POSITION".into(), json!(position));
+
+        // Normals must be unit length, so point clouds without them get none
+        let normals: Option<Vec<[f32; 3]>> = mesh
+            .vertices
+            .iter()
+            .map(|v| {
+                let normal = Vector3::from(v.1);
+                (normal.magnitude2() > 0.0).then(|| normal.normalize().into())
+            })
+            .collect();
+        if let Some(normals) = normals {
+            let normal = self.add_accessor(&normals.concat(), "VEC3", ARRAY_BUFFER);
+            attributes.insert("NORMAL".into(), json!(normal));
+        }
+
+        let groups: Vec<(usize, usize, Option<&Material>)> = if mesh.groups.is_empty() {
+            vec![(0, mesh.indices.len(), None)]
+        } else {
+            mesh.groups
+                .iter()
+                .map(|g| (g.start, g.count, g.material.as_ref()))
+                .collect()
+        };
+        let materials: Vec<Option<usize>> = groups.iter().map(|g| self.add_material(g.2)).collect();
+
+        // Texture coordinates are only worth writing when a texture samples them
+        let textured = materials
+            .iter()
+            .flatten()
+            .any(|m| self.materials[*m]["pbrMetallicRoughness"]["baseColorTexture"].is_object());
+        if textured {
+            // glTF puts v = 0 at the top of the image
+            let uvs: Vec<f32> = mesh
+                .vertices
+                .iter()
+                .flat_map(|v| {
+                    let [s, t] = v.2;
+                    [s, 1.0 - t]
+                })
+                .collect();
+            let uv = self.add_accessor(&uvs, "VEC2", ARRAY_BUFFER);
+            attributes.insert("TEXCOORD_0".into(), json!(uv));
+        }
+        if mesh.vertex_colors {
+            let colors: Vec<f32> = mesh.vertices.iter().flat_map(|v| v.3).collect();
+            let color = self.add_accessor(&colors, "VEC3", ARRAY_BUFFER);
+            attributes.insert("COLOR_0".into(), json!(color));
+        }
+
+        let mut primitives = vec![];
+        if mesh.indices.is_empty() {
+            let mut primitive = json!({ "attributes": attributes, "mode": POINTS });
+            if let Some(material) = materials[0] {
+                primitive["material"] = json!(material);
+            }
+            primitives.push(primitive);
+        }
+        for ((start, count, _), material) in groups.into_iter().zip(materials) {
+            if count == 0 {
+                continue;
+            }
+            let indices = self.add_indices(&mesh.indices[start..start + count]);
+            let mut primitive = json!({
+                "attributes": attributes,
+                "indices": indices,
+                "mode": TRIANGLES,
+            });
+            if let Some(material) = material {
+                primitive["material"] = json!(material);
+            }
+            primitives.push(primitive);
+        }
+
+        let mut json = json!({ "primitives": primitives });
+        if !name.is_empty() {
+            json["name"] = json!(name);
+        }
+        self.meshes.push(json);
+        Some(self.meshes.len() - 1)
+    }
+
+    fn add_material(&mut self, material: Option<&Material>) -> Option<usize> {
+        let material = material?;
+        if let Some(index) = self.material_sources.iter().position(|m| m == material) {
+            return Some(index);
+        }
+
+        // The inverse of the importer's roughness to shininess mapping
+        let roughness = (2.0 / (material.shininess.max(0.0) + 2.0)).powf(0.25);
+        let [r, g, b] = material.diffuse;
+        let opacity = material.opacity.clamp(0.0, 1.0);
+        let base_color = [r, g, b, opacity].map(|c| c.clamp(0.0, 1.0));
+        let mut pbr = json!({
+            "baseColorFactor": base_color,
+            "metallicFactor": 0.0,
+            "roughnessFactor": roughness,
+        });
+        if let Some(texture) = material
+            .diffuse_map
+            .as_ref()
+            .and_then(|m| self.add_texture(m))
+        {
+            pbr["baseColorTexture"] = json!({ "index": texture });
+        }
+        let mut json = json!({ "pbrMetallicRoughness": pbr });
+        if !material.name.is_empty() {
+            json["name"] = json!(material.name);
+        }
+        if opacity < 1.0 {
+            json["alphaMode"] = json!("BLEND");
+        }
+        self.materials.push(json);
+        self.material_sources.push(material.clone());
+        Some(self.materials.len() - 1)
+    }
+
+    fn add_texture(&mut self, name: &str) -> Option<usize> {
+        if let Some(texture) = self.texture_lookup.get(name) {
+            return *texture;
+        }
+        let images = self.images;
+        let texture = images.get(name).and_then(|bytes| {
+            let mime_type = if bytes.starts_with(b"\x89PNG") {
+                "image/png"
+            } else if bytes.starts_with(&[0xff, 0xd8]) {
+                "image/jpeg"
+            } else {
+                return None;
+            };
+            let view = self.add_view(bytes, None);
+            self.gltf_images.push(json!({
+                "name": name,
+                "bufferView": view,
+                "mimeType": mime_type,
+            }));
+            self.textures.push(json!({
+                "sampler": 0,
+                "source": self.gltf_images.len() - 1,
+            }));
+            Some(self.textures.len() - 1)
+        });
+        self.texture_lookup.insert(name.to_string(), texture);
+        texture
+    }
+
+    fn add_accessor(&mut self, values: &[f32], kind: &str, target: u32) -> usize {
+        let components = match kind {
+            "VEC2" => 2,
+            "VEC3" => 3,
+            _ => 1,
+        };
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let view = self.add_view(&bytes, Some(target));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": values.len() / components,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn add_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.add_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    // Views start on four byte boundaries, as float and index data need
+    fn add_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::geometry::NormalMode;
+    use crate::gltf_import::parse_gltf;
+    use crate::import::MeshGroup;
+    use crate::texture::TextureImage;
+    use crate::vertex::{Vertex, WHITE};
+
+    #[test]
+    fn glb_round_trip() {
+        let vertex = |x: f32, y: f32| Vertex([x, y, 0.0], [0.0, 0.0, 1.0], [x, y], WHITE);
+        let textured = Material {
+            name: "checker".to_string(),
+            diffuse: [0.5, 0.25, 1.0],
+            opacity: 0.5,
+            diffuse_map: Some("checker.png".to_string()),
+            ..Material::default()
+        };
+        let image = TextureImage {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 0, 0, 255],
+        };
+        let scene = ImportedScene {
+            nodes: vec![ImportedNode {
+                name: "root".to_string(),
+                transform: Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)),
+                mesh: None,
+                children: vec![ImportedNode {
+                    name: "square".to_string(),
+                    transform: Matrix4::identity(),
+                    mesh: Some(ImportedMesh {
+                        vertices: vec![
+                            vertex(0.0, 0.0),
+                            vertex(1.0, 0.0),
+                            vertex(1.0, 1.0),
+                            vertex(0.0, 1.0),
+                        ],
+                        indices: vec![0, 1, 2, 0, 2, 3],
+                        groups: vec![MeshGroup {
+                            start: 0,
+                            count: 6,
+                            material: Some(textured),
+                        }],
+                        generated_normals: vec![],
+                        vertex_colors: false,
+                        warnings: vec![],
+                    }),
+                    children: vec![],
+                }],
+            }],
+            images: HashMap::from([("checker.png".to_string(), image.encode_png().unwrap())]),
+            warnings: vec![],
+        };
+
+        let glb = write_glb(&scene);
+        assert_eq!(glb.len() % 4, 0);
+        let imported = parse_gltf(&glb, NormalMode::default(), &Assets::new()).unwrap();
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+
+        let root = &imported.nodes[0];
+        assert_eq!(root.name, "root");
+        assert_eq!(root.transform, scene.nodes[0].transform);
+        let mesh = root.children[0].mesh.as_ref().unwrap();
+        assert_eq!(mesh.indices.len(), 6);
+        // The texture coordinates come back the way they went out
+        let uv = mesh.vertices[2].2;
+        assert_eq!(uv, [1.0, 1.0]);
+
+        let material = mesh.groups[0].material.as_ref().unwrap();
+        assert_eq!(material.name, "checker");
+        assert_eq!(material.diffuse, [0.5, 0.25, 1.0]);
+        assert_eq!(material.opacity, 0.5);
+        let map = material.diffuse_map.as_ref().unwrap();
+        let decoded = TextureImage::decode(&imported.images[map]).unwrap();
+        assert_eq!(decoded.pixels, image.pixels);
+    }
+}
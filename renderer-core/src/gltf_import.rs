@@ -0,0 +1,406 @@
+use base64::Engine;
+use cgmath::Matrix4;
+use gltf::{buffer, image, mesh::Mode, Gltf};
+use std::collections::{HashMap, HashSet};
+
+use crate::assets::Assets;
+use crate::geometry::{regenerate_normals, NormalMode};
+use crate::import::{ImportError, ImportedMesh, ImportedNode, ImportedScene, MeshGroup};
+use crate::material::Material;
+use crate::vertex::{Color, Vertex, WHITE};
+
+/// Whether `bytes` look like a binary (GLB) or JSON glTF file.
+pub fn is_gltf(bytes: &[u8]) -> bool {
+    if bytes.starts_with(b"glTF") {
+        return true;
+    }
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    start.is_some_and(|start| bytes[start] == b'{')
+}
+
+/// Reads a glTF 2.0 file, JSON or binary.
+///
+/// The default scene is imported with its node hierarchy and transforms. Buffers and images are
+/// taken from the GLB binary chunk, data URIs or `assets`. Metallic-roughness
+/// materials are approximated with the Phong material model; only the base color texture is
+/// used.
+pub fn parse_gltf(
+    bytes: &[u8],
+    mode: NormalMode,
+    assets: &Assets,
+) -> Result<ImportedScene, ImportError> {
+    let gltf = Gltf::from_slice(bytes).map_err(|e| ImportError::new(format!("glTF: {}", e)))?;
+    let mut warnings: Vec<String> = vec![];
+
+    let buffers = gltf
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| ImportError::new("glTF: binary chunk is missing")),
+            buffer::Source::Uri(uri) => read_uri(uri, assets),
+        })
+        .collect::<Result<Vec<Vec<u8>>, ImportError>>()?;
+
+    // Embedded images are named after their index, external ones by their URI
+    let mut images: HashMap<String, Vec<u8>> = HashMap::new();
+    for image in gltf.images() {
+        match image.source() {
+            image::Source::View { view, .. } => {
+                let data = view_data(&buffers, &view)?;
+                images.insert(image_name(&image), data.to_vec());
+            }
+            image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                images.insert(image_name(&image), read_uri(uri, assets)?);
+            }
+            image::Source::Uri { .. } => {}
+        }
+    }
+
+    let materials: Vec<Material> = gltf.materials().map(|m| convert_material(&m)).collect();
+
+    let meshes: Vec<ImportedMesh> = gltf
+        .meshes()
+        .map(|mesh| {
+            let mut imported = ImportedMesh {
+                vertices: vec![],
+                indices: vec![],
+                groups: vec![],
+                generated_normals: vec![],
+                vertex_colors: false,
+                warnings: vec![],
+            };
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| d.as_slice()));
+                let Some(positions) = reader.read_positions() else {
+                    warnings.push(format!(
+                        "mesh {}: primitive without positions, skipped",
+                        mesh.index()
+                    ));
+                    continue;
+                };
+                let positions: Vec<[f32; 3]> = positions.collect();
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let Some(mut indices) = triangle_list(primitive.mode(), &indices) else {
+                    warnings.push(format!(
+                        "mesh {}: {:?} primitives are not supported, skipped",
+                        mesh.index(),
+                        primitive.mode()
+                    ));
+                    continue;
+                };
+                if indices.iter().any(|i| *i as usize >= positions.len()) {
+                    warnings.push(format!(
+                        "mesh {}: primitive index out of range, skipped",
+                        mesh.index()
+                    ));
+                    continue;
+                }
+
+                // glTF puts v = 0 at the top of the image, the shader expects it at the bottom
+                let texcoords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                    Some(uv) => uv.into_f32().map(|[u, v]| [u, 1.0 - v]).collect(),
+                    None => vec![[0.0, 0.0]; positions.len()],
+                };
+                let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+                let colors: Option<Vec<Color>> =
+                    reader.read_colors(0).map(|c| c.into_rgb_f32().collect());
+                let mut vertices: Vec<Vertex> = positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let normal = normals.as_ref().map_or([0.0; 3], |n| n[i]);
+                        let uv = texcoords.get(i).copied().unwrap_or_default();
+                        let color = colors.as_ref().and_then(|c| c.get(i).copied());
+                        Vertex(*p, normal, uv, color.unwrap_or(WHITE))
+                    })
+                    .collect();
+                imported.vertex_colors |= colors.is_some();
+                if normals.is_none() {
+                    (vertices, indices) = regenerate_normals(&vertices, &indices, mode);
+                    // Earlier primitives kept the normals they came with
+                    let generated = &mut imported.generated_normals;
+                    generated.resize(imported.vertices.len(), false);
+                    generated.resize(imported.vertices.len() + vertices.len(), true);
+                } else if !imported.generated_normals.is_empty() {
+                    let count = imported.vertices.len() + vertices.len();
+                    imported.generated_normals.resize(count, false);
+                }
+
+                let offset = imported.vertices.len() as u32;
+                imported.groups.push(MeshGroup {
+                    start: imported.indices.len(),
+                    count: indices.len(),
+                    material: primitive.material().index().map(|i| materials[i].clone()),
+                });
+                imported.vertices.extend(vertices);
+                imported.indices.extend(indices.iter().map(|i| i + offset));
+            }
+            imported
+        })
+        .collect();
+
+    // The default scene, or failing that every node without a parent
+    let roots: Vec<gltf::Node> = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: HashSet<usize> = gltf
+                .nodes()
+                .flat_map(|n| n.children().map(|c| c.index()))
+                .collect();
+            gltf.nodes()
+                .filter(|n| !children.contains(&n.index()))
+                .collect()
+        }
+    };
+    let mut visited = HashSet::new();
+    let nodes = roots
+        .iter()
+        .map(|node| convert_node(node, &meshes, &mut visited))
+        .collect::<Result<Vec<ImportedNode>, ImportError>>()?;
+
+    Ok(ImportedScene {
+        nodes,
+        images,
+        warnings,
+    })
+}
+
+fn convert_node(
+    node: &gltf::Node,
+    meshes: &[ImportedMesh],
+    visited: &mut HashSet<usize>,
+) -> Result<ImportedNode, ImportError> {
+    if !visited.insert(node.index()) {
+        return Err(ImportError::new(format!(
+            "glTF: node {} appears more than once in the hierarchy",
+            node.index()
+        )));
+    }
+    let children = node
+        .children()
+        .map(|child| convert_node(&child, meshes, visited))
+        .collect::<Result<Vec<ImportedNode>, ImportError>>()?;
+    Ok(ImportedNode {
+        name: node
+            .name()
+            .map_or_else(|| format!("node {}", node.index()), str::to_string),
+        transform: Matrix4::from(node.transform().matrix()),
+        mesh: node.mesh().map(|mesh| meshes[mesh.index()].clone()),
+        children,
+    })
+}
+
+fn convert_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let metallic = pbr.metallic_factor();
+    let roughness = pbr.roughness_factor();
+
+    // Metals reflect in their own color, dielectrics in white at about 4%; rough surfaces
+    // spread the highlight until it disappears
+    let gloss = 1.0 - roughness;
+    let specular = [r, g, b].map(|c| (0.04 + (c - 0.04) * metallic) * gloss);
+    let shininess = (2.0 / roughness.powi(4).max(1e-4) - 2.0).clamp(1.0, 512.0);
+
+    Material {
+        name: material.name().map_or_else(
+            || format!("material {}", material.index().unwrap_or(0)),
+            str::to_string,
+        ),
+        diffuse: [r, g, b],
+        specular,
+        shininess,
+        opacity: match material.alpha_mode() {
+            gltf::material::AlphaMode::Blend => a,
+            _ => 1.0,
+        },
+        diffuse_map: pbr
+            .base_color_texture()
+            .map(|info| image_name(&info.texture().source())),
+        ..Material::default()
+    }
+}
+
+// Name an image is referenced by from materials and looked up by in `ImportedScene::images`
+fn image_name(image: &gltf::Image) -> String {
+    match image.source() {
+        image::Source::Uri { uri, .. } if !uri.starts_with("data:") => percent_decode(uri),
+        _ => format!("glTF image {}", image.index()),
+    }
+}
+
+fn view_data<'a>(buffers: &'a [Vec<u8>], view: &buffer::View) -> Result<&'a [u8], ImportError> {
+    buffers[view.buffer().index()]
+        .get(view.offset()..view.offset() + view.length())
+        .ok_or_else(|| {
+            ImportError::new(format!(
+                "glTF: buffer view {} is out of range",
+                view.index()
+            ))
+        })
+}
+
+// Data URIs are decoded in place, anything else must have been supplied as an asset
+fn read_uri(uri: &str, assets: &Assets) -> Result<Vec<u8>, ImportError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| ImportError::new("glTF: only base64 data URIs are supported"))?;
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| ImportError::new(format!("glTF: invalid data URI: {}", e)));
+    }
+    let name = percent_decode(uri);
+    assets
+        .get(&name)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| ImportError::new(format!("'{}' was not supplied", name)))
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Triangle list for a primitive, or `None` for points and lines
+fn triangle_list(mode: Mode, indices: &[u32]) -> Option<Vec<u32>> {
+    match mode {
+        Mode::Triangles => Some(indices[..indices.len() / 3 * 3].to_vec()),
+        Mode::TriangleStrip => Some(
+            (2..indices.len())
+                .flat_map(|i| {
+                    // Every other triangle is flipped to keep the winding consistent
+                    if i % 2 == 0 {
+                        [indices[i - 2], indices[i - 1], indices[i]]
+                    } else {
+                        [indices[i - 1], indices[i - 2], indices[i]]
+                    }
+                })
+                .collect(),
+        ),
+        Mode::TriangleFan => Some(
+            (2..indices.len())
+                .flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit square in the XY plane, two triangles, moved up by a parent node
+    const SQUARE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0]}],
+        "nodes": [
+            {"name": "parent", "translation": [0, 2, 0], "children": [1]},
+            {"name": "square", "mesh": 0}
+        ],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]}],
+        "materials": [{
+            "name": "red",
+            "pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}
+        }],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+             "min": [0, 0, 0], "max": [1, 1, 0]},
+            {"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}
+        ],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 48},
+            {"buffer": 0, "byteOffset": 48, "byteLength": 12}
+        ],
+        "buffers": [{"byteLength": 60, "uri": "data:application/octet-stream;base64,BASE64"}]
+    }"#;
+
+    fn square_gltf() -> String {
+        let mut data: Vec<u8> = vec![];
+        for v in [
+            0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for i in [0u16, 1, 2, 0, 2, 3] {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        SQUARE.replace("BASE64", &encoded)
+    }
+
+    #[test]
+    fn reads_hierarchy_and_materials() {
+        let gltf = square_gltf();
+        assert!(is_gltf(gltf.as_bytes()));
+        let scene = parse_gltf(gltf.as_bytes(), NormalMode::default(), &Assets::new()).unwrap();
+
+        assert_eq!(scene.nodes.len(), 1);
+        let parent = &scene.nodes[0];
+        assert_eq!(parent.name, "parent");
+        assert_eq!(parent.transform.w.y, 2.0);
+        assert!(parent.mesh.is_none());
+
+        let square = &parent.children[0];
+        let mesh = square.mesh.as_ref().unwrap();
+        assert_eq!(mesh.indices.len(), 6);
+        // Missing normals are generated, facing +Z
+        assert_eq!(mesh.generated_normals, vec![true; mesh.vertices.len()]);
+        let normal = mesh.vertices[0].1;
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+
+        let material = mesh.groups[0].material.as_ref().unwrap();
+        assert_eq!(material.name, "red");
+        assert_eq!(material.diffuse, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn converts_strips_and_fans() {
+        assert_eq!(
+            triangle_list(Mode::TriangleStrip, &[0, 1, 2, 3]).unwrap(),
+            vec![0, 1, 2, 2, 1, 3]
+        );
+        assert_eq!(
+            triangle_list(Mode::TriangleFan, &[0, 1, 2, 3]).unwrap(),
+            vec![0, 1, 2, 0, 2, 3]
+        );
+        assert!(triangle_list(Mode::Lines, &[0, 1]).is_none());
+    }
+
+    #[test]
+    fn decodes_uris() {
+        assert_eq!(
+            percent_decode("textures/My%20Wood.png"),
+            "textures/My Wood.png"
+        );
+        assert_eq!(
+            read_uri("data:text/plain;base64,aGk=", &Assets::new()).unwrap(),
+            b"hi"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use cgmath::{Matrix4, SquareMatrix};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::JsValue;
+
+use crate::assets::Assets;
+use crate::geometry::NormalMode;
+use crate::gltf_import::{is_gltf, parse_gltf};
+use crate::material::Material;
+use crate::obj::parse_obj;
+use crate::ply::{is_ply, parse_ply};
+use crate::stl::{is_stl, parse_stl};
+use crate::texture::TextureImage;
+use crate::vertex::Vertex;
+
+/// Why an import failed, with the location of the problem in the source file when known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportError {
+    /// 1-based line number.
+    pub line: Option<usize>,
+    pub token: Option<String>,
+    pub reason: String,
+}
+
+impl ImportError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            token: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn at_line(line: usize, token: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            line: Some(line),
+            token: Some(token.into()),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(ref token) = self.token {
+            write!(f, "'{}': ", token)?;
+        }
+        write!(f, "{}", self.reason)
+    }
+}
+
+/// Converts to a JS `Error` carrying `line`, `token` and `reason` properties.
+#[cfg(feature = "wasm")]
+impl From<ImportError> for JsValue {
+    fn from(error: ImportError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&js_error, &JsValue::from_str(key), &value).unwrap();
+        };
+        set(
+            "line",
+            error
+                .line
+                .map_or(JsValue::NULL, |line| JsValue::from_f64(line as f64)),
+        );
+        set(
+            "token",
+            error
+                .token
+                .as_deref()
+                .map_or(JsValue::NULL, JsValue::from_str),
+        );
+        set("reason", JsValue::from_str(&error.reason));
+        js_error.into()
+    }
+}
+
+/// Geometry read from a file, ready to be handed to the model.
+///
+/// A mesh with vertices but no indices is a point cloud.
+#[derive(Debug, Clone)]
+pub struct ImportedMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Index ranges sharing a material, covering all of `indices`.
+    pub groups: Vec<MeshGroup>,
+    /// For each vertex, whether its normal was generated rather than read from the file. Empty
+    /// when the file provided every normal.
+    pub generated_normals: Vec<bool>,
+    /// Whether the vertex colors came from the file, rather than being left white.
+    pub vertex_colors: bool,
+    pub warnings: Vec<String>,
+}
+
+/// A range of `ImportedMesh::indices` drawn with one material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshGroup {
+    pub start: usize,
+    pub count: usize,
+    /// `None` uses the model color.
+    pub material: Option<Material>,
+}
+
+/// An object in an imported scene, placed relative to its parent.
+#[derive(Debug, Clone)]
+pub struct ImportedNode {
+    pub name: String,
+    pub transform: Matrix4<f32>,
+    pub mesh: Option<ImportedMesh>,
+    pub children: Vec<ImportedNode>,
+}
+
+/// Everything read from a file, ready to be added to the scene.
+#[derive(Debug, Default)]
+pub struct ImportedScene {
+    pub nodes: Vec<ImportedNode>,
+    /// Image files embedded in the model file, by the names its materials use. Other names are
+    /// looked up among the supplied assets.
+    pub images: HashMap<String, Vec<u8>>,
+    pub warnings: Vec<String>,
+}
+
+impl ImportedScene {
+    /// A scene holding just `mesh`.
+    pub fn from_mesh(mesh: ImportedMesh) -> Self {
+        Self {
+            nodes: vec![ImportedNode {
+                name: String::new(),
+                transform: Matrix4::identity(),
+                mesh: Some(mesh),
+                children: vec![],
+            }],
+            ..Self::default()
+        }
+    }
+}
+
+/// Every node in the tree, parents before their children.
+pub fn all_nodes(nodes: &[ImportedNode]) -> Vec<&ImportedNode> {
+    let mut all: Vec<&ImportedNode> = nodes.iter().collect();
+    let mut i = 0;
+    while i < all.len() {
+        all.extend(all[i].children.iter());
+        i += 1;
+    }
+    all
+}
+
+/// The files behind the texture maps the materials of `meshes` use, each once with the name the
+/// materials use.
+///
+/// Each map is taken from `embedded`, the images read from the model file, or else from `assets`.
+/// Maps that were not supplied are reported in `warnings`.
+pub fn texture_map_files<'a>(
+    meshes: &[&ImportedMesh],
+    embedded: &'a HashMap<String, Vec<u8>>,
+    assets: &'a Assets,
+    warnings: &mut Vec<String>,
+) -> Vec<(String, &'a [u8])> {
+    let mut files = vec![];
+    let mut seen: HashSet<&String> = HashSet::new();
+    let maps = meshes
+        .iter()
+        .flat_map(|m| m.groups.iter())
+        .filter_map(|g| g.material.as_ref())
+        .flat_map(|m| m.diffuse_map.iter().chain(m.bump_map.iter()));
+    for map in maps {
+        if !seen.insert(map) {
+            continue;
+        }
+        match embedded
+            .get(map)
+            .map(Vec::as_slice)
+            .or_else(|| assets.get(map))
+        {
+            Some(bytes) => files.push((map.clone(), bytes)),
+            None => warnings.push(format!("texture '{}' was not supplied", map)),
+        }
+    }
+    files
+}
+
+/// Decodes the texture maps the materials of `meshes` use, each once however many materials
+/// share it. See `texture_map_files` for where they are looked up.
+pub fn decode_texture_maps(
+    meshes: &[&ImportedMesh],
+    embedded: &HashMap<String, Vec<u8>>,
+    assets: &Assets,
+    warnings: &mut Vec<String>,
+) -> HashMap<String, TextureImage> {
+    let mut images = HashMap::new();
+    for (map, bytes) in texture_map_files(meshes, embedded, assets, warnings) {
+        match TextureImage::decode(bytes) {
+            Ok(image) => {
+                images.insert(map, image);
+            }
+            Err(e) => warnings.push(format!("texture '{}': {}", map, e)),
+        }
+    }
+    images
+}
+
+/// Reads a model file of any supported format.
+///
+/// The format is detected from the contents: glTF (JSON or GLB), PLY, binary or ASCII STL,
+/// otherwise Wavefront OBJ. Missing normals are generated with `mode`, and files the model refers
+/// to are looked up in `assets`.
+pub fn parse_model(
+    bytes: &[u8],
+    mode: NormalMode,
+    assets: &Assets,
+) -> Result<ImportedScene, ImportError> {
+    if is_gltf(bytes) {
+        return parse_gltf(bytes, mode, assets);
+    }
+    let mesh = if is_ply(bytes) {
+        parse_ply(bytes, mode)?
+    } else if is_stl(bytes) {
+        parse_stl(bytes, mode)?
+    } else {
+        let content = std::str::from_utf8(bytes)
+            .map_err(|_| ImportError::new("file is not a recognized model format"))?;
+        parse_obj(content, mode, assets)?
+    };
+    Ok(ImportedScene::from_mesh(mesh))
+}
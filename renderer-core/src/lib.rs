@@ -0,0 +1,24 @@
+//! Model parsing, geometry and scene math shared by the web renderer and native tools.
+//!
+//! Nothing here touches the browser or a GPU, so it builds and tests on any target.
+
+pub mod assets;
+pub mod bounds;
+pub mod bvh;
+pub mod camera;
+pub mod export;
+pub mod geometry;
+pub mod gltf_export;
+pub mod gltf_import;
+pub mod import;
+pub mod material;
+pub mod mtl;
+pub mod obj;
+pub mod ply;
+pub mod raster;
+pub mod render;
+pub mod stats;
+pub mod stl;
+pub mod texture;
+pub mod transform;
+pub mod vertex;
@@ -0,0 +1,50 @@
+/// Surface description following the Wavefront MTL model.
+///
+/// The defaults reproduce the viewer's built-in Phong shading with a white diffuse color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Ka, scaled by the scene's ambient light strength.
+    pub ambient: [f32; 3],
+    /// Kd
+    pub diffuse: [f32; 3],
+    /// Ks
+    pub specular: [f32; 3],
+    /// Ns
+    pub shininess: f32,
+    /// d, 1.0 is fully opaque.
+    pub opacity: f32,
+    /// map_Kd file name
+    pub diffuse_map: Option<String>,
+    /// map_Bump or bump file name, a height map
+    pub bump_map: Option<String>,
+    /// The `-bm` multiplier of the bump map.
+    pub bump_scale: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: [1.0, 1.0, 1.0],
+            diffuse: [1.0, 1.0, 1.0],
+            specular: [0.5, 0.5, 0.5],
+            shininess: 32.0,
+            opacity: 1.0,
+            diffuse_map: None,
+            bump_map: None,
+            bump_scale: 1.0,
+        }
+    }
+}
+
+impl Material {
+    /// The default material in the given diffuse color.
+    pub fn with_color(color: [f32; 4]) -> Self {
+        Self {
+            diffuse: [color[0], color[1], color[2]],
+            opacity: color[3],
+            ..Self::default()
+        }
+    }
+}
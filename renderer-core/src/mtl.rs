@@ -0,0 +1,210 @@
+use std::fmt::Write;
+
+use crate::import::ImportError;
+use crate::material::Material;
+
+/// Parses a Wavefront MTL material library.
+///
+/// Kd, Ka, Ks, Ns, d (or Tr), map_Kd and map_Bump (or bump) are read; other statements are
+/// ignored.
+pub fn parse_mtl(content: &str) -> Result<Vec<Material>, ImportError> {
+    let mut materials: Vec<Material> = vec![];
+
+    for (line_index, line) in content.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        let error = |reason: &str| ImportError::at_line(line_number, keyword, reason);
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(error("material has no name"));
+            }
+            materials.push(Material {
+                name: args.join(" "),
+                ..Material::default()
+            });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None if is_material_statement(keyword) => {
+                return Err(error("statement before the first newmtl"));
+            }
+            None => continue,
+        };
+        let color = || parse_color(&args).ok_or_else(|| error("expected a color"));
+        let number = || parse_number(&args).ok_or_else(|| error("expected a number"));
+        match keyword {
+            "Ka" => material.ambient = color()?,
+            "Kd" => material.diffuse = color()?,
+            "Ks" => material.specular = color()?,
+            "Ns" => material.shininess = number()?,
+            "d" => material.opacity = number()?,
+            "Tr" => material.opacity = 1.0 - number()?,
+            "map_Kd" => {
+                let (file, _) = parse_map(&args).ok_or_else(|| error("expected a file name"))?;
+                material.diffuse_map = Some(file);
+            }
+            "map_Bump" | "map_bump" | "bump" => {
+                let (file, scale) =
+                    parse_map(&args).ok_or_else(|| error("expected a file name"))?;
+                material.bump_map = Some(file);
+                material.bump_scale = scale;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Writes `materials` as an MTL material library that `parse_mtl` reads back unchanged.
+pub fn write_mtl(materials: &[Material]) -> String {
+    let mut mtl = String::new();
+    writeln!(mtl, "# Wavefront MTL exported by rust-renderer").unwrap();
+    for material in materials {
+        let [r, g, b] = material.ambient;
+        writeln!(mtl, "\nnewmtl {}", material.name).unwrap();
+        writeln!(mtl, "Ka {} {} {}", r, g, b).unwrap();
+        let [r, g, b] = material.diffuse;
+        writeln!(mtl, "Kd {} {} {}", r, g, b).unwrap();
+        let [r, g, b] = material.specular;
+        writeln!(mtl, "Ks {} {} {}", r, g, b).unwrap();
+        writeln!(mtl, "Ns {}", material.shininess).unwrap();
+        writeln!(mtl, "d {}", material.opacity).unwrap();
+        if let Some(ref map) = material.diffuse_map {
+            writeln!(mtl, "map_Kd {}", map).unwrap();
+        }
+        if let Some(ref map) = material.bump_map {
+            writeln!(mtl, "map_Bump -bm {} {}", material.bump_scale, map).unwrap();
+        }
+    }
+    mtl
+}
+
+fn is_material_statement(keyword: &str) -> bool {
+    matches!(
+        keyword,
+        "Ka" | "Kd" | "Ks" | "Ns" | "d" | "Tr" | "map_Kd" | "map_Bump" | "map_bump" | "bump"
+    )
+}
+
+// "r g b", where "r" alone means grey
+fn parse_color(args: &[&str]) -> Option<[f32; 3]> {
+    let values: Vec<f32> = args.iter().map(|a| a.parse().ok()).collect::<Option<_>>()?;
+    match values[..] {
+        [v] => Some([v, v, v]),
+        [r, g, b] => Some([r, g, b]),
+        _ => None,
+    }
+}
+
+fn parse_number(args: &[&str]) -> Option<f32> {
+    match args {
+        [value] => value.parse().ok(),
+        _ => None,
+    }
+}
+
+// A texture map statement: options followed by the file name, which may contain spaces.
+// Returns the file name and the bump multiplier.
+fn parse_map(args: &[&str]) -> Option<(String, f32)> {
+    let mut bump_scale = 1.0;
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') && args[i].parse::<f32>().is_err() {
+        let option = args[i];
+        i += 1;
+        if option == "-bm" {
+            bump_scale = args.get(i)?.parse().ok()?;
+            i += 1;
+        } else if matches!(option, "-o" | "-s" | "-t" | "-mm") {
+            // Up to three numeric arguments
+            while i < args.len() && args[i].parse::<f32>().is_ok() {
+                i += 1;
+            }
+        } else {
+            // -blendu, -blendv, -cc, -clamp, -boost, -texres, -imfchan take one argument
+            i += 1;
+        }
+    }
+    if i >= args.len() {
+        return None;
+    }
+    Some((args[i..].join(" "), bump_scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_materials() {
+        let materials = parse_mtl(
+            "# Blender MTL File\n\
+             newmtl Red\n\
+             Ns 250.0\n\
+             Ka 1.0 1.0 1.0\n\
+             Kd 0.8 0.1 0.1\n\
+             Ks 0.5\n\
+             d 0.5\n\
+             illum 2\n\
+             \n\
+             newmtl Wood\n\
+             map_Kd -o 0 0 0 -s 2 2 1 textures/wood grain.png\n\
+             map_Bump -bm 0.25 wood_height.jpg\n",
+        )
+        .unwrap();
+
+        assert_eq!(materials.len(), 2);
+        let red = &materials[0];
+        assert_eq!(red.name, "Red");
+        assert_eq!(red.shininess, 250.0);
+        assert_eq!(red.diffuse, [0.8, 0.1, 0.1]);
+        assert_eq!(red.specular, [0.5, 0.5, 0.5]);
+        assert_eq!(red.opacity, 0.5);
+        assert_eq!(red.diffuse_map, None);
+
+        let wood = &materials[1];
+        assert_eq!(wood.diffuse, Material::default().diffuse);
+        assert_eq!(wood.diffuse_map.as_deref(), Some("textures/wood grain.png"));
+        assert_eq!(wood.bump_map.as_deref(), Some("wood_height.jpg"));
+        assert_eq!(wood.bump_scale, 0.25);
+    }
+
+    #[test]
+    fn reports_bad_statements() {
+        let error = parse_mtl("newmtl a\nKd 1.0 x 1.0\n").unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.token.as_deref(), Some("Kd"));
+
+        let error = parse_mtl("Kd 1 1 1\n").unwrap_err();
+        assert_eq!(error.line, Some(1));
+    }
+
+    #[test]
+    fn written_library_reads_back() {
+        let materials = vec![
+            Material {
+                name: "Glass".to_string(),
+                diffuse: [0.2, 0.4, 0.6],
+                opacity: 0.25,
+                ..Material::default()
+            },
+            Material {
+                name: "Brick".to_string(),
+                diffuse_map: Some("brick wall.png".to_string()),
+                bump_map: Some("brick_height.png".to_string()),
+                bump_scale: 0.5,
+                ..Material::default()
+            },
+        ];
+        assert_eq!(parse_mtl(&write_mtl(&materials)).unwrap(), materials);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use wavefront_rs::obj::{entity::*, parser::*};
+
+use crate::assets::Assets;
+use crate::geometry::{corner_normals, triangulate, weld_key, NormalMode};
+use crate::import::{ImportError, ImportedMesh, MeshGroup};
+use crate::material::Material;
+use crate::mtl::parse_mtl;
+use crate::vertex::{Norm, Pos, Uv, Vertex, WHITE};
+
+// A triangle corner as referenced by an OBJ face
+#[derive(Clone, Copy)]
+struct Corner {
+    position: u32,
+    normal: Option<Norm>,
+    texture: Option<u32>,
+}
+
+/// Reads a Wavefront OBJ file.
+///
+/// Faces are triangulated and their materials looked up in the material libraries found in
+/// `assets`. Corners without a normal get one generated with `mode`.
+pub fn parse_obj(
+    content: &str,
+    mode: NormalMode,
+    assets: &Assets,
+) -> Result<ImportedMesh, ImportError> {
+    // Prepare to parse the OBJ content
+    let mut positions: Vec<Pos> = vec![];
+    let mut normals: Vec<Norm> = vec![];
+    let mut texcoords: Vec<Uv> = vec![];
+    let mut corners: Vec<Corner> = vec![];
+    let mut warnings: Vec<String> = vec![];
+    let mut materials: Vec<Material> = vec![];
+    // Index into `materials` of the current usemtl, and of each triangle
+    let mut current_material: Option<usize> = None;
+    let mut triangle_materials: Vec<Option<usize>> = vec![];
+
+    // Parse the OBJ content line by line so errors can be located
+    for (line_index, line) in content.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut entities = vec![];
+        Parser::read_to_end(&mut BufReader::new(line.as_bytes()), |x| entities.push(x)).map_err(
+            |e| {
+                let token = line.split_whitespace().next().unwrap_or_default();
+                ImportError::at_line(line_number, token, format!("{:?}", e))
+            },
+        )?;
+
+        for entity in entities {
+            match entity {
+                Entity::Vertex { x, y, z, w: _ } => {
+                    positions.push([x as f32, y as f32, z as f32]);
+                }
+                Entity::VertexNormal { x, y, z } => {
+                    normals.push([x as f32, y as f32, z as f32]);
+                }
+                Entity::Mtllib { name } => match assets.get(&name) {
+                    Some(bytes) => {
+                        let library = parse_mtl(&String::from_utf8_lossy(bytes)).map_err(|e| {
+                            ImportError::new(format!("material library '{}': {}", name, e))
+                        })?;
+                        materials.extend(library);
+                    }
+                    None => warnings.push(format!(
+                        "line {}: material library '{}' was not supplied",
+                        line_number, name
+                    )),
+                },
+                Entity::Usemtl { name } => {
+                    current_material = materials.iter().position(|m| m.name == name);
+                    if current_material.is_none() {
+                        warnings.push(format!(
+                            "line {}: material '{}' is not defined, using the model color",
+                            line_number, name
+                        ));
+                    }
+                }
+                Entity::VertexTexture { u, v, w: _ } => {
+                    texcoords.push([u as f32, v.unwrap_or(0.0) as f32]);
+                }
+                Entity::Face {
+                    vertices: face_vertices,
+                } => {
+                    if face_vertices.len() < 3 {
+                        warnings.push(format!(
+                            "line {}: face has fewer than 3 vertices, skipped",
+                            line_number
+                        ));
+                        continue;
+                    }
+
+                    // Resolve the corners, giving up on the face at the first bad position
+                    let mut face: Vec<Corner> = Vec::with_capacity(face_vertices.len());
+                    for v in &face_vertices {
+                        let position = match resolve_index(v.vertex, positions.len()) {
+                            Some(position) => position,
+                            None => break,
+                        };
+                        let normal = match v.normal {
+                            Some(n) => match resolve_index(n, normals.len()) {
+                                Some(n) => Some(normals[n as usize]),
+                                None => {
+                                    warnings.push(format!(
+                                        "line {}: normal index {} is out of range, generating one",
+                                        line_number, n
+                                    ));
+                                    None
+                                }
+                            },
+                            None => None,
+                        };
+                        let texture = match v.texture {
+                            Some(t) => match resolve_index(t, texcoords.len()) {
+                                Some(t) => Some(t),
+                                None => {
+                                    warnings.push(format!(
+                                        "line {}: texture index {} is out of range, ignored",
+                                        line_number, t
+                                    ));
+                                    None
+                                }
+                            },
+                            None => None,
+                        };
+                        face.push(Corner {
+                            position,
+                            normal,
+                            texture,
+                        });
+                    }
+                    if face.len() < face_vertices.len() {
+                        warnings.push(format!(
+                            "line {}: vertex index {} is out of range ({} defined), face skipped",
+                            line_number,
+                            face_vertices[face.len()].vertex,
+                            positions.len()
+                        ));
+                        continue;
+                    }
+
+                    // Ear clipping keeps concave faces from producing overlapping triangles
+                    let points: Vec<Pos> = face
+                        .iter()
+                        .map(|c| positions[c.position as usize])
+                        .collect();
+                    for [a, b, c] in triangulate(&points) {
+                        corners.extend_from_slice(&[face[a], face[b], face[c]]);
+                        triangle_materials.push(current_material);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Generate normals for corners that don't reference one
+    let generated = if corners.iter().any(|c| c.normal.is_none()) {
+        let corner_positions: Vec<u32> = corners.iter().map(|c| c.position).collect();
+        Some(corner_normals(&positions, &corner_positions, mode))
+    } else {
+        None
+    };
+
+    // Each distinct (position, texture, normal) combination becomes one shared vertex
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indices: Vec<u32> = Vec::with_capacity(corners.len());
+    let mut generated_normals: Vec<bool> = vec![];
+    // Generated and imported normals stay on separate vertices, so only the generated ones change
+    // with the normal mode
+    let mut vertex_lookup: HashMap<(u32, Option<u32>, [u32; 3], bool), u32> = HashMap::new();
+    for (i, corner) in corners.iter().enumerate() {
+        let normal = corner
+            .normal
+            .unwrap_or_else(|| generated.as_ref().unwrap()[i]);
+        let key = (
+            corner.position,
+            corner.texture,
+            weld_key(normal),
+            corner.normal.is_none(),
+        );
+        let index = *vertex_lookup.entry(key).or_insert_with(|| {
+            generated_normals.push(corner.normal.is_none());
+            let uv = corner.texture.map_or([0.0, 0.0], |t| texcoords[t as usize]);
+            vertices.push(Vertex(
+                positions[corner.position as usize],
+                normal,
+                uv,
+                WHITE,
+            ));
+            vertices.len() as u32 - 1
+        });
+        indices.push(index);
+    }
+
+    // Gather the triangles of each material into one contiguous range
+    let mut order: Vec<usize> = (0..triangle_materials.len()).collect();
+    order.sort_by_key(|t| triangle_materials[*t]);
+    let mut groups: Vec<MeshGroup> = vec![];
+    let mut sorted_indices: Vec<u32> = Vec::with_capacity(indices.len());
+    for run in order.chunk_by(|a, b| triangle_materials[*a] == triangle_materials[*b]) {
+        let start = sorted_indices.len();
+        for t in run {
+            sorted_indices.extend_from_slice(&indices[t * 3..t * 3 + 3]);
+        }
+        groups.push(MeshGroup {
+            start,
+            count: sorted_indices.len() - start,
+            material: triangle_materials[run[0]].map(|m| materials[m].clone()),
+        });
+    }
+
+    Ok(ImportedMesh {
+        vertices,
+        indices: sorted_indices,
+        groups,
+        generated_normals: if generated.is_some() {
+            generated_normals
+        } else {
+            vec![]
+        },
+        vertex_colors: false,
+        warnings,
+    })
+}
+
+// Converts a 1-based, or negative end-relative, OBJ index to a 0-based one
+fn resolve_index(index: i64, count: usize) -> Option<u32> {
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved >= 0 && (resolved as usize) < count {
+        Some(resolved as u32)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,662 @@
+use std::fmt::Write;
+
+use crate::export::WorldMesh;
+use crate::geometry::{regenerate_normals, triangulate, NormalMode};
+use crate::import::{ImportError, ImportedMesh};
+use crate::vertex::{Color, Norm, Pos, Uv, Vertex, WHITE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // Scale that maps the full range of an integer color channel to 0..1
+    fn color_scale(self) -> f64 {
+        match self {
+            Self::U8 | Self::I8 => 1.0 / 255.0,
+            Self::U16 | Self::I16 => 1.0 / 65535.0,
+            Self::U32 | Self::I32 => 1.0 / u32::MAX as f64,
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    }
+}
+
+/// Whether `bytes` start with the PLY magic line.
+pub fn is_ply(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+}
+
+/// Reads an ASCII or binary (little or big endian) PLY file.
+///
+/// Positions, normals, texture coordinates and RGB colors are read from the vertex element and
+/// polygons from the face element. Without faces the vertices form a point cloud, which keeps
+/// zero normals unless the file has them. Other elements are skipped.
+pub fn parse_ply(bytes: &[u8], mode: NormalMode) -> Result<ImportedMesh, ImportError> {
+    let (format, elements, body_start) = parse_header(bytes)?;
+    let mut body = Body::new(format, &bytes[body_start..]);
+    let mut warnings: Vec<String> = vec![];
+
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut faces: Vec<Vec<f64>> = vec![];
+    let mut has_normals = false;
+    let mut has_colors = false;
+    for element in &elements {
+        if !body.fits(element) {
+            return Err(ImportError::new(format!(
+                "{} {} elements do not fit in the file",
+                element.count, element.name
+            )));
+        }
+        match element.name.as_str() {
+            "vertex" => {
+                let position = [
+                    element.find(&["x"]),
+                    element.find(&["y"]),
+                    element.find(&["z"]),
+                ];
+                let [Some(x), Some(y), Some(z)] = position else {
+                    return Err(ImportError::new("vertex element has no x, y and z"));
+                };
+                let normal = [
+                    element.find(&["nx"]),
+                    element.find(&["ny"]),
+                    element.find(&["nz"]),
+                ];
+                let color = [
+                    element.find(&["red", "r", "diffuse_red"]),
+                    element.find(&["green", "g", "diffuse_green"]),
+                    element.find(&["blue", "b", "diffuse_blue"]),
+                ];
+                let uv = [
+                    element.find(&["s", "u", "texture_u", "texture_s"]),
+                    element.find(&["t", "v", "texture_v", "texture_t"]),
+                ];
+                has_normals = normal.iter().all(Option::is_some);
+                has_colors = color.iter().all(Option::is_some);
+                let color_scale: Vec<f64> = element
+                    .properties
+                    .iter()
+                    .map(|p| match p.kind {
+                        PropertyType::Scalar(ty) => ty.color_scale(),
+                        PropertyType::List { .. } => 1.0,
+                    })
+                    .collect();
+
+                vertices.reserve(element.count);
+                for i in 0..element.count {
+                    let values = body
+                        .read_element(element)
+                        .map_err(|e| ImportError::new(format!("vertex {}: {}", i, e)))?;
+                    let value = |p: usize| values[p].first().copied().unwrap_or(0.0);
+                    let pos: Pos = [x, y, z].map(|p| value(p) as f32);
+                    let norm: Norm = match normal {
+                        [Some(nx), Some(ny), Some(nz)] => [nx, ny, nz].map(|p| value(p) as f32),
+                        _ => [0.0; 3],
+                    };
+                    let color: Color = match color {
+                        [Some(r), Some(g), Some(b)] => {
+                            [r, g, b].map(|p| (value(p) * color_scale[p]) as f32)
+                        }
+                        _ => WHITE,
+                    };
+                    let uv: Uv = match uv {
+                        [Some(u), Some(v)] => [value(u) as f32, value(v) as f32],
+                        _ => [0.0, 0.0],
+                    };
+                    vertices.push(Vertex(pos, norm, uv, color));
+                }
+            }
+            "face" => {
+                let list = element
+                    .find(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| ImportError::new("face element has no vertex_indices"))?;
+                for i in 0..element.count {
+                    let mut values = body
+                        .read_element(element)
+                        .map_err(|e| ImportError::new(format!("face {}: {}", i, e)))?;
+                    faces.push(std::mem::take(&mut values[list]));
+                }
+            }
+            _ => {
+                for i in 0..element.count {
+                    body.read_element(element)
+                        .map_err(|e| ImportError::new(format!("{} {}: {}", element.name, i, e)))?;
+                }
+            }
+        }
+    }
+
+    let mut indices: Vec<u32> = vec![];
+    for (i, values) in faces.iter().enumerate() {
+        if values.len() < 3 {
+            warnings.push(format!("face {}: fewer than 3 vertices, skipped", i));
+            continue;
+        }
+        // Negative and fractional indices count as out of range rather than being cast into it
+        let face: Vec<u32> = values
+            .iter()
+            .map_while(|v| {
+                let valid = *v >= 0.0 && v.fract() == 0.0 && *v < vertices.len() as f64;
+                valid.then_some(*v as u32)
+            })
+            .collect();
+        if let Some(index) = values.get(face.len()) {
+            warnings.push(format!(
+                "face {}: vertex index {} is out of range ({} defined), skipped",
+                i,
+                index,
+                vertices.len()
+            ));
+            continue;
+        }
+        let points: Vec<Pos> = face.iter().map(|v| vertices[*v as usize].0).collect();
+        for [a, b, c] in triangulate(&points) {
+            indices.extend_from_slice(&[face[a], face[b], face[c]]);
+        }
+    }
+
+    let mut generated_normals = vec![];
+    if !indices.is_empty() && !has_normals {
+        (vertices, indices) = regenerate_normals(&vertices, &indices, mode);
+        generated_normals = vec![true; vertices.len()];
+    }
+
+    Ok(ImportedMesh {
+        vertices,
+        indices,
+        groups: vec![],
+        generated_normals,
+        vertex_colors: has_colors,
+        warnings,
+    })
+}
+
+/// Writes `meshes` merged into one binary (little endian) or ASCII PLY file.
+///
+/// Normals, texture coordinates and colors are written when any mesh has them, colors as bytes.
+/// Without triangles the file is a point cloud with no face element.
+pub fn write_ply(meshes: &[WorldMesh], binary: bool) -> Vec<u8> {
+    let vertices = || meshes.iter().flat_map(|m| m.mesh.vertices.iter());
+    let vertex_count = vertices().count();
+    let face_count: usize = meshes.iter().map(|m| m.mesh.indices.len() / 3).sum();
+    let has_normals = vertices().any(|v| { v.1 } != [0.0; 3]);
+    let has_uvs = vertices().any(|v| { v.2 } != [0.0; 2]);
+    let has_colors = meshes.iter().any(|m| m.mesh.vertex_colors);
+
+    let mut header = String::from("ply\n");
+    let format = if binary {
+        "binary_little_endian"
+    } else {
+        "ascii"
+    };
+    writeln!(header, "format {} 1.0", format).unwrap();
+    writeln!(header, "comment exported by rust-renderer").unwrap();
+    writeln!(header, "element vertex {}", vertex_count).unwrap();
+    let mut properties = vec!["x", "y", "z"];
+    if has_normals {
+        properties.extend(["nx", "ny", "nz"]);
+    }
+    if has_uvs {
+        properties.extend(["s", "t"]);
+    }
+    for property in properties {
+        writeln!(header, "property float {}", property).unwrap();
+    }
+    if has_colors {
+        for property in ["red", "green", "blue"] {
+            writeln!(header, "property uchar {}", property).unwrap();
+        }
+    }
+    if face_count > 0 {
+        writeln!(header, "element face {}", face_count).unwrap();
+        writeln!(header, "property list uchar int vertex_indices").unwrap();
+    }
+    header.push_str("end_header\n");
+
+    let mut bytes = header.into_bytes();
+    for Vertex(pos, norm, uv, color) in vertices().cloned() {
+        let mut floats = pos.to_vec();
+        if has_normals {
+            floats.extend(norm);
+        }
+        if has_uvs {
+            floats.extend(uv);
+        }
+        let color = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        if binary {
+            bytes.extend(floats.iter().flat_map(|f| f.to_le_bytes()));
+            if has_colors {
+                bytes.extend(color);
+            }
+        } else {
+            let mut line: Vec<String> = floats.iter().map(|f| f.to_string()).collect();
+            if has_colors {
+                line.extend(color.map(|c| c.to_string()));
+            }
+            bytes.extend(line.join(" ").bytes());
+            bytes.push(b'\n');
+        }
+    }
+
+    let mut offset = 0;
+    for WorldMesh { mesh, .. } in meshes {
+        for triangle in mesh.indices.chunks_exact(3) {
+            let triangle = triangle.iter().map(|i| (offset + i) as i32);
+            if binary {
+                bytes.push(3);
+                bytes.extend(triangle.flat_map(|i| i.to_le_bytes()));
+            } else {
+                let line: Vec<String> = triangle.map(|i| i.to_string()).collect();
+                bytes.extend(format!("3 {}\n", line.join(" ")).bytes());
+            }
+        }
+        offset += mesh.vertices.len() as u32;
+    }
+    bytes
+}
+
+// Returns the body format, the elements in file order and where the body starts
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), ImportError> {
+    if !is_ply(bytes) {
+        return Err(ImportError::at_line(
+            1,
+            "",
+            "file does not start with 'ply'",
+        ));
+    }
+    let mut format: Option<Format> = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+
+    for (line_index, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        let line_number = line_index + 1;
+        offset += line.len() + 1;
+        let line = String::from_utf8_lossy(line);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let keyword = tokens.first().copied().unwrap_or_default();
+        let error = |reason: &str| ImportError::at_line(line_number, keyword, reason);
+
+        match keyword {
+            "ply" | "comment" | "obj_info" | "" => {}
+            "format" => {
+                format = Some(match tokens.get(1).copied() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(error("unknown format")),
+                });
+            }
+            "element" => {
+                let (Some(name), Some(count)) = (tokens.get(1), tokens.get(2)) else {
+                    return Err(error("expected a name and a count"));
+                };
+                let count = count.parse().map_err(|_| error("invalid element count"))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            "property" => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before the first element"))?;
+                let scalar = |name: &str| {
+                    ScalarType::parse(name)
+                        .ok_or_else(|| error(&format!("unknown type '{}'", name)))
+                };
+                let property = match tokens[1..] {
+                    ["list", count, item, name] => Property {
+                        name: name.to_string(),
+                        kind: PropertyType::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                    },
+                    [ty, name] => Property {
+                        name: name.to_string(),
+                        kind: PropertyType::Scalar(scalar(ty)?),
+                    },
+                    _ => return Err(error("expected a type and a name")),
+                };
+                element.properties.push(property);
+            }
+            "end_header" => {
+                let format = format.ok_or_else(|| error("header has no format line"))?;
+                return Ok((format, elements, offset.min(bytes.len())));
+            }
+            _ => return Err(error("unknown header keyword")),
+        }
+    }
+
+    Err(ImportError::new("header has no end_header line"))
+}
+
+// Reads element values in either the ASCII or a binary encoding
+struct Body<'a> {
+    format: Format,
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Body<'a> {
+    fn new(format: Format, data: &'a [u8]) -> Self {
+        Self {
+            format,
+            data,
+            position: 0,
+        }
+    }
+
+    // Whether the rest of the data is long enough for `element.count` elements with empty lists,
+    // so a bogus count fails before anything is allocated for it
+    fn fits(&self, element: &Element) -> bool {
+        let remaining = self.data.len() - self.position;
+        let row_size: usize = element
+            .properties
+            .iter()
+            .map(|property| match property.kind {
+                // At least a digit
+                _ if self.format == Format::Ascii => 1,
+                PropertyType::Scalar(ty) => ty.size(),
+                PropertyType::List { count, .. } => count.size(),
+            })
+            .sum();
+        element
+            .count
+            .checked_mul(row_size.max(1))
+            .is_some_and(|size| size <= remaining)
+    }
+
+    // One value list per property; scalars give a single value
+    fn read_element(&mut self, element: &Element) -> Result<Vec<Vec<f64>>, String> {
+        element
+            .properties
+            .iter()
+            .map(|property| match property.kind {
+                PropertyType::Scalar(ty) => Ok(vec![self.read(ty)?]),
+                PropertyType::List { count, item } => {
+                    let count = self.read(count)?;
+                    if count < 0.0 {
+                        return Err(format!("negative list length for '{}'", property.name));
+                    }
+                    (0..count as usize).map(|_| self.read(item)).collect()
+                }
+            })
+            .collect()
+    }
+
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            return self.read_token();
+        }
+        let bytes = self
+            .data
+            .get(self.position..self.position + ty.size())
+            .ok_or("unexpected end of file")?;
+        self.position += ty.size();
+        let big_endian = self.format == Format::BinaryBigEndian;
+        macro_rules! decode {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                (if big_endian {
+                    <$t>::from_be_bytes(bytes)
+                } else {
+                    <$t>::from_le_bytes(bytes)
+                }) as f64
+            }};
+        }
+        Ok(match ty {
+            ScalarType::I8 => decode!(i8),
+            ScalarType::U8 => decode!(u8),
+            ScalarType::I16 => decode!(i16),
+            ScalarType::U16 => decode!(u16),
+            ScalarType::I32 => decode!(i32),
+            ScalarType::U32 => decode!(u32),
+            ScalarType::F32 => decode!(f32),
+            ScalarType::F64 => decode!(f64),
+        })
+    }
+
+    fn read_token(&mut self) -> Result<f64, String> {
+        let rest = &self.data[self.position..];
+        let start = rest
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .ok_or("unexpected end of file")?;
+        let end = rest[start..]
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .map_or(rest.len(), |end| start + end);
+        self.position += end;
+        let token = String::from_utf8_lossy(&rest[start..end]);
+        token
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_quad_with_colors() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   comment a red and green quad\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   property uchar red\n\
+                   property uchar green\n\
+                   property uchar blue\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0 255 0 0\n\
+                   1 0 0 255 0 0\n\
+                   1 1 0 0 255 0\n\
+                   0 1 0 0 255 0\n\
+                   4 0 1 2 3\n";
+        let mesh = parse_ply(ply.as_bytes(), NormalMode::default()).unwrap();
+        assert!(mesh.vertex_colors);
+        assert_eq!(mesh.generated_normals, vec![true; mesh.vertices.len()]);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.vertices.len(), 4);
+        // Regenerating the normals may reorder the vertices
+        let origin = mesh.vertices.iter().find(|v| { v.0 } == [0.0; 3]).unwrap();
+        let Vertex(_, normal, _, color) = *origin;
+        assert_eq!(color, [1.0, 0.0, 0.0]);
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn binary_point_clouds_in_both_byte_orders() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = format!(
+                "ply\nformat {} 1.0\nelement vertex 2\nproperty float x\nproperty float y\n\
+                 property float z\nproperty double confidence\nend_header\n",
+                format
+            )
+            .into_bytes();
+            for (position, confidence) in [([1.0f32, 2.0, 3.0], 0.5f64), ([4.0, 5.0, 6.0], 1.0)] {
+                for v in position {
+                    let encoded = if big_endian {
+                        v.to_be_bytes()
+                    } else {
+                        v.to_le_bytes()
+                    };
+                    bytes.extend_from_slice(&encoded);
+                }
+                let encoded = if big_endian {
+                    confidence.to_be_bytes()
+                } else {
+                    confidence.to_le_bytes()
+                };
+                bytes.extend_from_slice(&encoded);
+            }
+
+            let mesh = parse_ply(&bytes, NormalMode::default()).unwrap();
+            assert!(mesh.indices.is_empty());
+            assert!(!mesh.vertex_colors);
+            let position = mesh.vertices[1].0;
+            assert_eq!(position, [4.0, 5.0, 6.0]);
+            let normal = mesh.vertices[1].1;
+            assert_eq!(normal, [0.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn reports_header_and_body_errors() {
+        let error = parse_ply(
+            b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float64x x\n",
+            NormalMode::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.line, Some(4));
+
+        let error = parse_ply(
+            b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\n\
+              property float z\nend_header\n0 0 0\n1 1\n",
+            NormalMode::default(),
+        )
+        .unwrap_err();
+        assert!(error.reason.starts_with("vertex 1"));
+    }
+
+    #[test]
+    fn skips_faces_with_invalid_indices() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 4\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   0 1 0\n\
+                   3 0 1 2\n\
+                   3 0 -1 2\n\
+                   3 0 1.5 2\n\
+                   3 0 1 3\n";
+        let mesh = parse_ply(ply.as_bytes(), NormalMode::default()).unwrap();
+        assert_eq!(mesh.indices.len(), 3);
+        assert_eq!(
+            mesh.warnings,
+            [
+                "face 1: vertex index -1 is out of range (3 defined), skipped",
+                "face 2: vertex index 1.5 is out of range (3 defined), skipped",
+                "face 3: vertex index 3 is out of range (3 defined), skipped",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_counts_larger_than_the_file() {
+        for format in ["ascii", "binary_little_endian"] {
+            let ply = format!(
+                "ply\nformat {} 1.0\nelement vertex 4000000000000000000\nproperty float x\n\
+                 property float y\nproperty float z\nend_header\n0 0 0\n",
+                format
+            );
+            let error = parse_ply(ply.as_bytes(), NormalMode::default()).unwrap_err();
+            assert!(error.reason.contains("do not fit"), "{}", error.reason);
+        }
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let vertex = |x: f32, color: Color| Vertex([x, 0.0, 1.0], [0.0; 3], [0.0; 2], color);
+        let points = ImportedMesh {
+            vertices: vec![vertex(0.0, [1.0, 0.0, 0.0]), vertex(2.0, [0.0, 0.0, 1.0])],
+            indices: vec![],
+            groups: vec![],
+            generated_normals: vec![],
+            vertex_colors: true,
+            warnings: vec![],
+        };
+        let meshes = [WorldMesh {
+            name: String::new(),
+            mesh: points,
+        }];
+        for binary in [false, true] {
+            let bytes = write_ply(&meshes, binary);
+            let mesh = parse_ply(&bytes, NormalMode::default()).unwrap();
+            assert!(mesh.indices.is_empty());
+            assert!(mesh.vertex_colors);
+            let Vertex(position, _, _, color) = mesh.vertices[1];
+            assert_eq!(position, [2.0, 0.0, 1.0]);
+            assert_eq!(color, [0.0, 0.0, 1.0]);
+        }
+    }
+}
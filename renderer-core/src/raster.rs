@@ -0,0 +1,583 @@
+use cgmath::{
+    ElementWise, EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector2,
+    Vector3, Vector4,
+};
+
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::texture::TextureImage;
+use crate::vertex::Vertex;
+
+// Light the web renderer sets up: white, with a fixed share of ambient light
+const LIGHT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const AMBIENT_STRENGTH: f32 = 0.2;
+
+/// What one draw call hands the shaders through uniforms and bound textures.
+pub struct DrawState<'a> {
+    pub model: Matrix4<f32>,
+    pub material: &'a Material,
+    pub diffuse_map: Option<&'a TextureImage>,
+    pub bump_map: Option<&'a TextureImage>,
+    pub use_vertex_color: bool,
+    /// Size in pixels of points drawn with `draw_points`.
+    pub point_size: f32,
+}
+
+// Vertex shader outputs, interpolated across primitives
+#[derive(Debug, Clone, Copy)]
+struct Varyings {
+    frag_pos: Vector3<f32>,
+    normal: Vector3<f32>,
+    tex_coord: Vector2<f32>,
+    color: Vector3<f32>,
+}
+
+impl Varyings {
+    fn blend(parts: &[(Varyings, f32)]) -> Self {
+        let mut sum = Varyings {
+            frag_pos: Vector3::new(0.0, 0.0, 0.0),
+            normal: Vector3::new(0.0, 0.0, 0.0),
+            tex_coord: Vector2::new(0.0, 0.0),
+            color: Vector3::new(0.0, 0.0, 0.0),
+        };
+        for (v, weight) in parts {
+            sum.frag_pos += v.frag_pos * *weight;
+            sum.normal += v.normal * *weight;
+            sum.tex_coord += v.tex_coord * *weight;
+            sum.color += v.color * *weight;
+        }
+        sum
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClipVertex {
+    position: Vector4<f32>,
+    varyings: Varyings,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: self.position + (other.position - self.position) * t,
+            varyings: Varyings::blend(&[(self.varyings, 1.0 - t), (other.varyings, t)]),
+        }
+    }
+
+    // Signed distance from the near clip plane, positive on the visible side
+    fn near_distance(&self) -> f32 {
+        self.position.z + self.position.w
+    }
+}
+
+// A vertex in window coordinates: pixels from the bottom left corner and depth in 0..1
+#[derive(Debug, Clone, Copy)]
+struct WindowVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    varyings: Varyings,
+}
+
+/// Draws triangles, lines and points the way the WebGL renderer's shader program does, into an
+/// RGBA image held in memory.
+///
+/// The vertex and fragment shaders, the `LESS` depth test and the `SRC_ALPHA`,
+/// `ONE_MINUS_SRC_ALPHA` blending follow `main` in the web renderer, so changes to its GLSL
+/// belong here too. Textures are sampled bilinearly without mipmaps, and lines are one pixel
+/// wide.
+pub struct Rasterizer {
+    width: usize,
+    height: usize,
+    // Bottom row first, like the GL framebuffer
+    color: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+    view_projection: Matrix4<f32>,
+    light_pos: Point3<f32>,
+    view_pos: Point3<f32>,
+}
+
+impl Rasterizer {
+    /// A `width` x `height` image cleared to opaque black, seen through an identity camera.
+    pub fn new(width: u32, height: u32) -> Self {
+        let width = width.max(1) as usize;
+        let height = height.max(1) as usize;
+        Self {
+            width,
+            height,
+            color: vec![[0.0, 0.0, 0.0, 1.0]; width * height],
+            depth: vec![1.0; width * height],
+            view_projection: Matrix4::identity(),
+            light_pos: Point3::new(0.0, 5.0, 5.0),
+            view_pos: Point3::new(0.0, 0.0, 2.0),
+        }
+    }
+
+    pub fn clear(&mut self, color: [f32; 4]) {
+        self.color.fill(color);
+        self.depth.fill(1.0);
+    }
+
+    /// Draws from `camera`'s point of view, with the light where the web renderer puts it.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.view_projection = camera.projection_matrix() * camera.view();
+        self.light_pos = camera.light_position();
+        self.view_pos = camera.eye();
+    }
+
+    /// Draws an indexed triangle list. Both sides of the triangles are drawn.
+    pub fn draw_triangles(&mut self, vertices: &[Vertex], indices: &[u32], state: &DrawState) {
+        let clip = self.run_vertex_shader(vertices, state);
+        for triangle in indices.chunks_exact(3) {
+            let corners = [triangle[0], triangle[1], triangle[2]].map(|i| clip.get(i as usize));
+            if let [Some(a), Some(b), Some(c)] = corners {
+                for [a, b, c] in clip_triangle(*a, *b, *c) {
+                    self.fill_triangle([a, b, c].map(|v| self.to_window(&v)), state);
+                }
+            }
+        }
+    }
+
+    /// Draws an indexed line list.
+    pub fn draw_lines(&mut self, vertices: &[Vertex], indices: &[u32], state: &DrawState) {
+        let clip = self.run_vertex_shader(vertices, state);
+        for line in indices.chunks_exact(2) {
+            if let (Some(a), Some(b)) = (clip.get(line[0] as usize), clip.get(line[1] as usize)) {
+                if let Some((a, b)) = clip_line(*a, *b) {
+                    self.draw_segment(self.to_window(&a), self.to_window(&b), state);
+                }
+            }
+        }
+    }
+
+    /// Draws a square point of `state.point_size` pixels for every vertex.
+    pub fn draw_points(&mut self, vertices: &[Vertex], state: &DrawState) {
+        let size = state.point_size.max(1.0);
+        for v in self.run_vertex_shader(vertices, state) {
+            let p = v.position;
+            let inside = [p.x, p.y, p.z].iter().all(|c| c.abs() <= p.w);
+            if !inside || p.w <= 0.0 {
+                continue;
+            }
+            let center = self.to_window(&v);
+            let Some(color) = self.shade(&center.varyings, None, state) else {
+                continue;
+            };
+            let half = size / 2.0;
+            for y in pixel_span(center.y - half, center.y + half, self.height) {
+                for x in pixel_span(center.x - half, center.x + half, self.width) {
+                    self.write(x, y, center.z, color);
+                }
+            }
+        }
+    }
+
+    /// The pixels drawn so far, top row first.
+    pub fn image(&self) -> TextureImage {
+        let mut pixels = Vec::with_capacity(self.width * self.height * 4);
+        for row in self.color.chunks_exact(self.width).rev() {
+            for color in row {
+                pixels.extend(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+            }
+        }
+        TextureImage {
+            width: self.width as u32,
+            height: self.height as u32,
+            pixels,
+        }
+    }
+
+    fn run_vertex_shader(&self, vertices: &[Vertex], state: &DrawState) -> Vec<ClipVertex> {
+        let normal_matrix = state
+            .model
+            .invert()
+            .map(|inverse| inverse.transpose())
+            .unwrap_or_else(Matrix4::identity);
+        vertices
+            .iter()
+            .map(|v| {
+                let Vertex(pos, norm, uv, color) = *v;
+                let frag_pos = (state.model * Vector3::from(pos).extend(1.0)).truncate();
+                ClipVertex {
+                    position: self.view_projection * frag_pos.extend(1.0),
+                    varyings: Varyings {
+                        frag_pos,
+                        normal: (normal_matrix * Vector3::from(norm).extend(0.0)).truncate(),
+                        tex_coord: Vector2::from(uv),
+                        color: Vector3::from(color),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn to_window(&self, v: &ClipVertex) -> WindowVertex {
+        let inv_w = 1.0 / v.position.w;
+        WindowVertex {
+            x: (v.position.x * inv_w + 1.0) * 0.5 * self.width as f32,
+            y: (v.position.y * inv_w + 1.0) * 0.5 * self.height as f32,
+            z: (v.position.z * inv_w + 1.0) * 0.5,
+            inv_w,
+            varyings: v.varyings,
+        }
+    }
+
+    fn fill_triangle(&mut self, corners: [WindowVertex; 3], state: &DrawState) {
+        let [a, mut b, mut c] = corners;
+        let edge = |p: &WindowVertex, q: &WindowVertex, x: f32, y: f32| {
+            (q.x - p.x) * (y - p.y) - (q.y - p.y) * (x - p.x)
+        };
+        let mut area = edge(&a, &b, c.x, c.y);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+        // Wind counter-clockwise so the inside is left of every edge
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+            area = -area;
+        }
+        let corners = [a, b, c];
+
+        // Pixels on a shared edge belong to the triangle that has it as a top or left edge
+        let top_left = |p: &WindowVertex, q: &WindowVertex| {
+            let (dx, dy) = (q.x - p.x, q.y - p.y);
+            dy < 0.0 || (dy == 0.0 && dx < 0.0)
+        };
+        let edges = [(1, 2), (2, 0), (0, 1)].map(|(i, j)| (corners[i], corners[j]));
+        let owned = edges.map(|(p, q)| top_left(&p, &q));
+
+        // Perspective correct attributes at any point of the triangle's plane
+        let interpolate = |x: f32, y: f32| {
+            let weights = edges.map(|(p, q)| edge(&p, &q, x, y) / area);
+            let inv_w: f32 = (0..3).map(|i| weights[i] * corners[i].inv_w).sum();
+            let parts =
+                [0, 1, 2].map(|i| (corners[i].varyings, weights[i] * corners[i].inv_w / inv_w));
+            Varyings::blend(&parts)
+        };
+
+        // Bounding box of the triangle, a pixel wider so centers on its far edges are tested
+        let min_x = corners.iter().map(|v| v.x).fold(f32::MAX, f32::min);
+        let max_x = corners.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+        let min_y = corners.iter().map(|v| v.y).fold(f32::MAX, f32::min);
+        let max_y = corners.iter().map(|v| v.y).fold(f32::MIN, f32::max);
+        let bump = state.bump_map.is_some();
+
+        for y in pixel_span(min_y, max_y + 1.0, self.height) {
+            let py = y as f32 + 0.5;
+            for x in pixel_span(min_x, max_x + 1.0, self.width) {
+                let px = x as f32 + 0.5;
+                let weights = edges.map(|(p, q)| edge(&p, &q, px, py));
+                let inside = (0..3).all(|i| weights[i] > 0.0 || (weights[i] == 0.0 && owned[i]));
+                if !inside {
+                    continue;
+                }
+                let z: f32 = (0..3).map(|i| weights[i] / area * corners[i].z).sum();
+                if !(0.0..=1.0).contains(&z) {
+                    continue;
+                }
+                let varyings = interpolate(px, py);
+                // Screen space derivatives from the neighbouring pixels, as dFdx and dFdy
+                let neighbours =
+                    bump.then(|| (interpolate(px + 1.0, py), interpolate(px, py + 1.0)));
+                if let Some(color) = self.shade(&varyings, neighbours, state) {
+                    self.write(x, y, z, color);
+                }
+            }
+        }
+    }
+
+    // One pixel per step along the longer axis, at the pixel centers it crosses
+    fn draw_segment(&mut self, a: WindowVertex, b: WindowVertex, state: &DrawState) {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        // Work in (major, minor) coordinates, swapping back when writing
+        let x_major = dx.abs() >= dy.abs();
+        let (major, minor, along, across) = if x_major {
+            (a.x, a.y, dx, dy)
+        } else {
+            (a.y, a.x, dy, dx)
+        };
+        let (major_size, minor_size) = if x_major {
+            (self.width, self.height)
+        } else {
+            (self.height, self.width)
+        };
+        if along == 0.0 {
+            return;
+        }
+
+        let end = major + along;
+        for i in pixel_span(major.min(end), major.max(end), major_size) {
+            let t = (i as f32 + 0.5 - major) / along;
+            let j = (minor + t * across).floor();
+            if j < 0.0 || j >= minor_size as f32 {
+                continue;
+            }
+            let z = a.z + (b.z - a.z) * t;
+            if !(0.0..=1.0).contains(&z) {
+                continue;
+            }
+            let inv_w = a.inv_w + (b.inv_w - a.inv_w) * t;
+            let weight = t * b.inv_w / inv_w;
+            let varyings = Varyings::blend(&[(a.varyings, 1.0 - weight), (b.varyings, weight)]);
+            if let Some(color) = self.shade(&varyings, None, state) {
+                let (x, y) = if x_major {
+                    (i, j as usize)
+                } else {
+                    (j as usize, i)
+                };
+                self.write(x, y, z, color);
+            }
+        }
+    }
+
+    // The fragment shader; `neighbours` are the attributes one pixel to the right and above
+    fn shade(
+        &self,
+        v: &Varyings,
+        neighbours: Option<(Varyings, Varyings)>,
+        state: &DrawState,
+    ) -> Option<[f32; 4]> {
+        let material = state.material;
+        let color = if state.use_vertex_color {
+            v.color
+        } else {
+            Vector3::from(material.diffuse)
+        };
+        let base = match state.diffuse_map {
+            Some(image) => Vector4::from(sample(image, v.tex_coord)),
+            None => color.extend(1.0),
+        };
+        let alpha = base.w * material.opacity;
+
+        // Points without normals have nothing to shade against
+        if v.normal.magnitude2() == 0.0 {
+            return Some([base.x, base.y, base.z, alpha]);
+        }
+
+        let light_color = Vector3::from(LIGHT_COLOR);
+        let ambient =
+            (Vector3::from(material.ambient) * AMBIENT_STRENGTH).mul_element_wise(light_color);
+
+        let mut norm = v.normal.normalize();
+        if let (Some(bump_map), Some((right, up))) = (state.bump_map, neighbours) {
+            let height = |v: &Varyings| sample(bump_map, v.tex_coord)[0] * material.bump_scale;
+            let dpdx = right.frag_pos - v.frag_pos;
+            let dpdy = up.frag_pos - v.frag_pos;
+            let dhdx = height(&right) - height(v);
+            let dhdy = height(&up) - height(v);
+            let r1 = dpdy.cross(norm);
+            let r2 = norm.cross(dpdx);
+            let det = dpdx.dot(r1);
+            let gradient = (r1 * dhdx + r2 * dhdy) * glsl_sign(det);
+            norm = (norm * det.abs() - gradient).normalize();
+        }
+
+        let frag_pos = Point3::from_vec(v.frag_pos);
+        let light_dir = (self.light_pos - frag_pos).normalize();
+        let diff = norm.dot(light_dir).max(0.0);
+        let diffuse = light_color * diff;
+
+        let view_dir = (self.view_pos - frag_pos).normalize();
+        let reflect_dir = -light_dir - norm * (2.0 * norm.dot(-light_dir));
+        let spec = view_dir
+            .dot(reflect_dir)
+            .max(0.0)
+            .powf(material.shininess.max(1.0));
+        let specular = (Vector3::from(material.specular) * spec).mul_element_wise(light_color);
+
+        let result = (ambient + diffuse).mul_element_wise(base.truncate()) + specular;
+        Some([result.x, result.y, result.z, alpha])
+    }
+
+    // Depth test, then blending by the fragment's alpha
+    fn write(&mut self, x: usize, y: usize, depth: f32, color: [f32; 4]) {
+        let index = y * self.width + x;
+        if depth >= self.depth[index] || color.iter().any(|c| c.is_nan()) {
+            return;
+        }
+        self.depth[index] = depth;
+        let alpha = color[3].clamp(0.0, 1.0);
+        let target = &mut self.color[index];
+        for (dst, src) in target.iter_mut().zip(color) {
+            *dst = src.clamp(0.0, 1.0) * alpha + *dst * (1.0 - alpha);
+        }
+    }
+}
+
+// Pixels whose centers lie in `start..end`, limited to `0..size`
+fn pixel_span(start: f32, end: f32, size: usize) -> std::ops::Range<usize> {
+    let first = (start - 0.5).ceil().clamp(0.0, size as f32) as usize;
+    let last = (end - 0.5).ceil().clamp(0.0, size as f32) as usize;
+    first..last
+}
+
+// GLSL's sign, which is 0 for 0
+fn glsl_sign(x: f32) -> f32 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+// Bilinear lookup with repeat wrapping, `v = 0` being the bottom row as the texture is uploaded
+// flipped
+fn sample(image: &TextureImage, uv: Vector2<f32>) -> [f32; 4] {
+    let (width, height) = (image.width as i64, image.height as i64);
+    if width == 0 || height == 0 || image.pixels.len() < (width * height * 4) as usize {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let x = uv.x * width as f32 - 0.5;
+    let y = uv.y * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |i: i64, j: i64| {
+        let i = i.rem_euclid(width);
+        let row = height - 1 - j.rem_euclid(height);
+        let offset = ((row * width + i) * 4) as usize;
+        let p = &image.pixels[offset..offset + 4];
+        [0, 1, 2, 3].map(|c| p[c] as f32 / 255.0)
+    };
+    let (i, j) = (x0 as i64, y0 as i64);
+    let [a, b, c, d] = [
+        texel(i, j),
+        texel(i + 1, j),
+        texel(i, j + 1),
+        texel(i + 1, j + 1),
+    ];
+    [0, 1, 2, 3].map(|k| {
+        let bottom = a[k] + (b[k] - a[k]) * fx;
+        let top = c[k] + (d[k] - c[k]) * fx;
+        bottom + (top - bottom) * fy
+    })
+}
+
+// Cuts a triangle against the near plane, into none, one or two triangles
+fn clip_triangle(a: ClipVertex, b: ClipVertex, c: ClipVertex) -> Vec<[ClipVertex; 3]> {
+    let corners = [a, b, c];
+    if corners.iter().all(|v| v.near_distance() >= 0.0) {
+        return vec![corners];
+    }
+    let mut polygon = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (p, q) = (corners[i], corners[(i + 1) % 3]);
+        let (dp, dq) = (p.near_distance(), q.near_distance());
+        if dp >= 0.0 {
+            polygon.push(p);
+        }
+        if (dp >= 0.0) != (dq >= 0.0) {
+            polygon.push(p.lerp(&q, dp / (dp - dq)));
+        }
+    }
+    (1..polygon.len().saturating_sub(1))
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+// Cuts a segment against the near plane
+fn clip_line(a: ClipVertex, b: ClipVertex) -> Option<(ClipVertex, ClipVertex)> {
+    let (da, db) = (a.near_distance(), b.near_distance());
+    match (da >= 0.0, db >= 0.0) {
+        (true, true) => Some((a, b)),
+        (false, false) => None,
+        (true, false) => Some((a, a.lerp(&b, da / (da - db)))),
+        (false, true) => Some((a.lerp(&b, da / (da - db)), b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::WHITE;
+
+    // A square in front of the identity camera, facing it, at depth `z` in clip space
+    fn square(z: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let vertex = |x: f32, y: f32| Vertex([x, y, z], [0.0, 0.0, 1.0], [0.0, 0.0], WHITE);
+        let vertices = vec![
+            vertex(-0.5, -0.5),
+            vertex(0.5, -0.5),
+            vertex(0.5, 0.5),
+            vertex(-0.5, 0.5),
+        ];
+        (vertices, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    fn unlit(color: [f32; 4]) -> Material {
+        // No light but the ambient term, so the color comes out as given
+        Material {
+            ambient: [5.0, 5.0, 5.0],
+            specular: [0.0, 0.0, 0.0],
+            ..Material::with_color(color)
+        }
+    }
+
+    fn state(material: &Material) -> DrawState<'_> {
+        DrawState {
+            model: Matrix4::identity(),
+            material,
+            diffuse_map: None,
+            bump_map: None,
+            use_vertex_color: false,
+            point_size: 1.0,
+        }
+    }
+
+    fn pixel(image: &TextureImage, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * image.width + x) * 4) as usize;
+        image.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn nearer_triangles_win_regardless_of_order() {
+        let red = unlit([1.0, 0.0, 0.0, 1.0]);
+        let green = unlit([0.0, 1.0, 0.0, 1.0]);
+        let (near, indices) = square(-0.5);
+        let (far, _) = square(0.5);
+
+        let mut raster = Rasterizer::new(8, 8);
+        raster.draw_triangles(&near, &indices, &state(&red));
+        raster.draw_triangles(&far, &indices, &state(&green));
+        let image = raster.image();
+        assert_eq!(pixel(&image, 4, 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 0, 0), [0, 0, 0, 255]);
+        // The square covers the middle half of the image
+        let covered = image.pixels.chunks_exact(4).filter(|p| p[0] > 0).count();
+        assert_eq!(covered, 16);
+    }
+
+    #[test]
+    fn shared_edges_blend_once() {
+        let half = unlit([1.0, 1.0, 1.0, 0.5]);
+        let (vertices, indices) = square(0.0);
+        let mut raster = Rasterizer::new(16, 16);
+        raster.draw_triangles(&vertices, &indices, &state(&half));
+        let image = raster.image();
+        let covered: Vec<&[u8]> = image.pixels.chunks_exact(4).filter(|p| p[0] > 0).collect();
+        assert_eq!(covered.len(), 64);
+        assert!(covered.iter().all(|p| p == &[128, 128, 128, 191]));
+    }
+
+    #[test]
+    fn phong_terms_match_the_shader() {
+        let material = Material {
+            specular: [0.0, 0.0, 0.0],
+            ..Material::with_color([1.0, 0.5, 0.25, 1.0])
+        };
+        let (vertices, indices) = square(0.0);
+        let mut raster = Rasterizer::new(2, 2);
+        raster.light_pos = Point3::new(0.0, 0.0, 1.0);
+        let state = DrawState {
+            model: Matrix4::from_scale(2.0),
+            ..state(&material)
+        };
+        raster.draw_triangles(&vertices, &indices, &state);
+
+        // The light is straight in front of the pixel centers at (+-0.5, +-0.5), one unit away
+        let diff = 1.0 / 1.5f32.sqrt();
+        let expected = [1.0, 0.5, 0.25].map(|c| ((0.2 + diff) * c * 255.0).round() as u8);
+        let image = raster.image();
+        assert_eq!(pixel(&image, 0, 0)[..3], expected);
+        assert_eq!(pixel(&image, 1, 1)[..3], expected);
+    }
+}
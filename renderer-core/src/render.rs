@@ -0,0 +1,220 @@
+use cgmath::{Matrix4, SquareMatrix};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::camera::Camera;
+use crate::import::{ImportedMesh, ImportedNode};
+use crate::material::Material;
+use crate::raster::{DrawState, Rasterizer};
+use crate::texture::TextureImage;
+use crate::vertex::{Vertex, WHITE};
+
+/// Size in pixels that point clouds are drawn at until changed.
+pub const DEFAULT_POINT_SIZE: f32 = 2.0;
+
+/// Number of grid lines on each side of the origin.
+pub const GRID_HALF_COUNT: i32 = 100;
+
+/// The web renderer's display settings, for drawing a scene without it.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    /// The model color, RGBA in 0..1.
+    pub color: [f32; 4],
+    /// Draws materials in `color` instead of their own diffuse colors.
+    pub color_override: bool,
+    pub wireframe: bool,
+    /// Drawn in place of every diffuse color and map, like a texture set on the model.
+    pub texture: Option<TextureImage>,
+    pub point_size: f32,
+    /// Draws the ground grid beneath the scene.
+    pub grid: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            color: [0.75, 0.75, 0.75, 1.0],
+            color_override: false,
+            wireframe: false,
+            texture: None,
+            point_size: DEFAULT_POINT_SIZE,
+            grid: true,
+        }
+    }
+}
+
+/// Draws `nodes` seen from `camera` the way the web renderer draws its scene, without a GPU.
+///
+/// `images` holds the texture maps by the names the materials use. The camera's aspect ratio is
+/// set to match the image.
+pub fn render(
+    nodes: &[ImportedNode],
+    images: &HashMap<String, TextureImage>,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> TextureImage {
+    let mut camera = camera.clone();
+    camera.aspect = options.width.max(1) as f32 / options.height.max(1) as f32;
+    let mut raster = Rasterizer::new(options.width, options.height);
+    raster.set_camera(&camera);
+
+    if options.grid {
+        let (vertices, indices) = generate_grid(GRID_HALF_COUNT, 1.0);
+        let white = Material::with_color([1.0, 1.0, 1.0, 1.0]);
+        let state = DrawState {
+            model: Matrix4::identity(),
+            material: &white,
+            diffuse_map: None,
+            bump_map: None,
+            use_vertex_color: false,
+            point_size: options.point_size,
+        };
+        raster.draw_lines(&vertices, &indices, &state);
+    }
+    draw_nodes(&mut raster, nodes, &Matrix4::identity(), images, options);
+    raster.image()
+}
+
+fn draw_nodes(
+    raster: &mut Rasterizer,
+    nodes: &[ImportedNode],
+    parent: &Matrix4<f32>,
+    images: &HashMap<String, TextureImage>,
+    options: &RenderOptions,
+) {
+    for node in nodes {
+        let world = parent * node.transform;
+        if let Some(ref mesh) = node.mesh {
+            draw_mesh(raster, mesh, &world, images, options);
+        }
+        draw_nodes(raster, &node.children, &world, images, options);
+    }
+}
+
+// Draws the points, the triangles, or their edges in wireframe mode. Each group is drawn with its
+// own material, opaque ones first so transparent ones blend over them.
+fn draw_mesh(
+    raster: &mut Rasterizer,
+    mesh: &ImportedMesh,
+    world: &Matrix4<f32>,
+    images: &HashMap<String, TextureImage>,
+    options: &RenderOptions,
+) {
+    let model_material = Material::with_color(options.color);
+    let model_state = DrawState {
+        model: *world,
+        material: &model_material,
+        diffuse_map: None,
+        bump_map: None,
+        use_vertex_color: mesh.vertex_colors && !options.color_override,
+        point_size: options.point_size,
+    };
+    if mesh.indices.is_empty() {
+        raster.draw_points(&mesh.vertices, &model_state);
+        return;
+    }
+    if options.wireframe {
+        let edges = triangle_edges(&mesh.indices);
+        raster.draw_lines(&mesh.vertices, &edges, &model_state);
+        return;
+    }
+
+    // The texture set on the model takes precedence over the material's diffuse map
+    let texture = options.texture.as_ref();
+    let image = |name: &Option<String>| name.as_ref().and_then(|name| images.get(name));
+    if mesh.groups.iter().all(|g| g.material.is_none()) {
+        let state = DrawState {
+            diffuse_map: texture,
+            ..model_state
+        };
+        raster.draw_triangles(&mesh.vertices, &mesh.indices, &state);
+        return;
+    }
+    let (opaque, transparent): (Vec<_>, Vec<_>) = mesh
+        .groups
+        .iter()
+        .partition(|g| g.material.as_ref().is_none_or(|m| m.opacity >= 1.0));
+    for group in opaque.into_iter().chain(transparent) {
+        let (material, diffuse_map, bump_map) = match group.material {
+            Some(ref material) if !options.color_override => (
+                Cow::Borrowed(material),
+                texture.or(image(&material.diffuse_map)),
+                image(&material.bump_map),
+            ),
+            Some(ref material) => (
+                Cow::Owned(Material {
+                    diffuse: model_material.diffuse,
+                    ..material.clone()
+                }),
+                texture,
+                image(&material.bump_map),
+            ),
+            None => (Cow::Borrowed(&model_material), texture, None),
+        };
+        let state = DrawState {
+            material: &material,
+            diffuse_map,
+            bump_map,
+            ..model_state
+        };
+        let indices = &mesh.indices[group.start..group.start + group.count];
+        raster.draw_triangles(&mesh.vertices, indices, &state);
+    }
+}
+
+/// Line list for the ground grid: `half_count` lines either side of the origin along X and Z,
+/// `spacing` apart.
+pub fn generate_grid(half_count: i32, spacing: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new(); // u16 is enough for ≤-65 535 verts
+
+    // unit +Y normal (0,1,0)
+    const N: [f32; 3] = [0.0, 1.0, 0.0];
+
+    // helper to push a vertex and return its index
+    let push_vert = |p: [f32; 3], verts: &mut Vec<Vertex>| -> u16 {
+        let idx = verts.len() as u16;
+        verts.push(Vertex(p, N, [0.0, 0.0], WHITE));
+        idx
+    };
+
+    // horizontal (X-axis) lines – vary Z
+    for i in -half_count..=half_count {
+        let z = i as f32 * spacing;
+        let i0 = push_vert([-half_count as f32 * spacing, 0.0, z], &mut vertices) as u32;
+        let i1 = push_vert([half_count as f32 * spacing, 0.0, z], &mut vertices) as u32;
+        indices.extend_from_slice(&[i0, i1]);
+    }
+
+    // vertical (Z-axis) lines – vary X
+    for i in -half_count..=half_count {
+        let x = i as f32 * spacing;
+        let i0 = push_vert([x, 0.0, -half_count as f32 * spacing], &mut vertices) as u32;
+        let i1 = push_vert([x, 0.0, half_count as f32 * spacing], &mut vertices) as u32;
+        indices.extend_from_slice(&[i0, i1]);
+    }
+
+    (vertices, indices)
+}
+
+/// Line list with every distinct edge of a triangle list.
+pub fn triangle_edges(indices: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    let mut edges = Vec::new();
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [
+            (triangle[0], triangle[1]),
+            (triangle[1], triangle[2]),
+            (triangle[2], triangle[0]),
+        ] {
+            if seen.insert((a.min(b), a.max(b))) {
+                edges.extend_from_slice(&[a, b]);
+            }
+        }
+    }
+    edges
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::bounds::BoundingBox;
+use crate::export::world_meshes;
+use crate::geometry::weld_key;
+use crate::import::{all_nodes, ImportedMesh, ImportedNode};
+
+/// Counts and checks describing an imported model, as reported by the command-line tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshStats {
+    pub nodes: usize,
+    pub meshes: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Vertices of meshes without triangles, drawn as points.
+    pub points: usize,
+    /// World-space bounds of all meshes, `None` when there are no vertices.
+    pub bounds: Option<BoundingBox>,
+    /// Material names in the order they are first used.
+    pub materials: Vec<String>,
+    /// Edges with a single triangle on them, where a closed surface has a hole.
+    pub boundary_edges: usize,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: usize,
+}
+
+impl MeshStats {
+    /// Gathers the statistics of `nodes` and all their descendants.
+    ///
+    /// Edges are compared by position, so seams where vertices are split for differing normals or
+    /// texture coordinates do not count as boundaries.
+    pub fn new(nodes: &[ImportedNode]) -> Self {
+        let meshes: Vec<&ImportedMesh> = all_nodes(nodes)
+            .into_iter()
+            .filter_map(|n| n.mesh.as_ref())
+            .collect();
+        let bounds = world_meshes(nodes)
+            .iter()
+            .filter_map(|m| BoundingBox::from_vertices(&m.mesh.vertices))
+            .reduce(|a, b| a.union(&b));
+
+        let mut stats = Self {
+            nodes: all_nodes(nodes).len(),
+            meshes: meshes.len(),
+            vertices: 0,
+            triangles: 0,
+            points: 0,
+            bounds,
+            materials: vec![],
+            boundary_edges: 0,
+            non_manifold_edges: 0,
+        };
+        for mesh in meshes {
+            stats.vertices += mesh.vertices.len();
+            stats.triangles += mesh.indices.len() / 3;
+            if mesh.indices.is_empty() {
+                stats.points += mesh.vertices.len();
+            }
+            for material in mesh.groups.iter().filter_map(|g| g.material.as_ref()) {
+                if !stats.materials.contains(&material.name) {
+                    stats.materials.push(material.name.clone());
+                }
+            }
+
+            for &count in edge_counts(mesh).values() {
+                match count {
+                    1 => stats.boundary_edges += 1,
+                    2 => {}
+                    _ => stats.non_manifold_edges += 1,
+                }
+            }
+        }
+        stats
+    }
+}
+
+// Number of triangles on each edge, by the welded positions of its ends
+fn edge_counts(mesh: &ImportedMesh) -> HashMap<([u32; 3], [u32; 3]), usize> {
+    let keys: Vec<[u32; 3]> = mesh.vertices.iter().map(|v| weld_key(v.0)).collect();
+    let mut counts = HashMap::new();
+    for triangle in mesh.indices.chunks_exact(3) {
+        for (a, b) in [
+            (triangle[0], triangle[1]),
+            (triangle[1], triangle[2]),
+            (triangle[2], triangle[0]),
+        ] {
+            let (a, b) = (keys[a as usize], keys[b as usize]);
+            if a != b {
+                *counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::ImportedScene;
+    use crate::vertex::{Vertex, WHITE};
+
+    fn mesh(positions: &[[f32; 3]], indices: Vec<u32>) -> ImportedMesh {
+        ImportedMesh {
+            vertices: positions
+                .iter()
+                .map(|&p| Vertex(p, [0.0, 0.0, 1.0], [0.0, 0.0], WHITE))
+                .collect(),
+            indices,
+            groups: vec![],
+            generated_normals: vec![],
+            vertex_colors: false,
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn closed_surface_has_no_open_edges() {
+        // A tetrahedron, with one corner split as if it had two texture coordinates
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0],
+        ];
+        let indices = vec![0, 2, 1, 0, 1, 3, 4, 3, 2, 1, 2, 3];
+        let scene = ImportedScene::from_mesh(mesh(&positions, indices));
+        let stats = MeshStats::new(&scene.nodes);
+        assert_eq!((stats.vertices, stats.triangles), (5, 4));
+        assert_eq!((stats.boundary_edges, stats.non_manifold_edges), (0, 0));
+        let bounds = stats.bounds.unwrap();
+        assert_eq!(bounds.max, cgmath::Point3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn counts_open_and_shared_edges() {
+        // Three triangles hinged on the edge from the origin up the Y axis
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [-1.0, 0.0, 0.0],
+        ];
+        let indices = vec![0, 1, 2, 0, 1, 3, 0, 1, 4];
+        let scene = ImportedScene::from_mesh(mesh(&positions, indices));
+        let stats = MeshStats::new(&scene.nodes);
+        assert_eq!(stats.non_manifold_edges, 1);
+        assert_eq!(stats.boundary_edges, 6);
+    }
+}
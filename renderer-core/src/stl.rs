@@ -0,0 +1,433 @@
+use cgmath::Vector3;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::export::WorldMesh;
+use crate::geometry::{corner_normals, weld_key, NormalMode};
+use crate::import::{ImportError, ImportedMesh};
+use crate::vertex::{Norm, Pos, Vertex, WHITE};
+
+// 80 byte header followed by the triangle count
+const HEADER_SIZE: usize = 84;
+// Normal, three vertices and the attribute byte count
+const TRIANGLE_SIZE: usize = 50;
+
+/// Whether `bytes` look like an STL file, binary or ASCII.
+pub fn is_stl(bytes: &[u8]) -> bool {
+    is_binary_stl(bytes) || is_ascii_stl(bytes)
+}
+
+// Binary files may also begin with "solid", so one that holds as many triangles as its count
+// says is binary unless facets follow the keyword. Bytes after the last triangle are allowed.
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    match binary_size(bytes) {
+        Some(size) if size == bytes.len() => true,
+        Some(size) => size < bytes.len() && !(is_ascii_stl(bytes) && has_facet_keyword(bytes)),
+        None => false,
+    }
+}
+
+// The size of a binary file with the triangle count in its header, `None` without a header or
+// when the size does not fit in a `usize`
+fn binary_size(bytes: &[u8]) -> Option<usize> {
+    let count = u32::from_le_bytes(bytes.get(80..HEADER_SIZE)?.try_into().unwrap()) as usize;
+    count.checked_mul(TRIANGLE_SIZE)?.checked_add(HEADER_SIZE)
+}
+
+fn is_ascii_stl(bytes: &[u8]) -> bool {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    bytes[start..].starts_with(b"solid")
+}
+
+// ASCII files name their first facet near the start
+fn has_facet_keyword(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(1024)]
+        .windows(5)
+        .any(|w| w == b"facet")
+}
+
+/// Reads an ASCII or binary STL file.
+///
+/// Coincident vertices are welded. Facet normals are used as given, except zero ones which are
+/// generated with `mode`.
+pub fn parse_stl(bytes: &[u8], mode: NormalMode) -> Result<ImportedMesh, ImportError> {
+    let mut warnings: Vec<String> = vec![];
+    let facets = if is_binary_stl(bytes) || !is_ascii_stl(bytes) {
+        parse_binary(bytes, &mut warnings)?
+    } else {
+        parse_ascii(&String::from_utf8_lossy(bytes))?
+    };
+
+    // Weld positions so generated normals can be smoothed across triangles
+    let mut positions: Vec<Pos> = vec![];
+    let mut position_lookup: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut corners: Vec<u32> = Vec::with_capacity(facets.len() * 3);
+    for facet in &facets {
+        for pos in facet.vertices {
+            let index = *position_lookup.entry(weld_key(pos)).or_insert_with(|| {
+                positions.push(pos);
+                positions.len() as u32 - 1
+            });
+            corners.push(index);
+        }
+    }
+
+    let missing_normals = facets.iter().any(|f| f.normal.is_none());
+    let generated = if missing_normals {
+        Some(corner_normals(&positions, &corners, mode))
+    } else {
+        None
+    };
+
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indices: Vec<u32> = Vec::with_capacity(corners.len());
+    let mut generated_normals: Vec<bool> = vec![];
+    let mut vertex_lookup: HashMap<(u32, [u32; 3], bool), u32> = HashMap::new();
+    for (i, position) in corners.iter().enumerate() {
+        let facet_normal = facets[i / 3].normal;
+        let normal = facet_normal.unwrap_or_else(|| generated.as_ref().unwrap()[i]);
+        let key = (*position, weld_key(normal), facet_normal.is_none());
+        let index = *vertex_lookup.entry(key).or_insert_with(|| {
+            generated_normals.push(facet_normal.is_none());
+            vertices.push(Vertex(
+                positions[*position as usize],
+                normal,
+                [0.0, 0.0],
+                WHITE,
+            ));
+            vertices.len() as u32 - 1
+        });
+        indices.push(index);
+    }
+
+    Ok(ImportedMesh {
+        vertices,
+        indices,
+        groups: vec![],
+        generated_normals: if missing_normals {
+            generated_normals
+        } else {
+            vec![]
+        },
+        vertex_colors: false,
+        warnings,
+    })
+}
+
+/// Writes the triangles of `meshes` as one binary or ASCII STL solid.
+///
+/// Facet normals are computed from the triangles; point clouds have none and are left out.
+pub fn write_stl(meshes: &[WorldMesh], binary: bool) -> Vec<u8> {
+    let facets: Vec<Facet> = meshes
+        .iter()
+        .flat_map(|m| {
+            m.mesh.indices.chunks_exact(3).map(|triangle| {
+                let vertices = [0, 1, 2].map(|i| m.mesh.vertices[triangle[i] as usize].0);
+                let [a, b, c] = vertices.map(Vector3::from);
+                Facet {
+                    normal: facet_normal((b - a).cross(c - a).into()),
+                    vertices,
+                }
+            })
+        })
+        .collect();
+
+    if binary {
+        let mut bytes = vec![0u8; 80];
+        let header = b"binary STL exported by rust-renderer";
+        bytes[..header.len()].copy_from_slice(header);
+        bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for facet in &facets {
+            let normal = facet.normal.unwrap_or_default();
+            for v in normal.iter().chain(facet.vertices.iter().flatten()) {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        return bytes;
+    }
+
+    let mut stl = String::from("solid model\n");
+    for facet in &facets {
+        let [x, y, z] = facet.normal.unwrap_or_default();
+        writeln!(stl, "  facet normal {} {} {}", x, y, z).unwrap();
+        writeln!(stl, "    outer loop").unwrap();
+        for [x, y, z] in facet.vertices {
+            writeln!(stl, "      vertex {} {} {}", x, y, z).unwrap();
+        }
+        writeln!(stl, "    endloop").unwrap();
+        writeln!(stl, "  endfacet").unwrap();
+    }
+    stl.push_str("endsolid model\n");
+    stl.into_bytes()
+}
+
+struct Facet {
+    // `None` when the file gives a zero normal
+    normal: Option<Norm>,
+    vertices: [Pos; 3],
+}
+
+fn facet_normal(n: Norm) -> Option<Norm> {
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 && length.is_finite() {
+        Some(n.map(|x| x / length))
+    } else {
+        None
+    }
+}
+
+fn parse_binary(bytes: &[u8], warnings: &mut Vec<String>) -> Result<Vec<Facet>, ImportError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(ImportError::new("binary STL is shorter than its header"));
+    }
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    let expected = binary_size(bytes).unwrap_or(usize::MAX);
+    if bytes.len() < expected {
+        return Err(ImportError::new(format!(
+            "binary STL is truncated: {} triangles need {} bytes, found {}",
+            count,
+            expected,
+            bytes.len()
+        )));
+    }
+    if bytes.len() > expected {
+        warnings.push(format!(
+            "{} bytes after the last triangle were ignored",
+            bytes.len() - expected
+        ));
+    }
+
+    let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let vector = |offset: usize| [float(offset), float(offset + 4), float(offset + 8)];
+    Ok((0..count)
+        .map(|i| {
+            let offset = HEADER_SIZE + i * TRIANGLE_SIZE;
+            Facet {
+                normal: facet_normal(vector(offset)),
+                vertices: [
+                    vector(offset + 12),
+                    vector(offset + 24),
+                    vector(offset + 36),
+                ],
+            }
+        })
+        .collect())
+}
+
+fn parse_ascii(content: &str) -> Result<Vec<Facet>, ImportError> {
+    let mut facets: Vec<Facet> = vec![];
+    let mut normal: Option<Norm> = None;
+    let mut loop_vertices: Vec<Pos> = vec![];
+    let mut in_facet = false;
+
+    for (line_index, line) in content.lines().enumerate() {
+        let line_number = line_index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let keyword = match tokens.first() {
+            Some(keyword) => *keyword,
+            None => continue,
+        };
+        let error = |reason: &str| ImportError::at_line(line_number, keyword, reason);
+        let vector = |args: &[&str]| -> Result<[f32; 3], ImportError> {
+            match args {
+                [x, y, z] => match (x.parse(), y.parse(), z.parse()) {
+                    (Ok(x), Ok(y), Ok(z)) => Ok([x, y, z]),
+                    _ => Err(error("expected three numbers")),
+                },
+                _ => Err(error("expected three numbers")),
+            }
+        };
+
+        match keyword {
+            "facet" => {
+                if in_facet {
+                    return Err(error("facet is missing its endfacet"));
+                }
+                if tokens.get(1) != Some(&"normal") {
+                    return Err(error("expected 'facet normal'"));
+                }
+                normal = facet_normal(vector(&tokens[2..])?);
+                loop_vertices.clear();
+                in_facet = true;
+            }
+            "vertex" => {
+                if !in_facet {
+                    return Err(error("vertex outside of a facet"));
+                }
+                loop_vertices.push(vector(&tokens[1..])?);
+            }
+            "endfacet" => {
+                if !in_facet {
+                    return Err(error("endfacet without a facet"));
+                }
+                if loop_vertices.len() != 3 {
+                    return Err(error(&format!(
+                        "facet has {} vertices, expected 3",
+                        loop_vertices.len()
+                    )));
+                }
+                facets.push(Facet {
+                    normal,
+                    vertices: [loop_vertices[0], loop_vertices[1], loop_vertices[2]],
+                });
+                in_facet = false;
+            }
+            "solid" | "endsolid" | "outer" | "endloop" => {}
+            _ => return Err(error("unknown keyword")),
+        }
+    }
+    if in_facet {
+        return Err(ImportError::new("file ends inside a facet"));
+    }
+
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::import::parse_model;
+
+    const ASCII_TETRAHEDRON: &str = "solid tetra
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 -1 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 0 1
+    endloop
+  endfacet
+  facet normal -1 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 0 1
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 1 0 0
+      vertex 0 1 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid tetra
+";
+
+    fn binary_stl(header: &[u8], facets: &[[[f32; 3]; 4]]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 80];
+        bytes[..header.len()].copy_from_slice(header);
+        bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for facet in facets {
+            for v in facet.iter().flatten() {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_welds_and_generates_zero_normals() {
+        let mesh = parse_stl(ASCII_TETRAHEDRON.as_bytes(), NormalMode::default()).unwrap();
+        assert_eq!(mesh.indices.len(), 12);
+        assert!(mesh.generated_normals.contains(&true));
+
+        // The sloped side had a zero normal and got a generated one facing outward
+        let normal = mesh.vertices[mesh.indices[9] as usize].1;
+        assert!(normal.iter().all(|n| *n > 0.0));
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        let facet = [
+            [0.0, 0.0, 2.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let mirrored = [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ];
+        let bytes = binary_stl(b"solid exported by a CAD tool", &[facet, mirrored]);
+        assert!(is_binary_stl(&bytes));
+
+        let mesh = parse_stl(&bytes, NormalMode::default()).unwrap();
+        assert!(mesh.generated_normals.is_empty());
+        assert_eq!(mesh.indices.len(), 6);
+        // The shared edge is welded and the facet normals are normalized
+        assert_eq!(mesh.vertices.len(), 4);
+        let normal = mesh.vertices[0].1;
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn padded_binary_through_parse_model() {
+        let facet = [
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let mut bytes = binary_stl(b"solid padded", &[facet]);
+        bytes.extend_from_slice(&[0, 0]);
+
+        let scene = parse_model(&bytes, NormalMode::default(), &Assets::new()).unwrap();
+        let mesh = scene.nodes[0].mesh.as_ref().unwrap();
+        assert_eq!(mesh.indices.len(), 3);
+        assert_eq!(
+            mesh.warnings,
+            ["2 bytes after the last triangle were ignored"]
+        );
+    }
+
+    #[test]
+    fn reports_truncated_and_malformed_files() {
+        let mut bytes = binary_stl(b"part", &[[[0.0; 3]; 4]]);
+        bytes.truncate(100);
+        assert!(parse_stl(&bytes, NormalMode::default())
+            .unwrap_err()
+            .reason
+            .contains("truncated"));
+
+        let error = parse_stl(
+            b"solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 x 0\n",
+            NormalMode::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.line, Some(4));
+        assert_eq!(error.token.as_deref(), Some("vertex"));
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let tetrahedron = parse_stl(ASCII_TETRAHEDRON.as_bytes(), NormalMode::default()).unwrap();
+        let meshes = [WorldMesh {
+            name: "tetra".to_string(),
+            mesh: tetrahedron,
+        }];
+        for binary in [false, true] {
+            let bytes = write_stl(&meshes, binary);
+            assert_eq!(is_binary_stl(&bytes), binary);
+            let mesh = parse_stl(&bytes, NormalMode::default()).unwrap();
+            // Every facet normal is written, so none need generating
+            assert!(mesh.generated_normals.is_empty());
+            assert_eq!(mesh.indices.len(), 12);
+            assert_eq!(mesh.vertices.len(), 12);
+        }
+    }
+}
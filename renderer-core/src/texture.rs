@@ -0,0 +1,32 @@
+/// Decoded RGBA8 pixels, top row first.
+#[derive(Debug, Clone)]
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl TextureImage {
+    /// Decodes a PNG or JPEG file.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| format!("could not decode image: {}", e))?
+            .to_rgba8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        })
+    }
+
+    /// Encodes the pixels as a PNG file.
+    pub fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let image = image::RgbaImage::from_raw(self.width, self.height, self.pixels.clone())
+            .ok_or("image size does not match its pixels")?;
+        let mut png = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, image::ImageFormat::Png)
+            .map_err(|e| format!("could not encode image: {}", e))?;
+        Ok(png.into_inner())
+    }
+}
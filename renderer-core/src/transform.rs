@@ -0,0 +1,144 @@
+use cgmath::{Deg, Euler, InnerSpace, Matrix3, Matrix4, One, Quaternion, SquareMatrix, Vector3};
+
+/// Translation, rotation and scale, applied to a node in the order scale, rotate, translate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trs {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Trs {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Trs {
+    /// Splits `matrix` into a translation, rotation and scale.
+    ///
+    /// A mirroring matrix gets a negative X scale. Shear cannot be represented and is lost.
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let translation = matrix.w.truncate();
+        let mut columns = [
+            matrix.x.truncate(),
+            matrix.y.truncate(),
+            matrix.z.truncate(),
+        ];
+        let mut scale = Vector3::new(
+            columns[0].magnitude(),
+            columns[1].magnitude(),
+            columns[2].magnitude(),
+        );
+        if Matrix3::from_cols(columns[0], columns[1], columns[2]).determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        // A flattened axis leaves no rotation to recover
+        if scale.x * scale.y * scale.z == 0.0 {
+            return Self {
+                translation,
+                scale,
+                ..Self::default()
+            };
+        }
+        for (column, s) in columns.iter_mut().zip([scale.x, scale.y, scale.z]) {
+            *column /= s;
+        }
+        let rotation = Quaternion::from(Matrix3::from_cols(columns[0], columns[1], columns[2]));
+        Self {
+            translation,
+            rotation: rotation.normalize(),
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// The rotation as angles about X, then Y, then Z, in degrees.
+    pub fn euler_degrees(&self) -> [f32; 3] {
+        let euler = Euler::from(self.rotation);
+        [
+            Deg::from(euler.x).0,
+            Deg::from(euler.y).0,
+            Deg::from(euler.z).0,
+        ]
+    }
+
+    pub fn set_euler_degrees(&mut self, angles: [f32; 3]) {
+        self.rotation =
+            Quaternion::from(Euler::new(Deg(angles[0]), Deg(angles[1]), Deg(angles[2])));
+    }
+}
+
+/// The rotation of `matrix` with scale removed from each axis, for turning directions.
+pub fn axes(matrix: &Matrix4<f32>) -> [Vector3<f32>; 3] {
+    let normalize = |v: Vector3<f32>| {
+        if v.magnitude2() > 0.0 {
+            v.normalize()
+        } else {
+            v
+        }
+    };
+    let m = Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    );
+    [normalize(m.x), normalize(m.y), normalize(m.z)]
+}
+
+/// Turns a world space direction into the space of a node whose parent has `parent_world`.
+pub fn direction_to_parent(parent_world: &Matrix4<f32>, direction: Vector3<f32>) -> Vector3<f32> {
+    match parent_world.invert() {
+        Some(inverse) => (inverse * direction.extend(0.0)).truncate(),
+        None => direction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let a: &[f32; 16] = a.as_ref();
+        let b: &[f32; 16] = b.as_ref();
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn decomposes_a_composed_matrix() {
+        let mut trs = Trs {
+            translation: Vector3::new(1.0, -2.0, 3.0),
+            scale: Vector3::new(2.0, 0.5, 3.0),
+            ..Trs::default()
+        };
+        trs.set_euler_degrees([30.0, -45.0, 60.0]);
+        let matrix = trs.matrix();
+        assert_matrix_close(Trs::from_matrix(&matrix).matrix(), matrix);
+
+        let angles = trs.euler_degrees();
+        for (a, b) in angles.iter().zip([30.0, -45.0, 60.0]) {
+            assert!((a - b).abs() < 1e-3, "{:?}", angles);
+        }
+    }
+
+    #[test]
+    fn mirroring_becomes_negative_scale() {
+        let matrix = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+        let trs = Trs::from_matrix(&matrix);
+        assert!(trs.scale.x < 0.0);
+        assert_matrix_close(trs.matrix(), matrix);
+        assert_eq!(Trs::from_matrix(&Matrix4::identity()), Trs::default());
+    }
+}
@@ -0,0 +1,12 @@
+// vertex data type
+pub type Pos = [f32; 3];
+pub type Norm = [f32; 3];
+pub type Uv = [f32; 2];
+pub type Color = [f32; 3];
+
+/// Vertex color of geometry without per-vertex colors.
+pub const WHITE: Color = [1.0, 1.0, 1.0];
+
+#[repr(C, packed)]
+#[derive(Debug, Clone)]
+pub struct Vertex(pub Pos, pub Norm, pub Uv, pub Color);
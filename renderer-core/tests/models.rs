@@ -0,0 +1,93 @@
+//! Loads the sample models shipped with the repository.
+
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use renderer_core::assets::Assets;
+use renderer_core::bounds::BoundingBox;
+use renderer_core::export::{world_meshes, write_obj};
+use renderer_core::geometry::NormalMode;
+use renderer_core::import::{parse_model, ImportedMesh, ImportedNode};
+use renderer_core::obj::parse_obj;
+
+const SUZANNE: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../models/suzanne.obj"
+));
+const TORII: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../models/Japanese_Torii.obj"
+));
+
+fn assert_close(actual: Point3<f32>, expected: [f32; 3]) {
+    let distance = (actual - Point3::from(expected)).magnitude();
+    assert!(distance < 1e-4, "{:?} is not {:?}", actual, expected);
+}
+
+fn assert_unit_normals(mesh: &ImportedMesh) {
+    for v in &mesh.vertices {
+        let length = Vector3::from(v.1).magnitude();
+        assert!((length - 1.0).abs() < 1e-3, "normal of length {}", length);
+    }
+}
+
+#[test]
+fn loads_suzanne() {
+    let mesh = parse_obj(SUZANNE, NormalMode::default(), &Assets::new()).unwrap();
+    assert_eq!(mesh.indices.len(), 967 * 3);
+    assert!(mesh
+        .indices
+        .iter()
+        .all(|&i| (i as usize) < mesh.vertices.len()));
+    assert!(mesh.groups.iter().all(|g| g.material.is_none()));
+    assert_eq!(
+        mesh.warnings,
+        ["line 3: material library 'untitled.mtl' was not supplied"]
+    );
+    assert_unit_normals(&mesh);
+
+    let bounds = BoundingBox::from_vertices(&mesh.vertices).unwrap();
+    assert_close(bounds.min, [-1.367188, -0.984375, -0.851562]);
+    assert_close(bounds.max, [1.367188, 0.984375, 0.851562]);
+}
+
+#[test]
+fn loads_torii_with_its_material() {
+    let mut assets = Assets::new();
+    assets.insert("Torii.mtl", b"newmtl Torii\nKd 0.8 0.1 0.05\n".to_vec());
+    let scene = parse_model(TORII.as_bytes(), NormalMode::default(), &assets).unwrap();
+    assert!(scene.warnings.is_empty(), "{:?}", scene.warnings);
+    assert_eq!(scene.nodes.len(), 1);
+    let mesh = scene.nodes[0].mesh.as_ref().unwrap();
+    assert!(mesh.warnings.is_empty(), "{:?}", mesh.warnings);
+
+    // Triangles, quads and a few larger polygons, fanned into 2552 triangles
+    assert_eq!(mesh.indices.len(), 2552 * 3);
+    assert_eq!(mesh.groups.len(), 1);
+    let material = mesh.groups[0].material.as_ref().unwrap();
+    assert_eq!(material.name, "Torii");
+    assert_eq!(material.diffuse, [0.8, 0.1, 0.05]);
+    assert_unit_normals(mesh);
+
+    let bounds = BoundingBox::from_vertices(&mesh.vertices).unwrap();
+    assert_close(bounds.min, [-4.069813, 0.02, -0.45173]);
+    assert_close(bounds.max, [4.069813, 5.364122, 0.458841]);
+}
+
+#[test]
+fn torii_survives_an_obj_round_trip() {
+    let mesh = parse_obj(TORII, NormalMode::default(), &Assets::new()).unwrap();
+    let nodes = vec![ImportedNode {
+        name: "torii".to_string(),
+        transform: Matrix4::identity(),
+        mesh: Some(mesh.clone()),
+        children: vec![],
+    }];
+    let obj = write_obj(&world_meshes(&nodes), None);
+
+    let reloaded = parse_obj(&obj, NormalMode::default(), &Assets::new()).unwrap();
+    assert!(reloaded.warnings.is_empty(), "{:?}", reloaded.warnings);
+    assert_eq!(reloaded.indices.len(), mesh.indices.len());
+    let before = BoundingBox::from_vertices(&mesh.vertices).unwrap();
+    let after = BoundingBox::from_vertices(&reloaded.vertices).unwrap();
+    assert_close(after.min, before.min.into());
+    assert_close(after.max, before.max.into());
+}
@@ -0,0 +1,202 @@
+//! Compares software renders of the sample models with the images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write new reference images after an intended change to the
+//! drawing, and check them in with it.
+
+use cgmath::{Matrix4, SquareMatrix, Vector3};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use renderer_core::assets::Assets;
+use renderer_core::bounds::BoundingSphere;
+use renderer_core::camera::Camera;
+use renderer_core::export::world_meshes;
+use renderer_core::geometry::NormalMode;
+use renderer_core::import::{ImportedMesh, ImportedNode, MeshGroup};
+use renderer_core::material::Material;
+use renderer_core::obj::parse_obj;
+use renderer_core::render::{render, RenderOptions};
+use renderer_core::texture::TextureImage;
+use renderer_core::vertex::{Vertex, WHITE};
+
+const SUZANNE: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../models/suzanne.obj"
+));
+const TORII: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../models/Japanese_Torii.obj"
+));
+
+// Differences a change of compiler or platform may bring to the float math
+const CHANNEL_TOLERANCE: u8 = 8;
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+fn assert_matches_golden(name: &str, image: &TextureImage) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, image.encode_png().unwrap()).unwrap();
+        return;
+    }
+    let golden = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("{}: {}, run with UPDATE_GOLDEN=1", path.display(), e));
+    let golden = TextureImage::decode(&golden).unwrap();
+    assert_eq!(
+        (image.width, image.height),
+        (golden.width, golden.height),
+        "{}",
+        name
+    );
+
+    let different = image
+        .pixels
+        .chunks_exact(4)
+        .zip(golden.pixels.chunks_exact(4))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(*b)
+                .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let allowed = (MAX_DIFFERENT_PIXELS * (image.width * image.height) as f32) as usize;
+    if different > allowed {
+        let actual = path.with_extension("actual.png");
+        std::fs::write(&actual, image.encode_png().unwrap()).unwrap();
+        panic!(
+            "{}: {} pixels differ from the golden image, see {}",
+            name,
+            different,
+            actual.display()
+        );
+    }
+}
+
+fn node(mesh: ImportedMesh) -> ImportedNode {
+    ImportedNode {
+        name: String::new(),
+        transform: Matrix4::identity(),
+        mesh: Some(mesh),
+        children: vec![],
+    }
+}
+
+// The camera the web renderer ends up with after loading the nodes
+fn framed_camera(nodes: &[ImportedNode], options: &RenderOptions) -> Camera {
+    let mut camera = Camera::new(options.width as f32 / options.height as f32);
+    let vertices: Vec<Vertex> = world_meshes(nodes)
+        .into_iter()
+        .flat_map(|m| m.mesh.vertices)
+        .collect();
+    let sphere = BoundingSphere::from_vertices(&vertices).unwrap();
+    camera.frame(sphere.center, sphere.radius);
+    camera
+}
+
+#[test]
+fn suzanne_shaded() {
+    let mesh = parse_obj(SUZANNE, NormalMode::default(), &Assets::new()).unwrap();
+    let nodes = vec![node(mesh)];
+    let options = RenderOptions {
+        width: 160,
+        height: 120,
+        ..RenderOptions::default()
+    };
+    let camera = framed_camera(&nodes, &options);
+    let image = render(&nodes, &HashMap::new(), &camera, &options);
+    assert_matches_golden("suzanne.png", &image);
+}
+
+#[test]
+fn torii_wireframe_in_model_color() {
+    let mesh = parse_obj(TORII, NormalMode::default(), &Assets::new()).unwrap();
+    let nodes = vec![node(mesh)];
+    let options = RenderOptions {
+        width: 160,
+        height: 120,
+        color: [0.9, 0.2, 0.1, 1.0],
+        wireframe: true,
+        ..RenderOptions::default()
+    };
+    let mut camera = framed_camera(&nodes, &options);
+    camera.reset(0.0, 0.0);
+    let image = render(&nodes, &HashMap::new(), &camera, &options);
+    assert_matches_golden("torii_wireframe.png", &image);
+}
+
+#[test]
+fn textured_and_transparent_materials() {
+    // A checkered square, with a half transparent blue square in front of its right half
+    let vertex = |x: f32, y: f32, z: f32| Vertex([x, y, z], [0.0, 0.0, 1.0], [x, y], WHITE);
+    let mesh = ImportedMesh {
+        vertices: vec![
+            vertex(0.0, 0.0, 0.0),
+            vertex(1.0, 0.0, 0.0),
+            vertex(1.0, 1.0, 0.0),
+            vertex(0.0, 1.0, 0.0),
+            vertex(0.5, 0.25, 0.2),
+            vertex(1.25, 0.25, 0.2),
+            vertex(1.25, 0.75, 0.2),
+            vertex(0.5, 0.75, 0.2),
+        ],
+        // The transparent group comes first to check it is drawn last
+        indices: vec![4, 5, 6, 4, 6, 7, 0, 1, 2, 0, 2, 3],
+        groups: vec![
+            MeshGroup {
+                start: 0,
+                count: 6,
+                material: Some(Material {
+                    name: "glass".to_string(),
+                    diffuse: [0.1, 0.3, 1.0],
+                    opacity: 0.5,
+                    ..Material::default()
+                }),
+            },
+            MeshGroup {
+                start: 6,
+                count: 6,
+                material: Some(Material {
+                    name: "checker".to_string(),
+                    diffuse_map: Some("checker.png".to_string()),
+                    ..Material::default()
+                }),
+            },
+        ],
+        generated_normals: vec![],
+        vertex_colors: false,
+        warnings: vec![],
+    };
+    let mut pixels = vec![];
+    for y in 0..4 {
+        for x in 0..4 {
+            let light = (x + y) % 2 == 0;
+            pixels.extend(if light {
+                [240, 220, 40, 255]
+            } else {
+                [40, 120, 40, 255]
+            });
+        }
+    }
+    let images = HashMap::from([(
+        "checker.png".to_string(),
+        TextureImage {
+            width: 4,
+            height: 4,
+            pixels,
+        },
+    )]);
+
+    let mut nodes = vec![node(mesh)];
+    nodes[0].transform = Matrix4::from_translation(Vector3::new(-0.5, 0.5, 0.0));
+    let options = RenderOptions {
+        width: 128,
+        height: 128,
+        ..RenderOptions::default()
+    };
+    let mut camera = framed_camera(&nodes, &options);
+    camera.reset(20.0, -20.0);
+    let image = render(&nodes, &images, &camera, &options);
+    assert_matches_golden("materials.png", &image);
+}
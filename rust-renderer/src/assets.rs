@@ -0,0 +1,18 @@
+use wasm_bindgen::prelude::*;
+
+use crate::global::ASSETS;
+
+/// Makes a file available to imports that refer to it by name, such as the material libraries
+/// and textures of an OBJ.
+///
+/// Files are matched by their base name, ignoring case and any directories in the reference.
+#[wasm_bindgen]
+pub fn add_asset(name: &str, bytes: &[u8]) {
+    ASSETS.with(|assets| assets.write().unwrap().insert(name, bytes.to_vec()));
+}
+
+/// Forgets every file passed to `add_asset`.
+#[wasm_bindgen]
+pub fn clear_assets() {
+    ASSETS.with(|assets| assets.write().unwrap().clear());
+}
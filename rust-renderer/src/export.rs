@@ -0,0 +1,129 @@
+use cgmath::{Matrix4, Vector4};
+use renderer_core::export::{
+    self, world_meshes, write_obj, MaterialLibrary, WorldMesh, MTL_FILE_NAME,
+};
+use renderer_core::gltf_export::write_glb;
+use renderer_core::import::{ImportedNode, ImportedScene};
+use renderer_core::mtl::write_mtl;
+use renderer_core::ply::write_ply;
+use renderer_core::stl::write_stl;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::global::SCENE;
+
+/// Writes the visible nodes of the scene as a Wavefront OBJ file.
+///
+/// Every object becomes an `o` block in world space. When the model has materials the file refers
+/// to them as `model.mtl`, which `export_mtl` provides.
+#[wasm_bindgen]
+pub fn export_obj() -> Result<String, JsValue> {
+    let meshes = loaded_meshes()?;
+    let mtl_file = (!MaterialLibrary::new(&meshes).is_empty()).then_some(MTL_FILE_NAME);
+    Ok(write_obj(&meshes, mtl_file))
+}
+
+/// Writes the material library for `export_obj`, or returns nothing when there are no materials.
+#[wasm_bindgen]
+pub fn export_mtl() -> Result<Option<String>, JsValue> {
+    let library = MaterialLibrary::new(&loaded_meshes()?);
+    Ok((!library.is_empty()).then(|| write_mtl(&library.materials)))
+}
+
+/// Writes the visible nodes of the scene as a binary or ASCII STL file, placed and scaled by
+/// `options`.
+#[wasm_bindgen]
+pub fn export_stl(binary: bool, options: Option<ExportOptions>) -> Result<Vec<u8>, JsValue> {
+    let meshes = options.unwrap_or_default().options.apply(loaded_meshes()?);
+    Ok(write_stl(&meshes, binary))
+}
+
+/// Writes the visible nodes of the scene as a binary (little endian) or ASCII PLY file, placed and
+/// scaled by `options`.
+#[wasm_bindgen]
+pub fn export_ply(binary: bool, options: Option<ExportOptions>) -> Result<Vec<u8>, JsValue> {
+    let meshes = options.unwrap_or_default().options.apply(loaded_meshes()?);
+    Ok(write_ply(&meshes, binary))
+}
+
+/// Writes the visible nodes of the scene as a binary glTF (GLB) file, with their hierarchy and
+/// transforms.
+///
+/// Materials are exported the way they are drawn, so a color picked with `set_model_color` and a
+/// texture set with `set_model_texture` are part of them. Textures are embedded as PNG.
+#[wasm_bindgen]
+pub fn export_glb() -> Result<Vec<u8>, JsValue> {
+    let scene = SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        let scene = scene
+            .as_ref()
+            .ok_or_else(|| JsValue::from_str("renderer is not initialized"))?;
+        let mut images = HashMap::new();
+        for (name, image) in scene.texture_images() {
+            let png = image.encode_png().map_err(|e| JsValue::from_str(&e))?;
+            images.insert(name, png);
+        }
+        Ok::<_, JsValue>(ImportedScene {
+            nodes: scene.to_imported(true),
+            images,
+            warnings: vec![],
+        })
+    })?;
+    if world_meshes(&scene.nodes).is_empty() {
+        return Err(JsValue::from_str("no model is loaded"));
+    }
+    Ok(write_glb(&scene))
+}
+
+/// Placement and units for the mesh exporters, applied on top of the model's own transforms.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    options: export::ExportOptions,
+}
+
+#[wasm_bindgen]
+impl ExportOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bakes a 4x4 column-major matrix into the exported positions.
+    pub fn set_transform(&mut self, matrix: &[f32]) -> Result<(), JsValue> {
+        let matrix: &[f32; 16] = matrix
+            .try_into()
+            .map_err(|_| JsValue::from_str("transform must have 16 elements"))?;
+        let column =
+            |i: usize| Vector4::new(matrix[i], matrix[i + 1], matrix[i + 2], matrix[i + 3]);
+        self.options.transform = Matrix4::from_cols(column(0), column(4), column(8), column(12));
+        Ok(())
+    }
+
+    /// Converts from the units the model was made in to the ones the file should use.
+    ///
+    /// Units are "mm", "cm", "m", "in" or "ft".
+    pub fn set_units(&mut self, model_units: &str, output_units: &str) -> Result<(), JsValue> {
+        Ok(self.options.set_units(model_units, output_units)?)
+    }
+}
+
+/// The visible nodes of the scene.
+pub fn loaded_nodes() -> Result<Vec<ImportedNode>, JsValue> {
+    SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        let scene = scene
+            .as_ref()
+            .ok_or_else(|| JsValue::from_str("renderer is not initialized"))?;
+        Ok(scene.to_imported(false))
+    })
+}
+
+// The meshes of the visible nodes, failing when there are none to export
+fn loaded_meshes() -> Result<Vec<WorldMesh>, JsValue> {
+    let meshes = world_meshes(&loaded_nodes()?);
+    if meshes.is_empty() {
+        return Err(JsValue::from_str("no model is loaded"));
+    }
+    Ok(meshes)
+}
@@ -0,0 +1,637 @@
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix,
+    Vector2, Vector3,
+};
+use renderer_core::camera::Camera;
+use renderer_core::transform::{axes, direction_to_parent, Trs};
+use renderer_core::vertex::{Color, Vertex};
+use std::f32::consts::TAU;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlUniformLocation};
+
+use crate::global::{CAMERA, GIZMO, SCENE};
+use crate::history::{record, Change, Edit};
+use crate::material::MaterialUniforms;
+use crate::model::ModelObject;
+use crate::scene::{NodeId, Scene};
+use crate::trigger_draw_event;
+
+// Length of the gizmo axes as a fraction of the view height
+const GIZMO_SCREEN_SIZE: f32 = 0.15;
+
+// How close in pixels the cursor has to be to a handle to grab it
+const HANDLE_TOLERANCE: f32 = 8.0;
+
+const RING_SEGMENTS: usize = 48;
+const ARROW_SEGMENTS: usize = 8;
+
+// Smallest factor one scale drag can shrink an axis by
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+const AXIS_COLORS: [Color; 3] = [[0.9, 0.2, 0.2], [0.2, 0.8, 0.2], [0.2, 0.4, 0.9]];
+const HIGHLIGHT_COLOR: Color = [1.0, 0.85, 0.1];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// Whether the gizmo follows the world axes or the selected node's own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoSpace {
+    Local,
+    World,
+}
+
+/// Where the gizmo is: centered on the node, along `axes`, `size` world units long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GizmoFrame {
+    pub origin: Point3<f32>,
+    pub axes: [Vector3<f32>; 3],
+    pub size: f32,
+}
+
+impl GizmoFrame {
+    /// The frame for a node placed by `world`.
+    ///
+    /// Scaling always uses the node's own axes, since scaling along any others would shear it.
+    pub fn new(world: &Matrix4<f32>, mode: GizmoMode, space: GizmoSpace, camera: &Camera) -> Self {
+        let origin = Point3::from_vec(world.w.truncate());
+        let axes = if space == GizmoSpace::World && mode != GizmoMode::Scale {
+            [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+        } else {
+            axes(world)
+        };
+        Self {
+            origin,
+            axes,
+            size: camera.view_height_at(origin) * GIZMO_SCREEN_SIZE,
+        }
+    }
+
+    // Places the unit gizmo geometry
+    fn matrix(&self) -> Matrix4<f32> {
+        let [x, y, z] = self.axes.map(|axis| axis * self.size);
+        Matrix4::from_translation(self.origin.to_vec()) * Matrix4::from(Matrix3::from_cols(x, y, z))
+    }
+
+    // Points along the handle of `axis`, in world space
+    fn handle(&self, mode: GizmoMode, axis: usize) -> Vec<Point3<f32>> {
+        let d = self.axes[axis] * self.size;
+        match mode {
+            GizmoMode::Translate | GizmoMode::Scale => vec![self.origin, self.origin + d],
+            GizmoMode::Rotate => {
+                let u = self.axes[(axis + 1) % 3] * self.size;
+                let v = self.axes[(axis + 2) % 3] * self.size;
+                (0..=RING_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f32 / RING_SEGMENTS as f32 * TAU;
+                        self.origin + u * angle.cos() + v * angle.sin()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The handle closest to `mouse`, if any is within reach.
+    ///
+    /// `mouse` is in pixels from the top left of a `viewport` sized view.
+    pub fn hit(
+        &self,
+        mode: GizmoMode,
+        camera: &Camera,
+        mouse: Vector2<f32>,
+        viewport: Vector2<f32>,
+    ) -> Option<usize> {
+        let view_projection = camera.projection_matrix() * camera.view();
+        let to_screen = |p: Point3<f32>| {
+            let clip = view_projection * p.to_homogeneous();
+            (clip.w > 0.0).then(|| {
+                Vector2::new(
+                    (clip.x / clip.w + 1.0) / 2.0 * viewport.x,
+                    (1.0 - clip.y / clip.w) / 2.0 * viewport.y,
+                )
+            })
+        };
+        (0..3)
+            .filter_map(|axis| {
+                let points: Vec<Vector2<f32>> = self
+                    .handle(mode, axis)
+                    .into_iter()
+                    .map(to_screen)
+                    .collect::<Option<_>>()?;
+                let distance = points
+                    .windows(2)
+                    .map(|s| segment_distance(mouse, s[0], s[1]))
+                    .fold(f32::INFINITY, f32::min);
+                Some((axis, distance))
+            })
+            .filter(|(_, distance)| *distance <= HANDLE_TOLERANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(axis, _)| axis)
+    }
+
+    /// Where the ray from the viewer meets the handle of `axis`: the closest point on the axis for
+    /// moving and scaling, or on the plane of the ring for rotating. `None` when the ray runs
+    /// along the axis or the plane.
+    pub fn grab_point(
+        &self,
+        mode: GizmoMode,
+        axis: usize,
+        ray: (Point3<f32>, Vector3<f32>),
+    ) -> Option<Point3<f32>> {
+        let (start, direction) = ray;
+        let d = self.axes[axis];
+        let w = self.origin - start;
+        match mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let b = d.dot(direction);
+                let denominator = direction.magnitude2() * d.magnitude2() - b * b;
+                if denominator.abs() < 1e-6 {
+                    return None;
+                }
+                let t = (b * w.dot(direction) - direction.magnitude2() * w.dot(d)) / denominator;
+                Some(self.origin + d * t)
+            }
+            GizmoMode::Rotate => {
+                let facing = d.dot(direction);
+                if facing.abs() < 1e-6 {
+                    return None;
+                }
+                Some(start + direction * (d.dot(w) / facing))
+            }
+        }
+    }
+}
+
+// Distance from `p` to the segment from `a` to `b`
+fn segment_distance(p: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let ab = b - a;
+    let t = if ab.magnitude2() > 0.0 {
+        ((p - a).dot(ab) / ab.magnitude2()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t - p).magnitude()
+}
+
+/// A handle being dragged, with what it started from.
+#[derive(Debug, Clone)]
+pub struct GizmoDrag {
+    pub node: NodeId,
+    pub mode: GizmoMode,
+    pub axis: usize,
+    pub frame: GizmoFrame,
+    /// World transform of the node's parent, for turning world space changes into local ones.
+    pub parent_world: Matrix4<f32>,
+    pub start: Trs,
+    /// Where the handle was grabbed, see `GizmoFrame::grab_point`.
+    pub grab: Point3<f32>,
+}
+
+impl GizmoDrag {
+    /// The node's transform with the handle moved from `grab` to `point`.
+    pub fn transform_to(&self, point: Point3<f32>) -> Trs {
+        let d = self.frame.axes[self.axis];
+        let mut trs = self.start;
+        match self.mode {
+            GizmoMode::Translate => {
+                let delta = d * d.dot(point - self.grab);
+                trs.translation += direction_to_parent(&self.parent_world, delta);
+            }
+            GizmoMode::Rotate => {
+                let from = self.grab - self.frame.origin;
+                let to = point - self.frame.origin;
+                let angle = d.dot(from.cross(to)).atan2(from.dot(to));
+                let axis = direction_to_parent(&self.parent_world, d);
+                if axis.magnitude2() > 0.0 {
+                    let turn = Quaternion::from_axis_angle(axis.normalize(), Rad(angle));
+                    trs.rotation = (turn * self.start.rotation).normalize();
+                }
+            }
+            GizmoMode::Scale => {
+                let from = d.dot(self.grab - self.frame.origin);
+                let to = d.dot(point - self.frame.origin);
+                if from.abs() > f32::EPSILON {
+                    trs.scale[self.axis] *= (to / from).max(MIN_SCALE_FACTOR);
+                }
+            }
+        }
+        trs
+    }
+}
+
+/// The transform gizmo drawn on the selected node, and the drag in progress.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    hovered: Option<usize>,
+    drag: Option<GizmoDrag>,
+    lines: Option<ModelObject>,
+    // What `lines` was last built for
+    built: Option<(GizmoMode, Option<usize>)>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            hovered: None,
+            drag: None,
+            lines: None,
+            built: None,
+        }
+    }
+}
+
+impl Gizmo {
+    // The selected node and the frame of its gizmo
+    fn frame(&self, scene: &Scene, camera: &Camera) -> Option<(NodeId, GizmoFrame)> {
+        let id = scene.selected()?;
+        let world = scene.world_transform(id).ok()?;
+        Some((id, GizmoFrame::new(&world, self.mode, self.space, camera)))
+    }
+
+    /// Highlights the handle under the cursor, returning whether that changed.
+    pub fn hover(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        mouse: Vector2<f32>,
+        viewport: Vector2<f32>,
+    ) -> bool {
+        let hovered = self
+            .frame(scene, camera)
+            .and_then(|(_, frame)| frame.hit(self.mode, camera, mouse, viewport));
+        let changed = hovered != self.hovered;
+        self.hovered = hovered;
+        changed
+    }
+
+    /// Starts dragging the handle under the cursor, returning false when there is none.
+    pub fn begin_drag(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        mouse: Vector2<f32>,
+        viewport: Vector2<f32>,
+    ) -> bool {
+        let Some((node, frame)) = self.frame(scene, camera) else {
+            return false;
+        };
+        let Some(axis) = frame.hit(self.mode, camera, mouse, viewport) else {
+            return false;
+        };
+        let ray = camera.ray(ndc(mouse.x, viewport.x), -ndc(mouse.y, viewport.y));
+        let (Some(grab), Ok(node_ref)) = (frame.grab_point(self.mode, axis, ray), scene.get(node))
+        else {
+            return false;
+        };
+        let parent_world = node_ref
+            .parent
+            .and_then(|parent| scene.world_transform(parent).ok())
+            .unwrap_or_else(Matrix4::identity);
+        self.hovered = Some(axis);
+        self.drag = Some(GizmoDrag {
+            node,
+            mode: self.mode,
+            axis,
+            frame,
+            parent_world,
+            start: node_ref.transform,
+            grab,
+        });
+        true
+    }
+
+    /// Moves the dragged handle to follow the cursor.
+    pub fn drag(
+        &mut self,
+        scene: &mut Scene,
+        camera: &Camera,
+        mouse: Vector2<f32>,
+        viewport: Vector2<f32>,
+    ) {
+        let Some(ref drag) = self.drag else {
+            return;
+        };
+        let ray = camera.ray(ndc(mouse.x, viewport.x), -ndc(mouse.y, viewport.y));
+        if let Some(point) = drag.frame.grab_point(drag.mode, drag.axis, ray) {
+            if let Ok(node) = scene.get_mut(drag.node) {
+                node.transform = drag.transform_to(point);
+            }
+        }
+    }
+
+    /// Finishes the drag, returning it.
+    pub fn end_drag(&mut self) -> Option<GizmoDrag> {
+        self.drag.take()
+    }
+
+    /// Makes new GL objects for the handles after the context was lost and restored.
+    pub fn restore(&mut self) {
+        if let Some(ref mut lines) = self.lines {
+            lines.restore();
+        }
+    }
+
+    /// Draws the gizmo over everything else.
+    pub fn draw(
+        &mut self,
+        gl: &GL,
+        scene: &Scene,
+        camera: &Camera,
+        uniforms: &MaterialUniforms,
+        model_loc: &WebGlUniformLocation,
+    ) {
+        let Some((_, frame)) = self.frame(scene, camera) else {
+            return;
+        };
+        let key = (self.mode, self.hovered);
+        let lines = self.lines.get_or_insert_with(|| {
+            let mut lines = ModelObject::new(gl.clone());
+            lines.set_vertex_colors(true);
+            lines
+        });
+        if self.built != Some(key) {
+            let (vertices, indices) = gizmo_lines(self.mode, self.hovered);
+            lines.update_lines(vertices, indices);
+            self.built = Some(key);
+        }
+        gl.clear(GL::DEPTH_BUFFER_BIT);
+        lines.draw(uniforms, model_loc, &frame.matrix());
+    }
+}
+
+// Maps a position in pixels across a `size` pixel view to -1..1
+fn ndc(position: f32, size: f32) -> f32 {
+    2.0 * position / size.max(1.0) - 1.0
+}
+
+/// Line geometry of the gizmo along unit X, Y and Z, drawn in the axis colors, with the
+/// `highlighted` axis in yellow. Normals are left zero so the lines are not lit.
+pub fn gizmo_lines(mode: GizmoMode, highlighted: Option<usize>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for (axis, axis_color) in AXIS_COLORS.into_iter().enumerate() {
+        let color = if highlighted == Some(axis) {
+            HIGHLIGHT_COLOR
+        } else {
+            axis_color
+        };
+        let d = unit(axis);
+        let u = unit((axis + 1) % 3);
+        let v = unit((axis + 2) % 3);
+        let mut line = |a: Vector3<f32>, b: Vector3<f32>| {
+            let first = vertices.len() as u32;
+            vertices.push(Vertex(a.into(), [0.0; 3], [0.0; 2], color));
+            vertices.push(Vertex(b.into(), [0.0; 3], [0.0; 2], color));
+            indices.extend_from_slice(&[first, first + 1]);
+        };
+        let circle = |i: usize, segments: usize| {
+            let angle = i as f32 / segments as f32 * TAU;
+            u * angle.cos() + v * angle.sin()
+        };
+        match mode {
+            GizmoMode::Translate => {
+                line(Vector3::new(0.0, 0.0, 0.0), d);
+                // Arrow head: a cone of lines from the tip
+                for i in 0..ARROW_SEGMENTS {
+                    let base = d * 0.8 + circle(i, ARROW_SEGMENTS) * 0.06;
+                    let next = d * 0.8 + circle(i + 1, ARROW_SEGMENTS) * 0.06;
+                    line(d, base);
+                    line(base, next);
+                }
+            }
+            GizmoMode::Rotate => {
+                for i in 0..RING_SEGMENTS {
+                    line(circle(i, RING_SEGMENTS), circle(i + 1, RING_SEGMENTS));
+                }
+            }
+            GizmoMode::Scale => {
+                line(Vector3::new(0.0, 0.0, 0.0), d * 0.95);
+                // A cube at the end of the axis
+                let corner = |i: usize| {
+                    let sign = |bit: usize| if i & bit == 0 { -0.05 } else { 0.05 };
+                    d * (0.95 + sign(1)) + u * sign(2) + v * sign(4)
+                };
+                for i in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if i & bit == 0 {
+                            line(corner(i), corner(i | bit));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+fn unit(axis: usize) -> Vector3<f32> {
+    let mut v = Vector3::new(0.0, 0.0, 0.0);
+    v[axis] = 1.0;
+    v
+}
+
+/// Mouse input from `input.rs`, in pixels over a `viewport` sized canvas. Returns whether a
+/// handle was grabbed.
+pub fn gizmo_mouse_down(mouse: Vector2<f32>, viewport: Vector2<f32>) -> bool {
+    CAMERA.with(|camera| {
+        SCENE.with(|scene| {
+            let scene = scene.read().unwrap();
+            let Some(scene) = scene.as_ref() else {
+                return false;
+            };
+            GIZMO.with(|gizmo| {
+                gizmo
+                    .write()
+                    .unwrap()
+                    .begin_drag(scene, &camera.read().unwrap(), mouse, viewport)
+            })
+        })
+    })
+}
+
+/// Drags the grabbed handle, or highlights the one under the cursor. Returns whether the view
+/// needs redrawing.
+pub fn gizmo_mouse_move(mouse: Vector2<f32>, viewport: Vector2<f32>, dragging: bool) -> bool {
+    CAMERA.with(|camera| {
+        SCENE.with(|scene| {
+            let mut scene = scene.write().unwrap();
+            let Some(scene) = scene.as_mut() else {
+                return false;
+            };
+            let camera = camera.read().unwrap();
+            GIZMO.with(|gizmo| {
+                let mut gizmo = gizmo.write().unwrap();
+                if dragging {
+                    gizmo.drag(scene, &camera, mouse, viewport);
+                    true
+                } else {
+                    gizmo.hover(scene, &camera, mouse, viewport)
+                }
+            })
+        })
+    })
+}
+
+/// Ends the drag, recording it as one edit.
+pub fn gizmo_mouse_up() {
+    let Some(drag) = GIZMO.with(|gizmo| gizmo.write().unwrap().end_drag()) else {
+        return;
+    };
+    let after = SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        Some(scene.as_ref()?.get(drag.node).ok()?.transform)
+    });
+    if let Some(after) = after {
+        let change = Change {
+            before: drag.start,
+            after,
+        };
+        record(Edit::Transform(drag.node, change), false);
+    }
+}
+
+/// Picks what the gizmo does: "translate", "rotate" or "scale".
+#[wasm_bindgen]
+pub fn set_gizmo_mode(mode: &str) -> Result<(), JsValue> {
+    let mode = match mode {
+        "translate" => GizmoMode::Translate,
+        "rotate" => GizmoMode::Rotate,
+        "scale" => GizmoMode::Scale,
+        _ => return Err(JsValue::from_str(&format!("unknown gizmo mode '{}'", mode))),
+    };
+    GIZMO.with(|gizmo| gizmo.write().unwrap().mode = mode);
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+    Ok(())
+}
+
+/// Aligns the gizmo with the "world" axes or the selected node's "local" ones.
+#[wasm_bindgen]
+pub fn set_gizmo_space(space: &str) -> Result<(), JsValue> {
+    let space = match space {
+        "local" => GizmoSpace::Local,
+        "world" => GizmoSpace::World,
+        _ => {
+            return Err(JsValue::from_str(&format!(
+                "unknown gizmo space '{}'",
+                space
+            )))
+        }
+    };
+    GIZMO.with(|gizmo| gizmo.write().unwrap().space = space);
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    fn drag(mode: GizmoMode, frame: GizmoFrame, axis: usize, grab: Point3<f32>) -> GizmoDrag {
+        GizmoDrag {
+            node: 1,
+            mode,
+            axis,
+            frame,
+            parent_world: Matrix4::from_scale(2.0),
+            start: Trs::default(),
+            grab,
+        }
+    }
+
+    #[test]
+    fn handles_are_hit_on_screen() {
+        let mut camera = Camera::new(1.0);
+        camera.reset(0.0, 0.0);
+        let frame = GizmoFrame::new(
+            &Matrix4::identity(),
+            GizmoMode::Translate,
+            GizmoSpace::World,
+            &camera,
+        );
+        let viewport = Vector2::new(400.0, 400.0);
+        // Seen from the front the X axis runs right from the center and Y runs up
+        let x = frame.hit(
+            GizmoMode::Translate,
+            &camera,
+            Vector2::new(240.0, 202.0),
+            viewport,
+        );
+        assert_eq!(x, Some(0));
+        let y = frame.hit(
+            GizmoMode::Translate,
+            &camera,
+            Vector2::new(201.0, 160.0),
+            viewport,
+        );
+        assert_eq!(y, Some(1));
+        let none = frame.hit(
+            GizmoMode::Translate,
+            &camera,
+            Vector2::new(300.0, 300.0),
+            viewport,
+        );
+        assert_eq!(none, None);
+    }
+
+    #[test]
+    fn dragging_moves_rotates_and_scales_in_parent_space() {
+        let frame = GizmoFrame {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            axes: [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()],
+            size: 1.0,
+        };
+
+        // A ray straight down onto the X axis at x = 3
+        let ray = (Point3::new(3.0, 5.0, 0.0), -Vector3::unit_y());
+        let point = frame.grab_point(GizmoMode::Translate, 0, ray).unwrap();
+        assert_close(point.x, 3.0);
+        let moved = drag(GizmoMode::Translate, frame, 0, Point3::new(1.0, 0.0, 0.0));
+        // The parent doubles everything, so two world units are one local unit
+        assert_close(moved.transform_to(point).translation.x, 1.0);
+
+        let scaled = drag(GizmoMode::Scale, frame, 0, Point3::new(1.0, 0.0, 0.0));
+        assert_close(scaled.transform_to(point).scale.x, 3.0);
+        assert_close(
+            scaled.transform_to(Point3::new(-1.0, 0.0, 0.0)).scale.x,
+            0.01,
+        );
+
+        let ray = (Point3::new(0.0, 1.0, 5.0), -Vector3::unit_z());
+        let point = frame.grab_point(GizmoMode::Rotate, 2, ray).unwrap();
+        let rotated = drag(GizmoMode::Rotate, frame, 2, Point3::new(1.0, 0.0, 0.0));
+        let expected = Quaternion::from_angle_z(Deg(90.0));
+        let rotation = rotated.transform_to(point).rotation;
+        assert_close(rotation.s, expected.s);
+        assert_close(rotation.v.z, expected.v.z);
+    }
+
+    #[test]
+    fn every_mode_has_lines_for_each_axis() {
+        for mode in [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale] {
+            let (vertices, indices) = gizmo_lines(mode, Some(1));
+            assert!(!indices.is_empty() && indices.len() % 2 == 0);
+            assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+            let highlighted = vertices
+                .iter()
+                .filter(|v| { v.3 } == HIGHLIGHT_COLOR)
+                .count();
+            assert_eq!(highlighted * 3, vertices.len());
+        }
+    }
+}
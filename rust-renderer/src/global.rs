@@ -1,16 +1,24 @@
-use crate::model::ModelObject;
+use crate::gizmo::Gizmo;
+use crate::gpu_memory::GpuMemory;
+use crate::history::{History, HISTORY_BUDGET};
+use crate::scene::Scene;
+use renderer_core::assets::Assets;
+use renderer_core::camera::Camera;
+use renderer_core::geometry::NormalMode;
 use std::sync::RwLock;
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext as GL, WebGlProgram};
 
-// vertex data type
-pub type Pos = [f32; 3];
-pub type Norm = [f32; 3];
-
-#[repr(C, packed)]
-#[derive(Debug, Clone)]
-pub struct Vertex(pub Pos, pub Norm);
-
-// Global storage for vertices and indices
+// Global storage for the scene, gizmo, edit history, camera, rendering context and shader
+// program, supplied asset files and GPU memory counts
 thread_local! {
-    pub static MODEL: RwLock<Option<ModelObject>> = RwLock::new(None);
-    pub static GRID: RwLock<Option<ModelObject>> = RwLock::new(None);
+    pub static SCENE: RwLock<Option<Scene>> = const { RwLock::new(None) };
+    pub static GIZMO: RwLock<Gizmo> = RwLock::new(Gizmo::default());
+    pub static HISTORY: RwLock<History> = const { RwLock::new(History::new(HISTORY_BUDGET)) };
+    pub static CAMERA: RwLock<Camera> = RwLock::new(Camera::new(1.0));
+    pub static CANVAS: RwLock<Option<HtmlCanvasElement>> = const { RwLock::new(None) };
+    pub static CONTEXT: RwLock<Option<GL>> = const { RwLock::new(None) };
+    pub static PROGRAM: RwLock<Option<WebGlProgram>> = const { RwLock::new(None) };
+    pub static NORMAL_MODE: RwLock<NormalMode> = RwLock::new(NormalMode::default());
+    pub static ASSETS: RwLock<Assets> = RwLock::new(Assets::new());
+    pub static GPU_MEMORY: RwLock<GpuMemory> = RwLock::new(GpuMemory::default());
 }
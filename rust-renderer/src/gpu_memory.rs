@@ -0,0 +1,30 @@
+use wasm_bindgen::prelude::*;
+
+use crate::global::GPU_MEMORY;
+
+/// WebGL objects the renderer holds and the bytes uploaded to them.
+///
+/// The counts go back down as objects are deleted, so numbers that keep growing while loading and
+/// removing models point to a leak.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpuMemory {
+    pub buffers: usize,
+    pub buffer_bytes: usize,
+    pub vertex_arrays: usize,
+    pub textures: usize,
+    /// Bytes of the full-size texture images, not counting mipmaps.
+    pub texture_bytes: usize,
+}
+
+/// The WebGL objects that are currently alive.
+#[wasm_bindgen]
+pub fn gpu_memory() -> GpuMemory {
+    GPU_MEMORY.with(|memory| *memory.read().unwrap())
+}
+
+/// Updates the counts as objects are created, filled and deleted.
+pub fn track(update: impl FnOnce(&mut GpuMemory)) {
+    // Objects dropped while the thread shuts down have nothing left to report to
+    let _ = GPU_MEMORY.try_with(|memory| update(&mut memory.write().unwrap()));
+}
@@ -0,0 +1,576 @@
+use js_sys::{Object, Reflect};
+use renderer_core::geometry::NormalMode;
+use renderer_core::transform::Trs;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{window, CustomEvent, CustomEventInit, EventTarget};
+
+use crate::global::{HISTORY, NORMAL_MODE, SCENE};
+use crate::scene::{DetachedNodes, NodeId, Placement, Scene};
+use crate::texture::Texture;
+use crate::trigger_draw_event;
+
+/// Bytes the history may hold on to before it forgets the oldest edits.
+pub const HISTORY_BUDGET: usize = 64 << 20;
+
+/// A value before and after an edit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+impl<T> Change<T> {
+    /// The value to go back to with `undo`, otherwise the one to go forward to.
+    pub fn get(&self, undo: bool) -> &T {
+        if undo {
+            &self.before
+        } else {
+            &self.after
+        }
+    }
+}
+
+/// An edit to the scene or its display settings, holding what it takes to revert and redo it.
+pub enum Edit {
+    /// The scene color and whether it overrides the materials' own.
+    Color(Change<([f32; 4], bool)>),
+    Wireframe(Change<bool>),
+    PointSize(Change<f32>),
+    Texture(Change<Option<Rc<Texture>>>),
+    NormalMode(Change<NormalMode>),
+    Transform(NodeId, Change<Trs>),
+    Visibility(NodeId, Change<bool>),
+    Rename(NodeId, Change<String>),
+    Reparent(NodeId, Change<Placement>),
+    /// A node added with its descendants, held here while the addition is undone.
+    Add(NodeId, Option<DetachedNodes>),
+    /// A node removed with its descendants, held here until the removal is undone.
+    Remove(NodeId, Option<DetachedNodes>),
+    /// Edits undone and redone together, under a label of their own.
+    Batch(&'static str, Vec<Edit>),
+}
+
+impl Edit {
+    /// Reverts the edit with `undo`, otherwise makes it again.
+    pub fn apply(&mut self, scene: &mut Scene, undo: bool) -> Result<(), String> {
+        match self {
+            Edit::Color(change) => {
+                let (color, color_override) = *change.get(undo);
+                scene.set_color(color);
+                scene.set_color_override(color_override);
+            }
+            Edit::Wireframe(change) => scene.set_draw_wireframe(*change.get(undo)),
+            Edit::PointSize(change) => scene.set_point_size(*change.get(undo)),
+            Edit::Texture(change) => scene.set_texture(change.get(undo).clone()),
+            Edit::NormalMode(change) => {
+                let mode = *change.get(undo);
+                NORMAL_MODE.with(|v| *v.write().unwrap() = mode);
+                scene.regenerate_normals(mode);
+            }
+            Edit::Transform(node, change) => scene.get_mut(*node)?.transform = *change.get(undo),
+            Edit::Visibility(node, change) => scene.set_visible(*node, *change.get(undo))?,
+            Edit::Rename(node, change) => scene.rename_node(*node, change.get(undo))?,
+            Edit::Reparent(node, change) => scene.place(*node, *change.get(undo))?,
+            // Undoing an addition and redoing a removal both take the nodes out
+            Edit::Add(node, detached) | Edit::Remove(node, detached) => match detached.take() {
+                // Nodes that cannot be put back stay with the edit
+                Some(nodes) => scene.attach(nodes).map_err(|(nodes, e)| {
+                    *detached = Some(nodes);
+                    e
+                })?,
+                None => *detached = Some(scene.detach(*node)?),
+            },
+            Edit::Batch(_, edits) => {
+                apply_batch(edits, undo, |edit, undo| edit.apply(scene, undo))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Short description for the Undo and Redo menu items.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Edit::Color(_) => "Color",
+            Edit::Wireframe(_) => "Wireframe",
+            Edit::PointSize(_) => "Point Size",
+            Edit::Texture(_) => "Texture",
+            Edit::NormalMode(_) => "Normals",
+            Edit::Transform(..) => "Transform",
+            Edit::Visibility(..) => "Visibility",
+            Edit::Rename(..) => "Rename",
+            Edit::Reparent(..) => "Reparent",
+            Edit::Add(..) => "Add",
+            Edit::Remove(..) => "Remove",
+            Edit::Batch(label, _) => label,
+        }
+    }
+
+    /// Bytes the edit holds on to, counting itself.
+    pub fn memory_size(&self) -> usize {
+        let texture_size = |texture: &Option<Rc<Texture>>| {
+            texture
+                .as_ref()
+                .and_then(|t| t.image.as_ref())
+                .map_or(0, |image| image.pixels.len())
+        };
+        std::mem::size_of::<Self>()
+            + match self {
+                Edit::Texture(change) => texture_size(&change.before) + texture_size(&change.after),
+                Edit::Rename(_, change) => change.before.len() + change.after.len(),
+                Edit::Add(_, detached) | Edit::Remove(_, detached) => {
+                    detached.as_ref().map_or(0, DetachedNodes::memory_size)
+                }
+                Edit::Batch(_, edits) => edits.iter().map(Edit::memory_size).sum(),
+                _ => 0,
+            }
+    }
+
+    // Makes new GL objects for the textures and nodes held by the edit after a lost context
+    fn restore(&mut self) {
+        match self {
+            Edit::Texture(change) => {
+                for texture in [&change.before, &change.after].into_iter().flatten() {
+                    unsafe { texture.restore() };
+                }
+            }
+            Edit::Add(_, Some(detached)) | Edit::Remove(_, Some(detached)) => detached.restore(),
+            Edit::Batch(_, edits) => edits.iter_mut().for_each(Edit::restore),
+            _ => {}
+        }
+    }
+
+    /// Whether the edit leaves everything as it was.
+    pub fn is_noop(&self) -> bool {
+        match self {
+            Edit::Color(change) => change.before == change.after,
+            Edit::Wireframe(change) | Edit::Visibility(_, change) => change.before == change.after,
+            Edit::PointSize(change) => change.before == change.after,
+            Edit::Texture(Change { before, after }) => match (before, after) {
+                (Some(before), Some(after)) => Rc::ptr_eq(before, after),
+                (before, after) => before.is_none() && after.is_none(),
+            },
+            Edit::NormalMode(change) => change.before == change.after,
+            Edit::Transform(_, change) => change.before == change.after,
+            Edit::Rename(_, change) => change.before == change.after,
+            Edit::Reparent(_, change) => change.before == change.after,
+            Edit::Add(..) | Edit::Remove(..) => false,
+            Edit::Batch(_, edits) => edits.iter().all(Edit::is_noop),
+        }
+    }
+
+    // Folds `next`, made right after this edit, into it, or hands it back when the two don't
+    // combine
+    fn merge(&mut self, next: Edit) -> Result<(), Edit> {
+        match (self, next) {
+            (Edit::Color(change), Edit::Color(next)) => change.after = next.after,
+            (Edit::PointSize(change), Edit::PointSize(next)) => change.after = next.after,
+            (Edit::Transform(node, change), Edit::Transform(next_node, next))
+                if *node == next_node =>
+            {
+                change.after = next.after
+            }
+            (Edit::Rename(node, change), Edit::Rename(next_node, next)) if *node == next_node => {
+                change.after = next.after
+            }
+            (_, next) => return Err(next),
+        }
+        Ok(())
+    }
+}
+
+// Applies `edits` with `apply`, the last one first when undoing. When one fails, those that
+// already ran are taken back in reverse, so a failed batch leaves everything as it was.
+fn apply_batch(
+    edits: &mut [Edit],
+    undo: bool,
+    mut apply: impl FnMut(&mut Edit, bool) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut order: Vec<&mut Edit> = edits.iter_mut().collect();
+    if undo {
+        order.reverse();
+    }
+    let mut failed = None;
+    for (i, edit) in order.iter_mut().enumerate() {
+        if let Err(e) = apply(edit, undo) {
+            failed = Some((i, e));
+            break;
+        }
+    }
+    let Some((done, e)) = failed else {
+        return Ok(());
+    };
+    for edit in order[..done].iter_mut().rev() {
+        // These have just been made the other way, so there is nothing to report if they fail
+        let _ = apply(edit, !undo);
+    }
+    Err(e)
+}
+
+/// Undo and redo stacks of edits, forgetting the oldest edits beyond a memory budget.
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    // Whether the newest edit still takes in continuous edits
+    open: bool,
+    budget: usize,
+}
+
+impl History {
+    pub const fn new(budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: false,
+            budget,
+        }
+    }
+
+    /// Records an edit that has already been made, dropping everything that could be redone.
+    ///
+    /// A `continuous` edit merges into the newest one when that was continuous too and nothing
+    /// has sealed it since, so that dragging a slider or a handle undoes in one step. Edits that
+    /// change nothing are left out.
+    pub fn record(&mut self, edit: Edit, continuous: bool) {
+        if edit.is_noop() {
+            return;
+        }
+        self.redo.clear();
+        let edit = match self.undo.back_mut() {
+            Some(last) if self.open && continuous => last.merge(edit).err(),
+            _ => Some(edit),
+        };
+        if let Some(edit) = edit {
+            self.undo.push_back(edit);
+        }
+        self.open = continuous;
+        self.trim();
+    }
+
+    /// Stops the newest edit from taking in further continuous edits.
+    pub fn seal(&mut self) {
+        self.open = false;
+    }
+
+    /// Reverts the newest edit with `revert` and moves it over to the redo stack. An edit that
+    /// fails to revert stays where it is.
+    pub fn undo(
+        &mut self,
+        revert: impl FnOnce(&mut Edit) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.open = false;
+        let Some(edit) = self.undo.back_mut() else {
+            return Ok(());
+        };
+        revert(edit)?;
+        self.redo.extend(self.undo.pop_back());
+        Ok(())
+    }
+
+    /// Makes the last undone edit again with `apply` and moves it back to the undo stack. An
+    /// edit that fails to apply stays where it is.
+    pub fn redo(
+        &mut self,
+        apply: impl FnOnce(&mut Edit) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.open = false;
+        let Some(edit) = self.redo.last_mut() else {
+            return Ok(());
+        };
+        apply(edit)?;
+        self.undo.extend(self.redo.pop());
+        self.trim();
+        Ok(())
+    }
+
+    /// Makes new GL objects for everything the edits hold after the context was lost and
+    /// restored, so undoing and redoing brings back what was there.
+    pub fn restore(&mut self) {
+        self.undo
+            .iter_mut()
+            .chain(self.redo.iter_mut())
+            .for_each(Edit::restore);
+    }
+
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(Edit::label)
+    }
+
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(Edit::label)
+    }
+
+    // Forgets the oldest edits until the rest fit the budget, always keeping the newest
+    fn trim(&mut self) {
+        let mut total: usize = self.undo.iter().map(Edit::memory_size).sum();
+        while total > self.budget && self.undo.len() > 1 {
+            total -= self.undo.pop_front().map_or(0, |edit| edit.memory_size());
+        }
+    }
+}
+
+/// Tells JavaScript what can be undone and redone, through a `history-change` event on the
+/// window. Its detail has `canUndo` and `canRedo`, and `undoLabel` and `redoLabel` naming the
+/// edits or null.
+pub fn trigger_history_event() {
+    let (undo_label, redo_label) = HISTORY.with(|history| {
+        let history = history.read().unwrap();
+        (history.undo_label(), history.redo_label())
+    });
+    let detail = Object::new();
+    for (key, value) in [
+        ("canUndo", JsValue::from_bool(undo_label.is_some())),
+        ("canRedo", JsValue::from_bool(redo_label.is_some())),
+        (
+            "undoLabel",
+            undo_label.map_or(JsValue::NULL, JsValue::from_str),
+        ),
+        (
+            "redoLabel",
+            redo_label.map_or(JsValue::NULL, JsValue::from_str),
+        ),
+    ] {
+        Reflect::set(&detail, &JsValue::from_str(key), &value).unwrap();
+    }
+    let init = CustomEventInit::new();
+    init.set_detail(&detail);
+
+    let window = window().unwrap();
+    let target: &EventTarget = window.as_ref();
+    let history_event = CustomEvent::new_with_event_init_dict("history-change", &init).unwrap();
+    target.dispatch_event(&history_event).unwrap();
+}
+
+/// Records an edit already made to the scene. The scene must not be locked.
+pub fn record(edit: Edit, continuous: bool) {
+    HISTORY.with(|history| history.write().unwrap().record(edit, continuous));
+    trigger_history_event();
+}
+
+/// Runs `f` on the scene and records the edit it returns, then redraws. See `History::record`
+/// for `continuous`.
+pub fn record_edit(
+    continuous: bool,
+    f: impl FnOnce(&mut Scene) -> Result<Edit, String>,
+) -> Result<(), JsValue> {
+    let edit = SCENE.with(|scene| {
+        let mut scene = scene.write().unwrap();
+        let scene = scene
+            .as_mut()
+            .ok_or_else(|| "renderer is not initialized".to_string())?;
+        f(scene)
+    });
+    let result = edit.map(|edit| record(edit, continuous));
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+
+    result.map_err(|e| JsValue::from_str(&e))
+}
+
+// Undoes or redoes one edit
+fn step(undo: bool) -> Result<(), JsValue> {
+    let result = SCENE.with(|scene| {
+        let mut scene = scene.write().unwrap();
+        let Some(scene) = scene.as_mut() else {
+            return Ok(());
+        };
+        HISTORY.with(|history| {
+            let mut history = history.write().unwrap();
+            let apply = |edit: &mut Edit| edit.apply(scene, undo);
+            if undo {
+                history.undo(apply)
+            } else {
+                history.redo(apply)
+            }
+        })
+    });
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+    trigger_history_event();
+
+    result.map_err(|e| JsValue::from_str(&e))
+}
+
+/// Reverts the newest edit, if there is one.
+#[wasm_bindgen]
+pub fn undo() -> Result<(), JsValue> {
+    step(true)
+}
+
+/// Makes the last undone edit again, if there is one.
+#[wasm_bindgen]
+pub fn redo() -> Result<(), JsValue> {
+    step(false)
+}
+
+#[wasm_bindgen]
+pub fn can_undo() -> bool {
+    HISTORY.with(|history| history.read().unwrap().undo_label().is_some())
+}
+
+#[wasm_bindgen]
+pub fn can_redo() -> bool {
+    HISTORY.with(|history| history.read().unwrap().redo_label().is_some())
+}
+
+/// Ends a continuous edit such as picking a color, so the next change undoes separately.
+#[wasm_bindgen]
+pub fn end_edit() {
+    HISTORY.with(|history| history.write().unwrap().seal());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(value: f32) -> Edit {
+        Edit::Color(Change {
+            before: ([value; 4], true),
+            after: ([value + 1.0; 4], true),
+        })
+    }
+
+    fn rename(node: NodeId, length: usize) -> Edit {
+        Edit::Rename(
+            node,
+            Change {
+                before: String::new(),
+                after: "x".repeat(length),
+            },
+        )
+    }
+
+    // Undoes the newest edit without a scene, returning what `f` makes of it
+    fn undo<T>(history: &mut History, f: impl FnOnce(&Edit) -> T) -> Option<T> {
+        let mut result = None;
+        history
+            .undo(|edit| {
+                result = Some(f(edit));
+                Ok(())
+            })
+            .unwrap();
+        result
+    }
+
+    fn redo(history: &mut History) -> Option<&'static str> {
+        let mut label = None;
+        history
+            .redo(|edit| {
+                label = Some(edit.label());
+                Ok(())
+            })
+            .unwrap();
+        label
+    }
+
+    #[test]
+    fn continuous_edits_merge_until_sealed() {
+        let mut history = History::new(HISTORY_BUDGET);
+        history.record(color(0.0), true);
+        history.record(color(1.0), true);
+        history.seal();
+        history.record(color(2.0), true);
+        // A different kind of edit does not merge, and ends the run of colors
+        history.record(rename(1, 1), true);
+        history.record(color(3.0), true);
+
+        assert_eq!(undo(&mut history, Edit::label), Some("Color"));
+        assert_eq!(undo(&mut history, Edit::label), Some("Rename"));
+        let colors = |edit: &Edit| match edit {
+            Edit::Color(change) => Some((change.before.0, change.after.0)),
+            _ => None,
+        };
+        assert_eq!(undo(&mut history, colors), Some(Some(([2.0; 4], [3.0; 4]))));
+        // The merged color edit
+        assert_eq!(undo(&mut history, colors), Some(Some(([0.0; 4], [2.0; 4]))));
+        assert_eq!(undo(&mut history, Edit::label), None);
+        assert_eq!(history.redo_label(), Some("Color"));
+
+        // Edits that change nothing are not recorded, and do not clear what can be redone
+        history.record(
+            Edit::Wireframe(Change {
+                before: true,
+                after: true,
+            }),
+            false,
+        );
+        assert_eq!(history.undo_label(), None);
+        assert_eq!(history.redo_label(), Some("Color"));
+    }
+
+    #[test]
+    fn recording_clears_redo_and_the_budget_forgets_the_oldest() {
+        let mut history = History::new(3000);
+        for node in 1..=3 {
+            history.record(rename(node, 1000), false);
+        }
+        // Only two of the renames fit
+        let node = |edit: &Edit| match edit {
+            Edit::Rename(node, _) => *node,
+            _ => 0,
+        };
+        assert_eq!(undo(&mut history, node), Some(3));
+        assert_eq!(undo(&mut history, node), Some(2));
+        assert_eq!(undo(&mut history, node), None);
+
+        assert_eq!(redo(&mut history), Some("Rename"));
+        history.record(color(0.0), false);
+        assert_eq!(redo(&mut history), None);
+
+        // The newest edit is kept even when it is over the budget alone
+        history.record(rename(4, 10_000), false);
+        assert_eq!(undo(&mut history, node), Some(4));
+        assert_eq!(undo(&mut history, node), None);
+    }
+
+    #[test]
+    fn failed_steps_leave_the_edit_in_place() {
+        let mut history = History::new(HISTORY_BUDGET);
+        history.record(rename(1, 1), false);
+        history.record(color(0.0), false);
+
+        let fail = |_: &mut Edit| Err("cannot".to_string());
+        assert_eq!(history.undo(fail), Err("cannot".to_string()));
+        assert_eq!(history.undo_label(), Some("Color"));
+        assert_eq!(history.redo_label(), None);
+
+        assert_eq!(undo(&mut history, Edit::label), Some("Color"));
+        assert_eq!(history.redo(fail), Err("cannot".to_string()));
+        assert_eq!(history.undo_label(), Some("Rename"));
+        assert_eq!(history.redo_label(), Some("Color"));
+    }
+
+    #[test]
+    fn failed_batches_take_back_what_ran() {
+        let hide = Edit::Visibility(
+            1,
+            Change {
+                before: true,
+                after: false,
+            },
+        );
+        let mut edits = vec![color(0.0), rename(1, 1), hide];
+        let mut log = vec![];
+        let result = apply_batch(&mut edits, false, |edit, undo| {
+            log.push((edit.label(), undo));
+            match edit {
+                Edit::Visibility(..) => Err("no node 1".to_string()),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(result, Err("no node 1".to_string()));
+        assert_eq!(
+            log,
+            [
+                ("Color", false),
+                ("Rename", false),
+                ("Visibility", false),
+                ("Rename", true),
+                ("Color", true),
+            ]
+        );
+    }
+}
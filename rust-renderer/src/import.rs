@@ -0,0 +1,141 @@
+use cgmath::{Matrix4, SquareMatrix};
+use renderer_core::import::{
+    all_nodes, decode_texture_maps, ImportError, ImportedMesh, ImportedNode, ImportedScene,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    frame_model,
+    global::{ASSETS, CONTEXT, SCENE},
+    history::{record, Edit},
+    scene::NodeId,
+    texture::Texture,
+};
+
+/// Summary of a successful import.
+#[wasm_bindgen(getter_with_clone)]
+pub struct ModelInfo {
+    /// The scene node holding everything that was imported.
+    pub node_id: NodeId,
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Problems that were skipped over while importing.
+    pub warnings: Vec<String>,
+}
+
+/// Adds `mesh` to the scene as a node called `name` and frames the scene.
+pub fn load_mesh(mesh: ImportedMesh, name: &str) -> Result<ModelInfo, ImportError> {
+    load_scene(ImportedScene::from_mesh(mesh), name, false)
+}
+
+/// Adds the nodes of `scene` to the scene and frames it.
+///
+/// A single top-level node is added as it is, several are grouped under a new node. Either way an
+/// unnamed top node is called `name`. With `replace` the nodes already in the scene are removed,
+/// in the same undo step as the import and only once it has succeeded.
+pub fn load_scene(
+    scene: ImportedScene,
+    name: &str,
+    replace: bool,
+) -> Result<ModelInfo, ImportError> {
+    let ImportedScene {
+        nodes,
+        images: embedded_images,
+        warnings: mut scene_warnings,
+    } = scene;
+    let meshes: Vec<&ImportedMesh> = all_nodes(&nodes)
+        .into_iter()
+        .filter_map(|n| n.mesh.as_ref())
+        .collect();
+    let triangle_count: usize = meshes.iter().map(|m| m.indices.len() / 3).sum();
+    let has_points = meshes
+        .iter()
+        .any(|m| m.indices.is_empty() && !m.vertices.is_empty());
+    if triangle_count == 0 && !has_points {
+        return Err(ImportError::new("file contains no faces"));
+    }
+    let vertex_count = meshes.iter().map(|m| m.vertices.len()).sum();
+    let mut warnings: Vec<String> = meshes
+        .iter()
+        .flat_map(|m| m.warnings.iter().cloned())
+        .collect();
+
+    let images = ASSETS.with(|assets| {
+        decode_texture_maps(
+            &meshes,
+            &embedded_images,
+            &assets.read().unwrap(),
+            &mut warnings,
+        )
+    });
+    warnings.append(&mut scene_warnings);
+
+    for warning in &warnings {
+        web_sys::console::warn_1(&warning.into());
+    }
+    let gl = CONTEXT
+        .with(|gl| gl.read().unwrap().clone())
+        .ok_or_else(|| ImportError::new("renderer is not initialized"))?;
+    let textures: HashMap<String, Rc<Texture>> = images
+        .into_iter()
+        .map(|(name, image)| unsafe {
+            let mut texture = Texture::new(&gl);
+            texture.set_image(&gl, image);
+            (name, Rc::new(texture))
+        })
+        .collect();
+
+    // Add the imported nodes under a single top-level node
+    let (node_id, edit) = SCENE.with(|v| {
+        let mut scene = v.write().unwrap();
+        let scene = scene
+            .as_mut()
+            .ok_or_else(|| ImportError::new("renderer is not initialized"))?;
+        let mut nodes = nodes;
+        let top = if nodes.len() == 1 {
+            let mut node = nodes.remove(0);
+            if node.name.is_empty() {
+                node.name = name.to_string();
+            }
+            node
+        } else {
+            ImportedNode {
+                name: name.to_string(),
+                transform: Matrix4::identity(),
+                mesh: None,
+                children: nodes,
+            }
+        };
+        let old_roots = if replace {
+            scene.roots().to_vec()
+        } else {
+            vec![]
+        };
+        let node_id = scene
+            .add_imported(None, top, &textures)
+            .map_err(ImportError::new)?;
+        if old_roots.is_empty() {
+            return Ok((node_id, Edit::Add(node_id, None)));
+        }
+        let mut edits = vec![Edit::Add(node_id, None)];
+        for id in old_roots {
+            let detached = scene.detach(id).map_err(ImportError::new)?;
+            edits.push(Edit::Remove(id, Some(detached)));
+        }
+        Ok((node_id, Edit::Batch("Open", edits)))
+    })?;
+
+    record(edit, false);
+
+    // Bring the new nodes into view, this also re-renders them
+    frame_model();
+
+    Ok(ModelInfo {
+        node_id,
+        vertex_count,
+        triangle_count,
+        warnings,
+    })
+}
@@ -1,71 +1,203 @@
+use cgmath::Vector2;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use web_sys::HtmlCanvasElement;
+use web_sys::{window, HtmlCanvasElement, MouseEvent};
 
+use crate::gizmo::{gizmo_mouse_down, gizmo_mouse_move, gizmo_mouse_up};
+use crate::global::CAMERA;
+use crate::pick::select_at;
+use crate::trigger_draw_event;
 
-pub fn enable_mouse_controls(
-    canvas: HtmlCanvasElement,
-) -> Result<(), JsValue> {
+// Degrees of rotation per pixel dragged
+const ORBIT_SENSITIVITY: f32 = 0.5;
+
+// Zoom factor applied per pixel of wheel scroll
+const DOLLY_SENSITIVITY: f32 = 1.0015;
+
+// Pixels the mouse can move between press and release for a click to still select
+const CLICK_TOLERANCE: f64 = 3.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DragMode {
+    Orbit,
+    Pan,
+    Gizmo,
+}
+
+// A left button press, which selects what is under it unless the mouse moves away first
+struct Click {
+    mouse: Vector2<f32>,
+    viewport: Vector2<f32>,
+    client: (f64, f64),
+}
+
+// The cursor position over the canvas and the canvas size, in CSS pixels
+fn canvas_position(canvas: &HtmlCanvasElement, event: &MouseEvent) -> (Vector2<f32>, Vector2<f32>) {
+    (
+        Vector2::new(event.offset_x() as f32, event.offset_y() as f32),
+        Vector2::new(canvas.client_width() as f32, canvas.client_height() as f32),
+    )
+}
+
+pub fn enable_mouse_controls(canvas: HtmlCanvasElement) -> Result<(), JsValue> {
     let canvas = Rc::new(canvas);
-    let is_dragging = Rc::new(RefCell::new(false));
+    let drag_mode: Rc<RefCell<Option<DragMode>>> = Rc::new(RefCell::new(None));
     let last_mouse_pos = Rc::new(RefCell::new((0.0, 0.0)));
+    let click: Rc<RefCell<Option<Click>>> = Rc::new(RefCell::new(None));
 
     // Clone references for the `mousedown` event
     let canvas_clone = canvas.clone();
-    let is_dragging_clone = is_dragging.clone();
+    let drag_mode_clone = drag_mode.clone();
     let last_mouse_pos_clone = last_mouse_pos.clone();
+    let click_clone = click.clone();
 
-    // Mouse down event
+    // Mouse down event: left button drags a gizmo handle or orbits, middle button (or shift + left)
+    // pans
     let on_mouse_down = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
-        *is_dragging_clone.borrow_mut() = true;
+        let mode = match event.button() {
+            0 if event.shift_key() => DragMode::Pan,
+            0 => {
+                let (mouse, viewport) = canvas_position(&canvas_clone, &event);
+                if gizmo_mouse_down(mouse, viewport) {
+                    DragMode::Gizmo
+                } else {
+                    *click_clone.borrow_mut() = Some(Click {
+                        mouse,
+                        viewport,
+                        client: (event.client_x() as f64, event.client_y() as f64),
+                    });
+                    DragMode::Orbit
+                }
+            }
+            1 => DragMode::Pan,
+            _ => return,
+        };
+
+        // Suppress the browser's middle-click autoscroll
+        event.prevent_default();
+
+        *drag_mode_clone.borrow_mut() = Some(mode);
         *last_mouse_pos_clone.borrow_mut() = (event.client_x() as f64, event.client_y() as f64);
     }) as Box<dyn FnMut(_)>);
-    canvas_clone
-        .add_event_listener_with_callback("mousedown", on_mouse_down.as_ref().unchecked_ref())?;
+    canvas.add_event_listener_with_callback("mousedown", on_mouse_down.as_ref().unchecked_ref())?;
     on_mouse_down.forget();
 
     // Clone references for the `mousemove` event
     let canvas_clone = canvas.clone();
-    let is_dragging_clone = is_dragging.clone();
+    let drag_mode_clone = drag_mode.clone();
     let last_mouse_pos_clone = last_mouse_pos.clone();
+    let click_clone = click.clone();
 
-    // Mouse move event
+    // Mouse move event: drags, or highlights the gizmo handle under the cursor
     let on_mouse_move = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
-        if *is_dragging_clone.borrow() {
-            let (last_x, last_y) = *last_mouse_pos_clone.borrow();
-            let (current_x, current_y) = (event.client_x() as f64, event.client_y() as f64);
+        let mode = match *drag_mode_clone.borrow() {
+            Some(mode) if mode != DragMode::Gizmo => mode,
+            mode => {
+                let (mouse, viewport) = canvas_position(&canvas_clone, &event);
+                if gizmo_mouse_move(mouse, viewport, mode.is_some()) {
+                    trigger_draw_event();
+                }
+                return;
+            }
+        };
+
+        let (last_x, last_y) = *last_mouse_pos_clone.borrow();
+        let (current_x, current_y) = (event.client_x() as f64, event.client_y() as f64);
 
-            // Calculate the change in mouse position
-            let _delta_x = current_x - last_x;
-            let _delta_y = current_y - last_y;
+        // Calculate the change in mouse position
+        let delta_x = current_x - last_x;
+        let delta_y = current_y - last_y;
 
-            // TODO
+        // Dragging away from where the button went down is not a click
+        let mut click = click_clone.borrow_mut();
+        if let Some((x, y)) = click.as_ref().map(|click| click.client) {
+            if (current_x - x).hypot(current_y - y) > CLICK_TOLERANCE {
+                *click = None;
+            }
+        }
 
-            // // Update rotation angles (scale the deltas for smoother rotation)
-            // let mut rotation = rotation_clone.borrow_mut();
-            // rotation.0 += delta_y * 0.05; // Rotate around X-axis
-            // rotation.1 += delta_x * 0.05; // Rotate around Y-axis
+        CAMERA.with(|camera| {
+            let mut camera = camera.write().unwrap();
+            match mode {
+                DragMode::Orbit => {
+                    camera.orbit(
+                        delta_y as f32 * ORBIT_SENSITIVITY,
+                        delta_x as f32 * ORBIT_SENSITIVITY,
+                    );
+                }
+                DragMode::Pan => {
+                    // Move the scene so the point under the cursor follows it
+                    let units_per_pixel =
+                        camera.view_height() / canvas_clone.client_height().max(1) as f32;
+                    camera.pan(
+                        delta_x as f32 * units_per_pixel,
+                        -delta_y as f32 * units_per_pixel,
+                    );
+                }
+                DragMode::Gizmo => {}
+            }
+        });
 
-            // // Update the last mouse position
-            // *last_mouse_pos_clone.borrow_mut() = (current_x, current_y);
+        // Update the last mouse position
+        *last_mouse_pos_clone.borrow_mut() = (current_x, current_y);
 
-            // web_sys::console::log_1(&format!("Rotation {},{}", rotation.0, rotation.1).into());
-        }
+        // Trigger a custom event to redraw the scene
+        trigger_draw_event();
     }) as Box<dyn FnMut(_)>);
-    canvas_clone
-        .add_event_listener_with_callback("mousemove", on_mouse_move.as_ref().unchecked_ref())?;
+    canvas.add_event_listener_with_callback("mousemove", on_mouse_move.as_ref().unchecked_ref())?;
     on_mouse_move.forget();
 
     // Clone references for the `mouseup` event
-    let is_dragging_clone = is_dragging.clone();
+    let drag_mode_clone = drag_mode.clone();
+    let click_clone = click.clone();
 
-    // Mouse up event
+    // Mouse up event, registered on the window so releasing outside the canvas ends the drag. A
+    // click without dragging selects the node under the cursor.
     let on_mouse_up = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        *is_dragging_clone.borrow_mut() = false;
+        if drag_mode_clone.borrow_mut().take() == Some(DragMode::Gizmo) {
+            gizmo_mouse_up();
+        }
+        if let Some(click) = click_clone.borrow_mut().take() {
+            select_at(click.mouse, click.viewport);
+        }
     }) as Box<dyn FnMut(_)>);
-    canvas.add_event_listener_with_callback("mouseup", on_mouse_up.as_ref().unchecked_ref())?;
+    window()
+        .unwrap()
+        .add_event_listener_with_callback("mouseup", on_mouse_up.as_ref().unchecked_ref())?;
     on_mouse_up.forget();
 
+    // Clone references for the `wheel` event
+    let canvas_clone = canvas.clone();
+
+    // Wheel event: dolly toward the point under the cursor
+    let on_wheel = Closure::wrap(Box::new(move |event: web_sys::WheelEvent| {
+        event.prevent_default();
+
+        // Normalize line and page scrolling to pixels
+        let delta = match event.delta_mode() {
+            web_sys::WheelEvent::DOM_DELTA_LINE => event.delta_y() * 16.0,
+            web_sys::WheelEvent::DOM_DELTA_PAGE => event.delta_y() * 400.0,
+            _ => event.delta_y(),
+        } as f32;
+
+        let width = canvas_clone.client_width().max(1) as f32;
+        let height = canvas_clone.client_height().max(1) as f32;
+        let ndc_x = 2.0 * event.offset_x() as f32 / width - 1.0;
+        let ndc_y = 1.0 - 2.0 * event.offset_y() as f32 / height;
+
+        CAMERA.with(|camera| {
+            camera
+                .write()
+                .unwrap()
+                .dolly_toward(DOLLY_SENSITIVITY.powf(delta), ndc_x, ndc_y);
+        });
+
+        // Trigger a custom event to redraw the scene
+        trigger_draw_event();
+    }) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback("wheel", on_wheel.as_ref().unchecked_ref())?;
+    on_wheel.forget();
+
     Ok(())
 }
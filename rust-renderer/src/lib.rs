@@ -1,17 +1,32 @@
+mod assets;
+mod export;
 mod file;
+mod gizmo;
 mod global;
+mod gpu_memory;
+mod history;
+mod import;
 mod input;
+mod material;
 mod matrix;
 mod model;
+mod pick;
+mod scene;
 mod shader;
+mod texture;
 mod vao;
 mod vbo;
 
-use cgmath::{perspective, Deg, Matrix4, Point3, SquareMatrix, Transform, Vector3};
-use global::{Vertex, GRID, MODEL};
+use cgmath::{Matrix4, Point3, SquareMatrix};
+use global::{CAMERA, CANVAS, CONTEXT, GIZMO, HISTORY, NORMAL_MODE, PROGRAM, SCENE};
+use history::{record_edit, redo, undo, Change, Edit};
+use material::{MaterialUniforms, BUMP_MAP_UNIT, DIFFUSE_MAP_UNIT};
 use model::ModelObject;
-use std::cell::RefCell;
-use std::rc::Rc;
+use renderer_core::camera::Camera;
+use renderer_core::geometry::NormalMode;
+use renderer_core::render::{generate_grid, GRID_HALF_COUNT};
+use renderer_core::texture::TextureImage;
+use scene::Scene;
 use wasm_bindgen::prelude::*;
 use web_sys::window;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext as GL, WebGlProgram, EventTarget, CustomEvent};
@@ -44,60 +59,198 @@ pub fn main(canvas: HtmlCanvasElement) -> Result<(), JsValue> {
     let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into().unwrap();
     let gl: GL = canvas.get_context("webgl2")?.unwrap().dyn_into::<GL>()?;
 
+    // Keep the canvas and context around for resizing
+    CANVAS.with(|v| *v.write().unwrap() = Some(canvas.clone()));
+    CONTEXT.with(|v| *v.write().unwrap() = Some(gl.clone()));
+
     // Enable mouse controls
-    let zoom = Rc::new(RefCell::new(15.0));
-    let rotation = Rc::new(RefCell::new((35.264, -45.0)));
-    let center_pos = Rc::new(RefCell::new((0.0, 0.0, 0.0)));
     enable_mouse_controls(canvas.clone())?;
 
-    // Create grid object
-    GRID.with(|v| {
-        let mut grid = v.write().unwrap();
-        let (vertices, indices) = generate_grid(100, 1.0);
-        let mut obj = ModelObject::new(gl.clone());
-        obj.update_model(vertices, indices);
-        *grid = Some(obj);
+    // Create an empty scene over the grid
+    SCENE.with(|v| {
+        let (vertices, indices) = generate_grid(GRID_HALF_COUNT, 1.0);
+        let mut grid = ModelObject::new(gl.clone());
+        grid.update_lines(vertices, indices);
+        *v.write().unwrap() = Some(Scene::new(gl.clone(), grid));
     });
 
-    // Create model object
-    MODEL.with(|v| {
-        let mut model = v.write().unwrap();
-        let mut m = ModelObject::new(gl.clone());
-        m.set_color([0.75, 0.75, 0.75, 1.0]);
-        m.set_draw_wireframe(false);
-        *model = Some(m);
+    // Shaders and GL state, set up again whenever the context is restored
+    let program = init_program(&gl).map_err(|e| JsValue::from_str(&e))?;
+    PROGRAM.with(|v| *v.write().unwrap() = Some(program));
+
+    // Keep the whole grid between the clip planes
+    CAMERA.with(|camera| {
+        camera.write().unwrap().set_scene_bounds(
+            Point3::new(0.0, 0.0, 0.0),
+            GRID_HALF_COUNT as f32 * std::f32::consts::SQRT_2,
+        );
     });
 
-    // Shaders
+    let gl_draw = gl.clone();
+
+    let key_handler = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        // Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes, with Cmd in place of Ctrl on macOS
+        if event.ctrl_key() || event.meta_key() {
+            let result = match event.key().to_lowercase().as_str() {
+                "z" if event.shift_key() => redo(),
+                "z" => undo(),
+                "y" => redo(),
+                _ => return,
+            };
+            event.prevent_default();
+            if let Err(e) = result {
+                web_sys::console::error_1(&e);
+            }
+            return;
+        }
+
+        CAMERA.with(|camera| {
+            let mut camera = camera.write().unwrap();
+            let pan_step = camera.view_height() / 120.0;
+            match event.key().as_str() {
+                "ArrowUp" if event.shift_key() => {
+                    camera.dolly(1.0 / 1.05);
+                }
+                "ArrowDown" if event.shift_key() => {
+                    camera.dolly(1.05);
+                }
+                "ArrowLeft" => {
+                    camera.pan(pan_step, 0.0);
+                }
+                "ArrowRight" => {
+                    camera.pan(-pan_step, 0.0);
+                }
+                "ArrowUp" => {
+                    camera.pan(0.0, -pan_step);
+                }
+                "ArrowDown" => {
+                    camera.pan(0.0, pan_step);
+                }
+                "1" => {
+                    camera.reset(0.0, 0.0);
+                }
+                "2" => {
+                    camera.reset(0.0, -90.0);
+                }
+                "3" => {
+                    camera.reset(90.0, 0.0);
+                }
+                "4" => {
+                    camera.reset(35.264, -45.0);
+                }
+                "5" => {
+                    camera.toggle_projection();
+                }
+                _ => {}
+            }
+        });
+
+        if event.key() == "f" {
+            frame_model();
+        }
+
+        // Trigger a custom event to redraw the scene
+        trigger_draw_event();
+
+    }) as Box<dyn FnMut(_)>);
+
+    window()
+        .unwrap()
+        .add_event_listener_with_callback("keydown", key_handler.as_ref().unchecked_ref())?;
+    key_handler.forget();
+
+    let draw = Closure::wrap(Box::new(move |_event: web_sys::CustomEvent| {
+        // Nothing can be drawn until a lost context is restored
+        if gl_draw.is_context_lost() {
+            return;
+        }
+        let Some(program) = PROGRAM.with(|v| v.read().unwrap().clone()) else {
+            return;
+        };
+        CAMERA.with(|camera| {
+            draw_model(gl_draw.clone(), program, &camera.read().unwrap());
+        });
+    }) as Box<dyn FnMut(_)>);
+
+    let window = window().unwrap();
+    let target: &EventTarget = window.as_ref();
+    target
+        .add_event_listener_with_callback("draw-event", draw.as_ref().unchecked_ref())
+        .unwrap();
+    draw.forget();
+
+    // The browser may drop the context, after a GPU reset for example. Cancelling the loss lets
+    // it be restored, and then everything is uploaded again from the copies kept on the CPU.
+    let on_context_lost = Closure::wrap(Box::new(move |event: web_sys::Event| {
+        event.prevent_default();
+    }) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback(
+        "webglcontextlost",
+        on_context_lost.as_ref().unchecked_ref(),
+    )?;
+    on_context_lost.forget();
+
+    let on_context_restored = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+        if let Err(e) = restore_context() {
+            web_sys::console::error_1(&JsValue::from_str(&e));
+        }
+    }) as Box<dyn FnMut(_)>);
+    canvas.add_event_listener_with_callback(
+        "webglcontextrestored",
+        on_context_restored.as_ref().unchecked_ref(),
+    )?;
+    on_context_restored.forget();
+
+    // Size the drawing buffer to the displayed size of the canvas
+    resize(canvas.client_width() as u32, canvas.client_height() as u32);
+
+    // Initial draw
+    trigger_draw_event();
+
+    Ok(())
+}
+// Compiles and links the shaders and sets up the GL state they draw with, on a new context or one
+// restored after it was lost
+fn init_program(gl: &GL) -> Result<WebGlProgram, String> {
+    // Mirrored on the CPU by renderer_core::raster for headless rendering
     let vert_shader = compile_shader(
-        &gl,
+        gl,
         GL::VERTEX_SHADER,
         r#"#version 300 es
         layout(location = 0) in vec3 aPos;
         layout(location = 1) in vec3 aNormal;
+        layout(location = 2) in vec2 aTexCoord;
+        layout(location = 3) in vec3 aColor;
 
         out vec3 FragPos;
         out vec3 Normal;
+        out vec2 TexCoord;
+        out vec3 VertexColor;
 
         uniform mat4 model;
         uniform mat4 view;
         uniform mat4 projection;
+        uniform float pointSize;
 
         void main() {
             FragPos = vec3(model * vec4(aPos, 1.0));
             Normal = mat3(transpose(inverse(model))) * aNormal;
+            TexCoord = aTexCoord;
+            VertexColor = aColor;
+            gl_PointSize = pointSize;
             gl_Position = projection * view * vec4(FragPos, 1.0);
         }"#,
-    )
-    .unwrap();
+    )?;
 
     let frag_shader = compile_shader(
-        &gl,
+        gl,
         GL::FRAGMENT_SHADER,
         r#"#version 300 es
         precision mediump float;
         in vec3 FragPos;
         in vec3 Normal;
+        in vec2 TexCoord;
+        in vec3 VertexColor;
 
         out vec4 FragColor;
 
@@ -105,227 +258,140 @@ pub fn main(canvas: HtmlCanvasElement) -> Result<(), JsValue> {
         uniform vec3 viewPos;
         uniform vec3 lightColor;
         uniform vec3 objectColor;
+        uniform vec3 ambientColor;
+        uniform vec3 specularColor;
+        uniform float shininess;
+        uniform float opacity;
+        uniform bool useTexture;
+        uniform sampler2D diffuseMap;
+        uniform bool useBumpMap;
+        uniform sampler2D bumpMap;
+        uniform float bumpScale;
+        uniform bool useVertexColor;
+
+        // Tilts the normal by the screen-space slope of the height map, no tangents needed
+        vec3 perturbNormal(vec3 n) {
+            vec3 dpdx = dFdx(FragPos);
+            vec3 dpdy = dFdy(FragPos);
+            float height = texture(bumpMap, TexCoord).r * bumpScale;
+            float dhdx = dFdx(height);
+            float dhdy = dFdy(height);
+            vec3 r1 = cross(dpdy, n);
+            vec3 r2 = cross(n, dpdx);
+            float det = dot(dpdx, r1);
+            vec3 gradient = sign(det) * (dhdx * r1 + dhdy * r2);
+            return normalize(abs(det) * n - gradient);
+        }
 
         void main() {
+            vec3 color = useVertexColor ? VertexColor : objectColor;
+            vec4 baseColor = useTexture ? texture(diffuseMap, TexCoord) : vec4(color, 1.0);
+
+            // Points without normals have nothing to shade against
+            if (dot(Normal, Normal) == 0.0) {
+                FragColor = vec4(baseColor.rgb, baseColor.a * opacity);
+                return;
+            }
+
             float ambientStrength = 0.2;
-            vec3 ambient = ambientStrength * lightColor;
+            vec3 ambient = ambientStrength * ambientColor * lightColor;
 
             vec3 norm = normalize(Normal);
+            if (useBumpMap) {
+                norm = perturbNormal(norm);
+            }
             vec3 lightDir = normalize(lightPos - FragPos);
             float diff = max(dot(norm, lightDir), 0.0);
             vec3 diffuse = diff * lightColor;
 
-            float specularStrength = 0.5;
             vec3 viewDir = normalize(viewPos - FragPos);
             vec3 reflectDir = reflect(-lightDir, norm);
-            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32.0);
-            vec3 specular = specularStrength * spec * lightColor;
+            float spec = pow(max(dot(viewDir, reflectDir), 0.0), max(shininess, 1.0));
+            vec3 specular = spec * specularColor * lightColor;
 
-            vec3 result = (ambient + diffuse + specular) * objectColor;
-            FragColor = vec4(result, 1.0);
+            vec3 result = (ambient + diffuse) * baseColor.rgb + specular;
+            FragColor = vec4(result, baseColor.a * opacity);
         }"#,
-    )
-    .unwrap();
+    )?;
 
-    let program = link_program(&gl, &vert_shader, &frag_shader).unwrap();
+    let program = link_program(gl, &vert_shader, &frag_shader)?;
     gl.use_program(Some(&program));
 
     // Model matrix
     let model = Matrix4::identity();
 
-    // Projection matrix
-    let projection = perspective(
-        Deg(45.0),
-        canvas.width() as f32 / canvas.height() as f32,
-        0.1,
-        100.0,
-    );
-
     let model_loc = gl
         .get_uniform_location(&program, "model")
-        .ok_or("ERROR: could not get model uniform location")
-        .unwrap();
-    let proj_loc = gl
-        .get_uniform_location(&program, "projection")
-        .ok_or("ERROR: could not get projection uniform location")
-        .unwrap();
+        .ok_or("ERROR: could not get model uniform location")?;
     let light_pos_loc = gl
         .get_uniform_location(&program, "lightPos")
-        .ok_or("ERROR: could not get lightPos uniform location")
-        .unwrap();
+        .ok_or("ERROR: could not get lightPos uniform location")?;
     let view_pos_loc = gl
         .get_uniform_location(&program, "viewPos")
-        .ok_or("ERROR: could not get viewPos uniform location")
-        .unwrap();
+        .ok_or("ERROR: could not get viewPos uniform location")?;
     let light_color_loc = gl
         .get_uniform_location(&program, "lightColor")
-        .ok_or("ERROR: could not get lightColor uniform location")
-        .unwrap();
+        .ok_or("ERROR: could not get lightColor uniform location")?;
     let object_color_loc = gl
         .get_uniform_location(&program, "objectColor")
-        .ok_or("ERROR: could not get objectColor uniform location")
-        .unwrap();
+        .ok_or("ERROR: could not get objectColor uniform location")?;
+    let diffuse_map_loc = gl
+        .get_uniform_location(&program, "diffuseMap")
+        .ok_or("ERROR: could not get diffuseMap uniform location")?;
+    let bump_map_loc = gl
+        .get_uniform_location(&program, "bumpMap")
+        .ok_or("ERROR: could not get bumpMap uniform location")?;
 
     // Assign shader variable data
     gl.uniform_matrix4fv_with_f32_array(Some(&model_loc), false, &matrix4_to_array(&model));
-    gl.uniform_matrix4fv_with_f32_array(Some(&proj_loc), false, &matrix4_to_array(&projection));
     gl.uniform3f(Some(&light_pos_loc), 0.0, 5.0, 5.0);
     gl.uniform3f(Some(&view_pos_loc), 0.0, 0.0, 2.0);
     gl.uniform3f(Some(&light_color_loc), 1.0, 1.0, 1.0);
     gl.uniform3f(Some(&object_color_loc), 1.0, 0.0, 0.5);
+    gl.uniform1i(Some(&diffuse_map_loc), DIFFUSE_MAP_UNIT as i32);
+    gl.uniform1i(Some(&bump_map_loc), BUMP_MAP_UNIT as i32);
 
     gl.enable(GL::DEPTH_TEST);
     gl.depth_func(GL::LESS);
 
+    // Materials with an opacity below 1 are blended over what is behind them
+    gl.enable(GL::BLEND);
+    gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+
     // Clear the screen
     gl.clear_color(0.0, 0.0, 0.0, 1.0);
     gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
 
-    let gl_draw = gl.clone();
-    let program_draw = program.clone();
-    let center_draw = center_pos.clone();
-    let rotation_draw = rotation.clone();
-    let zoom_draw = zoom.clone();
-
-    let rotation_wheel = rotation.clone();
-
-    let key_handler = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
-        if event.shift_key() && event.key() == "ArrowUp" {
-            let mut zoom = zoom.borrow_mut();
-            *zoom -= 0.5;
-            *zoom = (*zoom as f32).max(1.0).min(100.0);
-        } else if event.shift_key() && event.key() == "ArrowDown" {
-            let mut zoom = zoom.borrow_mut();
-            *zoom += 0.5;
-            *zoom = (*zoom as f32).max(1.0).min(100.0);
-        } else {
-            let mut center_pos = center_pos.borrow_mut();
-            let mut rotation = rotation.borrow_mut();
-            let mut zoom = zoom.borrow_mut();
-            match event.key().as_str() {
-                "ArrowLeft" => {
-                    center_pos.0 += 0.1;
-                }
-                "ArrowRight" => {
-                    center_pos.0 -= 0.1;
-                }
-                "ArrowUp" => {
-                    center_pos.1 -= 0.1;
-                }
-                "ArrowDown" => {
-                    center_pos.1 += 0.1;
-                }
-                "1" => {
-                    rotation.0 = 0.0;
-                    rotation.1 = 0.0;
-                    center_pos.0 = 0.0;
-                    center_pos.1 = 0.0;
-                    center_pos.2 = 0.0;
-                    *zoom = 15.0;
-                }
-                "2" => {
-                    rotation.0 = 0.0;
-                    rotation.1 = -90.0;
-                    center_pos.0 = 0.0;
-                    center_pos.1 = 0.0;
-                    center_pos.2 = 0.0;
-                    *zoom = 15.0;
-                }
-                "3" => {
-                    rotation.0 = 90.0;
-                    rotation.1 = 0.0;
-                    center_pos.0 = 0.0;
-                    center_pos.1 = 0.0;
-                    center_pos.2 = 0.0;
-                    *zoom = 15.0;
-                }
-                "4" => {
-                    rotation.0 = 35.264;
-                    rotation.1 = -45.0;
-                    center_pos.0 = 0.0;
-                    center_pos.1 = 0.0;
-                    center_pos.2 = 0.0;
-                    *zoom = 15.0;
-                }
-                _ => {}
-            }
-        }
-
-        // Trigger a custom event to redraw the scene
-        trigger_draw_event();
-
-    }) as Box<dyn FnMut(_)>);
-
-    let wheel_handler = Closure::wrap(Box::new(move |event: web_sys::WheelEvent| {
-        //web_sys::console::log_1(&format!("X").into());
-
-        // Only handle if ctrlKey is not pressed (not pinch-zoom)
-        if !event.ctrl_key() {
-            event.prevent_default();
-
-            let dx = event.delta_x();
-            let dy = event.delta_y();
-
-            // Sensitivity factor, adjust as needed
-            let sensitivity = 0.1;
+    Ok(program)
+}
 
-            let mut rot = rotation_wheel.borrow_mut();
-            rot.0 += dy * sensitivity;
-            rot.1 += dx * sensitivity;
+// Makes the shaders and every GL object of the scene, the gizmo and the edit history again on a
+// restored context, then draws
+fn restore_context() -> Result<(), String> {
+    let gl = CONTEXT
+        .with(|gl| gl.read().unwrap().clone())
+        .ok_or("renderer is not initialized")?;
+    let program = init_program(&gl)?;
+    PROGRAM.with(|v| *v.write().unwrap() = Some(program));
+
+    SCENE.with(|scene| {
+        if let Some(scene) = scene.write().unwrap().as_mut() {
+            scene.restore();
         }
-
-        // Trigger a custom event to redraw the scene
-        trigger_draw_event();
-
-    }) as Box<dyn FnMut(_)>);
-
-    canvas.add_event_listener_with_callback("wheel", wheel_handler.as_ref().unchecked_ref())?;
-    wheel_handler.forget();
-    window()
-        .unwrap()
-        .add_event_listener_with_callback("keydown", key_handler.as_ref().unchecked_ref())?;
-    key_handler.forget();
-
-    let draw = Closure::wrap(Box::new(move |_event: web_sys::CustomEvent| {
-    web_sys::console::log_1(&"draw event triggered!".into());
-     GRID.with(|model| {
-            let model = model.read().unwrap();
-            if let Some(model) = model.as_ref() {
-                model.bind();
-                draw_model(
-                    gl_draw.clone(),
-                    program_draw.clone(),
-                    center_draw.clone(),
-                    rotation_draw.clone(),
-                    zoom_draw.clone(),
-                );
-            }
-        });
-        MODEL.with(|model| {
-            let model = model.read().unwrap();
-            if let Some(model) = model.as_ref() {
-                model.bind();
-                draw_model(
-                    gl_draw.clone(),
-                    program_draw.clone(),
-                    center_draw.clone(),
-                    rotation_draw.clone(),
-                    zoom_draw.clone(),
-                );
-            }
-        });
-}) as Box<dyn FnMut(_)>);
-
-let window = window().unwrap();
-let target: &EventTarget = window.as_ref();
-target
-    .add_event_listener_with_callback("draw-event", draw.as_ref().unchecked_ref())
-    .unwrap();
-draw.forget();
-
-    // Initial draw
-    trigger_draw_event();
-
+    });
+    GIZMO.with(|gizmo| gizmo.write().unwrap().restore());
+    HISTORY.with(|history| history.write().unwrap().restore());
+
+    // Sets the viewport of the new context and redraws
+    let size = CANVAS.with(|canvas| {
+        let canvas = canvas.read().unwrap();
+        canvas
+            .as_ref()
+            .map(|c| (c.client_width() as u32, c.client_height() as u32))
+    });
+    if let Some((width, height)) = size {
+        resize(width, height);
+    }
     Ok(())
 }
 
@@ -336,126 +402,188 @@ pub fn trigger_draw_event() {
     target.dispatch_event(&draw_event).unwrap();
 }
 
-pub fn generate_grid(half_count: i32, spacing: f32) -> (Vec<Vertex>, Vec<u32>) {
-    let mut vertices: Vec<Vertex> = Vec::new();
-    let mut indices: Vec<u32> = Vec::new(); // u16 is enough for ≤-65 535 verts
-
-    // unit +Y normal (0,1,0)
-    const N: [f32; 3] = [0.0, 1.0, 0.0];
-
-    // helper to push a vertex and return its index
-    let push_vert = |p: [f32; 3], verts: &mut Vec<Vertex>| -> u16 {
-        let idx = verts.len() as u16;
-        verts.push(Vertex(p, N));
-        idx
-    };
+/// Resizes the canvas to `width` x `height` CSS pixels.
+///
+/// The drawing buffer is scaled by the device pixel ratio so the display stays sharp on HiDPI
+/// screens, and the viewport and camera aspect ratio follow the new size.
+#[wasm_bindgen]
+pub fn resize(width: u32, height: u32) {
+    let width = width.max(1);
+    let height = height.max(1);
+    let pixel_ratio = window().unwrap().device_pixel_ratio();
+    let buffer_width = (width as f64 * pixel_ratio).round() as u32;
+    let buffer_height = (height as f64 * pixel_ratio).round() as u32;
+
+    CANVAS.with(|canvas| {
+        if let Some(canvas) = canvas.read().unwrap().as_ref() {
+            let style = canvas.style();
+            style
+                .set_property("width", &format!("{}px", width))
+                .unwrap();
+            style
+                .set_property("height", &format!("{}px", height))
+                .unwrap();
+            canvas.set_width(buffer_width);
+            canvas.set_height(buffer_height);
+        }
+    });
+    CONTEXT.with(|gl| {
+        if let Some(gl) = gl.read().unwrap().as_ref() {
+            gl.viewport(0, 0, buffer_width as i32, buffer_height as i32);
+        }
+    });
+    CAMERA.with(|camera| {
+        camera.write().unwrap().aspect = width as f32 / height as f32;
+    });
 
-    // horizontal (X-axis) lines – vary Z
-    for i in -half_count..=half_count {
-        let z = i as f32 * spacing;
-        let i0 = push_vert([-half_count as f32 * spacing, 0.0, z], &mut vertices) as u32;
-        let i1 = push_vert([half_count as f32 * spacing, 0.0, z], &mut vertices) as u32;
-        indices.extend_from_slice(&[i0, i1]);
-    }
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+}
 
-    // vertical (Z-axis) lines – vary X
-    for i in -half_count..=half_count {
-        let x = i as f32 * spacing;
-        let i0 = push_vert([x, 0.0, -half_count as f32 * spacing], &mut vertices) as u32;
-        let i1 = push_vert([x, 0.0, half_count as f32 * spacing], &mut vertices) as u32;
-        indices.extend_from_slice(&[i0, i1]);
+/// Points the camera at the visible nodes and sets the distance and clip planes so they fill the
+/// view.
+#[wasm_bindgen]
+pub fn frame_model() {
+    let sphere = SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        scene.as_ref().and_then(|scene| scene.bounding_sphere())
+    });
+    if let Some(sphere) = sphere {
+        CAMERA.with(|camera| {
+            camera.write().unwrap().frame(sphere.center, sphere.radius);
+        });
     }
 
-    (vertices, indices)
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
 }
 
 #[wasm_bindgen]
 pub fn toggle_wireframe() {
-    MODEL.with(|model| {
-        let mut model = model.write().unwrap();
-        if let Some(model) = model.as_mut() {
-            model.set_draw_wireframe(!model.get_draw_wireframe());
-        }
-    });
+    record_edit(false, |scene| {
+        let before = scene.get_draw_wireframe();
+        scene.set_draw_wireframe(!before);
+        Ok(Edit::Wireframe(Change {
+            before,
+            after: !before,
+        }))
+    })
+    .ok();
+}
 
-    // Trigger a custom event to redraw the scene
-    trigger_draw_event();
+/// Selects how normals are generated for models that don't provide them.
+///
+/// `mode` is one of "flat", "smooth" or "crease"; `crease_angle` is the angle in degrees above
+/// which "crease" keeps edges sharp. Parts of the loaded model that use generated normals are
+/// rebuilt right away.
+#[wasm_bindgen]
+pub fn set_normal_mode(mode: &str, crease_angle: f32) -> Result<(), JsValue> {
+    let mode = match mode {
+        "flat" => NormalMode::Flat,
+        "smooth" => NormalMode::Smooth,
+        "crease" => NormalMode::Crease(crease_angle),
+        _ => {
+            return Err(JsValue::from_str(&format!(
+                "unknown normal mode '{}'",
+                mode
+            )))
+        }
+    };
+    record_edit(false, |scene| {
+        let before = NORMAL_MODE.with(|v| std::mem::replace(&mut *v.write().unwrap(), mode));
+        scene.regenerate_normals(mode);
+        Ok(Edit::NormalMode(Change {
+            before,
+            after: mode,
+        }))
+    })
 }
 
+/// Sets the model color as RGBA in 0..1, which also replaces the diffuse color of its materials.
+///
+/// Successive calls undo as one edit until `end_edit`, for picking a color by dragging.
 #[wasm_bindgen]
 pub fn set_model_color(color: js_sys::Array) {
     let r = color.get(0).as_f64().unwrap_or(0.75) as f32;
     let g = color.get(1).as_f64().unwrap_or(0.75) as f32;
     let b = color.get(2).as_f64().unwrap_or(0.75) as f32;
     let a = color.get(3).as_f64().unwrap_or(1.0) as f32;
-    MODEL.with(|model| {
-        let mut model = model.write().unwrap();
-        if let Some(model) = model.as_mut() {
-            model.set_color([r, g, b, a]);
-        }
-    });
+    record_edit(true, |scene| {
+        let before = (scene.color(), scene.color_override());
+        scene.set_color([r, g, b, a]);
+        scene.set_color_override(true);
+        Ok(Edit::Color(Change {
+            before,
+            after: ([r, g, b, a], true),
+        }))
+    })
+    .ok();
+}
 
-    // Trigger a custom event to redraw the scene
-    trigger_draw_event();
+/// Decodes a PNG or JPEG image and uses it as the diffuse texture of the loaded model.
+///
+/// The texture is sampled with the model's texture coordinates and replaces the model color.
+#[wasm_bindgen]
+pub fn set_model_texture(bytes: &[u8]) -> Result<(), JsValue> {
+    let image = TextureImage::decode(bytes).map_err(|e| JsValue::from_str(&e))?;
+    record_edit(false, |scene| {
+        let after = Some(scene.create_texture(image));
+        let before = scene.texture();
+        scene.set_texture(after.clone());
+        Ok(Edit::Texture(Change { before, after }))
+    })
+}
+
+#[wasm_bindgen]
+pub fn clear_model_texture() {
+    record_edit(false, |scene| {
+        let before = scene.texture();
+        scene.set_texture(None);
+        Ok(Edit::Texture(Change {
+            before,
+            after: None,
+        }))
+    })
+    .ok();
 }
 
-fn draw_model(
-    gl: GL,
-    program: WebGlProgram,
-    center: Rc<RefCell<(f32, f32, f32)>>,
-    rotation: Rc<RefCell<(f64, f64)>>,
-    zoom: Rc<RefCell<f32>>,
-) {
+/// Sets the size in pixels that point clouds are drawn at.
+#[wasm_bindgen]
+pub fn set_point_size(size: f32) -> Result<(), JsValue> {
+    if !(size > 0.0 && size.is_finite()) {
+        return Err(JsValue::from_str("point size must be a positive number"));
+    }
+    record_edit(false, |scene| {
+        let before = scene.point_size();
+        scene.set_point_size(size);
+        Ok(Edit::PointSize(Change {
+            before,
+            after: size,
+        }))
+    })
+}
+
+fn draw_model(gl: GL, program: WebGlProgram, camera: &Camera) {
     // Clear the screen
     gl.clear_color(0.0, 0.0, 0.0, 1.0);
     gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
 
-    // Retrieve the current rotation angles
-    let (x_rotation, y_rotation) = *rotation.borrow();
-
-    // Retrieve the current translation (center position)
-    let (center_x, center_y, center_z) = *center.borrow();
-
-    // Create the rotation matrix
-    let rotation_matrix = Matrix4::from_angle_x(Deg(x_rotation as f32))
-        * Matrix4::from_angle_y(Deg(y_rotation as f32));
-
-    // Transform the translation vector by the inverse of the rotation matrix
-    let inverse_rotation_matrix = rotation_matrix.invert().unwrap();
-    let transformed_translation =
-        inverse_rotation_matrix * Vector3::new(center_x, center_y, center_z).extend(1.0);
-
-    // Update the view matrix
-    let view = Matrix4::look_at_rh(
-        Point3::new(0.0, 0.0, *zoom.borrow()),
-        Point3::new(0.0, 0.0, 0.0),
-        Vector3::unit_y(),
-    ) * rotation_matrix
-        * Matrix4::from_translation(Vector3::new(
-            transformed_translation.x,
-            transformed_translation.y,
-            transformed_translation.z,
-        )); // Apply transformed translation last
-
-    // Update the shader program with the view matrix
+    // Update the shader program with the camera matrices
     gl.use_program(Some(&program));
     let view_loc = gl
         .get_uniform_location(&program, "view")
         .ok_or("ERROR: could not get view uniform location")
         .unwrap();
-    gl.uniform_matrix4fv_with_f32_array(Some(&view_loc), false, &matrix4_to_array(&view));
-
-    // Calculate rotated lightPos and viewPos
-    let light_pos = Vector3::new(0.0, 5.0, 5.0);
-    let view_pos = Vector3::new(0.0, 0.0, 2.0);
-
-    let rotation_matrix = Matrix4::from_angle_x(Deg(x_rotation as f32))
-        * Matrix4::from_angle_y(Deg(y_rotation as f32))
-            .invert()
-            .unwrap();
-
-    let rotated_light_pos = rotation_matrix.transform_vector(light_pos);
-    let rotated_view_pos = rotation_matrix.transform_vector(view_pos);
+    let proj_loc = gl
+        .get_uniform_location(&program, "projection")
+        .ok_or("ERROR: could not get projection uniform location")
+        .unwrap();
+    gl.uniform_matrix4fv_with_f32_array(Some(&view_loc), false, &matrix4_to_array(&camera.view()));
+    gl.uniform_matrix4fv_with_f32_array(
+        Some(&proj_loc),
+        false,
+        &matrix4_to_array(&camera.projection_matrix()),
+    );
 
     // Update lightPos and viewPos in the shader
     let light_pos_loc = gl
@@ -467,53 +595,26 @@ fn draw_model(
         .ok_or("ERROR: could not get viewPos uniform location")
         .unwrap();
 
-    gl.uniform3f(
-        Some(&light_pos_loc),
-        rotated_light_pos.x,
-        rotated_light_pos.y,
-        rotated_light_pos.z,
-    );
-    gl.uniform3f(
-        Some(&view_pos_loc),
-        rotated_view_pos.x,
-        rotated_view_pos.y,
-        rotated_view_pos.z,
-    );
+    let light_pos = camera.light_position();
+    let view_pos = camera.eye();
+    gl.uniform3f(Some(&light_pos_loc), light_pos.x, light_pos.y, light_pos.z);
+    gl.uniform3f(Some(&view_pos_loc), view_pos.x, view_pos.y, view_pos.z);
 
-    let object_color_loc = gl
-        .get_uniform_location(&program, "objectColor")
-        .ok_or("ERROR: could not get objectColor uniform location")
+    let material_uniforms = MaterialUniforms::new(&gl, &program);
+    let model_loc = gl
+        .get_uniform_location(&program, "model")
+        .ok_or("ERROR: could not get model uniform location")
         .unwrap();
 
     // Draw
-    GRID.with(|model| {
-        let model = model.read().unwrap();
-        if let Some(model) = model.as_ref() {
-            model.bind();
-            let color = model.get_color();
-            gl.uniform3f(Some(&object_color_loc), color[0], color[1], color[2]);
-
-            let indices_length = model.get_indices_count() as i32;
-            if model.get_draw_wireframe() {
-                gl.draw_arrays(GL::LINES, 0, indices_length);
-            } else {
-                gl.draw_arrays(GL::TRIANGLES, 0, indices_length);
-            }
-        }
-    });
-    MODEL.with(|model| {
-        let model = model.read().unwrap();
-        if let Some(model) = model.as_ref() {
-            model.bind();
-
-            let color = model.get_color();
-            gl.uniform3f(Some(&object_color_loc), color[0], color[1], color[2]);
-            let indices_length = model.get_indices_count() as i32;
-            if model.get_draw_wireframe() {
-                gl.draw_arrays(GL::LINES, 0, indices_length);
-            } else {
-                gl.draw_arrays(GL::TRIANGLES, 0, indices_length);
-            }
+    SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        if let Some(scene) = scene.as_ref() {
+            scene.draw(&material_uniforms, &model_loc);
+            GIZMO.with(|gizmo| {
+                let mut gizmo = gizmo.write().unwrap();
+                gizmo.draw(&gl, scene, camera, &material_uniforms, &model_loc);
+            });
         }
     });
 }
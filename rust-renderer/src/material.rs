@@ -0,0 +1,68 @@
+use renderer_core::material::Material;
+use web_sys::{WebGl2RenderingContext as GL, WebGlProgram, WebGlUniformLocation};
+
+/// Texture unit the diffuse map is bound to.
+pub const DIFFUSE_MAP_UNIT: u32 = 0;
+/// Texture unit the bump map is bound to.
+pub const BUMP_MAP_UNIT: u32 = 1;
+
+/// Shader uniform locations that make up a material and decide how it is colored.
+pub struct MaterialUniforms {
+    object_color: WebGlUniformLocation,
+    ambient_color: WebGlUniformLocation,
+    specular_color: WebGlUniformLocation,
+    shininess: WebGlUniformLocation,
+    opacity: WebGlUniformLocation,
+    use_texture: WebGlUniformLocation,
+    use_bump_map: WebGlUniformLocation,
+    bump_scale: WebGlUniformLocation,
+    use_vertex_color: WebGlUniformLocation,
+    point_size: WebGlUniformLocation,
+}
+
+impl MaterialUniforms {
+    pub fn new(gl: &GL, program: &WebGlProgram) -> Self {
+        let location = |name: &str| {
+            gl.get_uniform_location(program, name)
+                .ok_or(format!("ERROR: could not get {} uniform location", name))
+                .unwrap()
+        };
+        Self {
+            object_color: location("objectColor"),
+            ambient_color: location("ambientColor"),
+            specular_color: location("specularColor"),
+            shininess: location("shininess"),
+            opacity: location("opacity"),
+            use_texture: location("useTexture"),
+            use_bump_map: location("useBumpMap"),
+            bump_scale: location("bumpScale"),
+            use_vertex_color: location("useVertexColor"),
+            point_size: location("pointSize"),
+        }
+    }
+
+    /// Loads `material` into the shader, with the texture maps switched on as given.
+    pub fn apply(&self, gl: &GL, material: &Material, use_texture: bool, use_bump_map: bool) {
+        let [r, g, b] = material.diffuse;
+        gl.uniform3f(Some(&self.object_color), r, g, b);
+        let [r, g, b] = material.ambient;
+        gl.uniform3f(Some(&self.ambient_color), r, g, b);
+        let [r, g, b] = material.specular;
+        gl.uniform3f(Some(&self.specular_color), r, g, b);
+        gl.uniform1f(Some(&self.shininess), material.shininess);
+        gl.uniform1f(Some(&self.opacity), material.opacity);
+        gl.uniform1i(Some(&self.use_texture), use_texture as i32);
+        gl.uniform1i(Some(&self.use_bump_map), use_bump_map as i32);
+        gl.uniform1f(Some(&self.bump_scale), material.bump_scale);
+    }
+
+    /// Switches between the per-vertex colors and the material's diffuse color.
+    pub fn set_vertex_colors(&self, gl: &GL, use_vertex_color: bool) {
+        gl.uniform1i(Some(&self.use_vertex_color), use_vertex_color as i32);
+    }
+
+    /// Sets the size in pixels that points are drawn at.
+    pub fn set_point_size(&self, gl: &GL, size: f32) {
+        gl.uniform1f(Some(&self.point_size), size);
+    }
+}
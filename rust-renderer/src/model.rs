@@ -1,21 +1,62 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use renderer_core::bounds::{BoundingBox, BoundingSphere};
+use renderer_core::bvh::{Bvh, BvhHit};
+use renderer_core::import::{ImportedMesh, MeshGroup};
+use renderer_core::material::Material;
+use renderer_core::render::{triangle_edges, DEFAULT_POINT_SIZE};
+use renderer_core::texture::TextureImage;
+use renderer_core::vertex::Vertex;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::RwLock;
-use web_sys::WebGl2RenderingContext as GL;
+use web_sys::{WebGl2RenderingContext as GL, WebGlUniformLocation};
 
 use super::set_attribute;
-use crate::global::Vertex;
+use crate::material::{MaterialUniforms, BUMP_MAP_UNIT, DIFFUSE_MAP_UNIT};
+use crate::matrix::matrix4_to_array;
+use crate::texture::Texture;
 use crate::vao::VertexArray;
 use crate::vbo::Buffer;
 
+/// Name the exporters give the texture set with `Scene::set_texture`.
+pub const MODEL_TEXTURE_NAME: &str = "texture.png";
+
+/// Renderable geometry with its materials and display settings.
+///
+/// Where the object is placed is up to the scene node holding it.
 pub struct ModelObject {
     gl: GL,
     loaded: bool,
     vao: VertexArray,
     vbo: Buffer,
     ibo: Buffer,
+    edge_ibo: Buffer,
     vertices: Option<RwLock<Vec<Vertex>>>,
     indices: Option<RwLock<Vec<u32>>>,
+    // Line list drawn in wireframe mode, kept to upload again after a lost context
+    edges: Vec<u32>,
     draw_wireframe: bool,
     color: [f32; 4], // RGBA color
+    bounding_box: Option<BoundingBox>,
+    bounding_sphere: Option<BoundingSphere>,
+    // Built for triangles only, points and lines cannot be picked
+    bvh: Option<Bvh>,
+    generated_normals: Vec<bool>,
+    texture: Option<Rc<Texture>>,
+    submeshes: Vec<Submesh>,
+    color_override: bool,
+    vertex_colors: bool,
+    points: bool,
+    point_size: f32,
+}
+
+// A range of the triangle indices drawn with its own material
+struct Submesh {
+    start: usize,
+    count: usize,
+    material: Option<Material>,
+    diffuse_map: Option<Rc<Texture>>,
+    bump_map: Option<Rc<Texture>>,
 }
 
 impl ModelObject {
@@ -23,70 +64,299 @@ impl ModelObject {
         let vao = unsafe { VertexArray::new(&gl) };
         let vbo = unsafe { Buffer::new(&gl, GL::ARRAY_BUFFER) };
         let ibo = unsafe { Buffer::new(&gl, GL::ELEMENT_ARRAY_BUFFER) };
+        let edge_ibo = unsafe { Buffer::new(&gl, GL::ELEMENT_ARRAY_BUFFER) };
         Self {
             gl,
             loaded: false,
             vao,
             vbo,
             ibo,
+            edge_ibo,
             vertices: None,
             indices: None,
+            edges: Vec::new(),
             draw_wireframe: true,
             color: [1.0, 1.0, 1.0, 1.0],
+            bounding_box: None,
+            bounding_sphere: None,
+            bvh: None,
+            generated_normals: vec![],
+            texture: None,
+            submeshes: Vec::new(),
+            color_override: false,
+            vertex_colors: false,
+            points: false,
+            point_size: DEFAULT_POINT_SIZE,
         }
     }
 
+    /// Replaces the geometry with an indexed triangle list.
     pub fn update_model(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
-        // TODO - need to drop a previous allocation here?
+        let edges = triangle_edges(&indices);
+        self.points = false;
+        self.bvh = Some(Bvh::new(&vertices, &indices));
+        self.upload(vertices, indices, edges);
+    }
+
+    /// Replaces the geometry with indexed line segments, which are always drawn as lines.
+    pub fn update_lines(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.points = false;
+        self.bvh = None;
+        self.upload(vertices, Vec::new(), indices);
+    }
+
+    /// Replaces the geometry with a point cloud, one point per vertex.
+    pub fn update_points(&mut self, vertices: Vec<Vertex>) {
+        self.points = true;
+        self.bvh = None;
+        self.upload(vertices, Vec::new(), Vec::new());
+    }
+
+    fn upload(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>, edges: Vec<u32>) {
+        self.bounding_box = BoundingBox::from_vertices(&vertices);
+        self.bounding_sphere = BoundingSphere::from_vertices(&vertices);
+
         // TODO - is RwLock needed here?
-        self.vertices = Some(RwLock::new(Vec::new()));
-        self.indices = Some(RwLock::new(Vec::new()));
-        self.vertices
-            .as_ref()
-            .unwrap()
-            .write()
-            .unwrap()
-            .extend(vertices.clone());
-        self.indices
-            .as_ref()
-            .unwrap()
-            .write()
-            .unwrap()
-            .extend(indices.clone());
+        self.vertices = Some(RwLock::new(vertices));
+        self.indices = Some(RwLock::new(indices));
+        self.edges = edges;
+        self.upload_buffers();
+        self.loaded = true;
+    }
+
+    // Copies the geometry into the GL buffers, replacing what they held before
+    fn upload_buffers(&mut self) {
         unsafe {
             let vao = &self.vao;
             let gl = &self.gl;
             self.vbo.set_data(
-                &self.gl,
+                gl,
                 self.vertices.as_ref().unwrap().read().unwrap().to_vec(),
                 GL::STATIC_DRAW,
             );
             set_attribute!(vao, gl, 0, Vertex::0);
             set_attribute!(vao, gl, 1, Vertex::1);
+            set_attribute!(vao, gl, 2, Vertex::2);
+            set_attribute!(vao, gl, 3, Vertex::3);
+            self.edge_ibo
+                .set_data(gl, self.edges.clone(), GL::STATIC_DRAW);
             self.ibo.set_data(
-                &gl,
+                gl,
                 self.indices.as_ref().unwrap().read().unwrap().to_vec(),
                 GL::STATIC_DRAW,
             );
-            //self.vao.bind(&gl)
         };
-        self.loaded = true;
     }
 
-    pub fn set_color(&mut self, color: [f32; 4]) {
-        self.color = color;
+    /// Makes new GL objects after the context was lost and restored, and uploads the geometry and
+    /// textures kept on the CPU to them.
+    pub fn restore(&mut self) {
+        unsafe {
+            self.vao = VertexArray::new(&self.gl);
+            self.vbo = Buffer::new(&self.gl, GL::ARRAY_BUFFER);
+            self.ibo = Buffer::new(&self.gl, GL::ELEMENT_ARRAY_BUFFER);
+            self.edge_ibo = Buffer::new(&self.gl, GL::ELEMENT_ARRAY_BUFFER);
+        }
+        if self.loaded {
+            self.upload_buffers();
+        }
+        let textures = self
+            .submeshes
+            .iter()
+            .flat_map(|s| [&s.diffuse_map, &s.bump_map])
+            .chain([&self.texture]);
+        for texture in textures.flatten() {
+            unsafe { texture.restore() };
+        }
     }
 
-    pub fn get_color(&self) -> [f32; 4] {
-        self.color
+    /// Splits the triangles into per-material submeshes.
+    ///
+    /// `textures` holds the texture maps by file name; maps missing from it are skipped. Groups
+    /// without a material, or an empty list, are drawn in the model color.
+    pub fn set_submeshes(&mut self, groups: &[MeshGroup], textures: &HashMap<String, Rc<Texture>>) {
+        let texture = |name: &Option<String>| textures.get(name.as_ref()?).cloned();
+
+        self.submeshes = groups
+            .iter()
+            .map(|group| {
+                let material = group.material.as_ref();
+                Submesh {
+                    start: group.start,
+                    count: group.count,
+                    material: material.cloned(),
+                    diffuse_map: material.and_then(|m| texture(&m.diffuse_map)),
+                    bump_map: material.and_then(|m| texture(&m.bump_map)),
+                }
+            })
+            .collect();
+        if self.submeshes.iter().all(|s| s.material.is_none()) {
+            self.submeshes.clear();
+        }
+    }
+
+    /// Draws every submesh in the model color instead of its material's diffuse color.
+    pub fn set_color_override(&mut self, color_override: bool) {
+        self.color_override = color_override;
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
     }
 
     pub fn set_draw_wireframe(&mut self, draw_wireframe: bool) {
         self.draw_wireframe = draw_wireframe;
     }
 
-    pub fn get_draw_wireframe(&self) -> bool {
-        self.draw_wireframe
+    /// Marks which vertices got generated normals rather than ones read from the file, empty when
+    /// none did.
+    pub fn set_generated_normals(&mut self, generated_normals: Vec<bool>) {
+        self.generated_normals = generated_normals;
+    }
+
+    pub fn get_generated_normals(&self) -> &[bool] {
+        &self.generated_normals
+    }
+
+    /// Draws the per-vertex colors instead of the model color, unless the color is overridden.
+    pub fn set_vertex_colors(&mut self, vertex_colors: bool) {
+        self.vertex_colors = vertex_colors;
+    }
+
+    /// Sets the size in pixels of point cloud points.
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.point_size = point_size;
+    }
+
+    /// Draws `texture` in place of the diffuse color and maps, or stops doing so with `None`.
+    pub fn set_texture(&mut self, texture: Option<Rc<Texture>>) {
+        self.texture = texture;
+    }
+
+    pub fn get_vertices(&self) -> Vec<Vertex> {
+        match self.vertices {
+            Some(ref vertices) => vertices.read().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_indices(&self) -> Vec<u32> {
+        match self.indices {
+            Some(ref indices) => indices.read().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_bounding_box(&self) -> Option<BoundingBox> {
+        self.bounding_box
+    }
+
+    pub fn get_bounding_sphere(&self) -> Option<BoundingSphere> {
+        self.bounding_sphere
+    }
+
+    /// The geometry in the form the importers produce, for the exporters.
+    ///
+    /// With `as_drawn` every triangle gets the material it is drawn with: the model color fills
+    /// in for missing or overridden colors, and the texture from `set_texture` replaces diffuse
+    /// maps under the name `MODEL_TEXTURE_NAME`. Otherwise the materials are left as imported.
+    /// Lines are left out, so the grid has no mesh.
+    pub fn to_mesh(&self, as_drawn: bool) -> Option<ImportedMesh> {
+        (self.loaded && (self.points || self.get_indices_count() > 0)).then(|| ImportedMesh {
+            vertices: self.get_vertices(),
+            indices: self.get_indices(),
+            groups: if as_drawn {
+                self.drawn_groups()
+            } else {
+                self.submeshes
+                    .iter()
+                    .map(|s| MeshGroup {
+                        start: s.start,
+                        count: s.count,
+                        material: s.material.clone(),
+                    })
+                    .collect()
+            },
+            generated_normals: self.generated_normals.clone(),
+            vertex_colors: self.vertex_colors,
+            warnings: vec![],
+        })
+    }
+
+    // The groups `draw_geometry` draws, each with the material it ends up using
+    fn drawn_groups(&self) -> Vec<MeshGroup> {
+        // Vertex colors are drawn in place of the model color
+        let model_material = if self.vertex_colors && !self.color_override {
+            Material::with_color([1.0, 1.0, 1.0, self.color[3]])
+        } else {
+            Material::with_color(self.color)
+        };
+        let drawn = |material: Option<&Material>| {
+            let mut material = match material {
+                Some(material) if self.color_override => Material {
+                    diffuse: model_material.diffuse,
+                    diffuse_map: None,
+                    ..material.clone()
+                },
+                Some(material) => material.clone(),
+                None => model_material.clone(),
+            };
+            if self.texture.is_some() {
+                material.diffuse_map = Some(MODEL_TEXTURE_NAME.to_string());
+            }
+            Some(material)
+        };
+
+        if self.submeshes.is_empty() {
+            return vec![MeshGroup {
+                start: 0,
+                count: self.get_indices_count(),
+                material: drawn(None),
+            }];
+        }
+        self.submeshes
+            .iter()
+            .map(|s| MeshGroup {
+                start: s.start,
+                count: s.count,
+                material: drawn(s.material.as_ref()),
+            })
+            .collect()
+    }
+
+    /// Adds the images behind the texture maps to `images`, by the names the materials from
+    /// `to_mesh` use.
+    pub fn collect_texture_images(&self, images: &mut HashMap<String, TextureImage>) {
+        let mut add = |name: Option<&String>, texture: &Option<Rc<Texture>>| {
+            let image = texture.as_ref().and_then(|t| t.image.as_ref());
+            if let (Some(name), Some(image)) = (name, image) {
+                images.entry(name.clone()).or_insert_with(|| image.clone());
+            }
+        };
+        for submesh in &self.submeshes {
+            let material = submesh.material.as_ref();
+            add(
+                material.and_then(|m| m.diffuse_map.as_ref()),
+                &submesh.diffuse_map,
+            );
+            add(
+                material.and_then(|m| m.bump_map.as_ref()),
+                &submesh.bump_map,
+            );
+        }
+        add(Some(&MODEL_TEXTURE_NAME.to_string()), &self.texture);
+    }
+
+    /// The vertex indices of triangle `index`.
+    pub fn triangle(&self, index: usize) -> Option<[u32; 3]> {
+        let indices = self.indices.as_ref()?.read().unwrap();
+        let corners = indices.get(index * 3..index * 3 + 3)?;
+        Some([corners[0], corners[1], corners[2]])
+    }
+
+    /// The closest triangle along a ray in the object's own space.
+    pub fn intersect(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<BvhHit> {
+        self.bvh.as_ref()?.intersect(origin, direction)
     }
 
     pub fn get_indices_count(&self) -> usize {
@@ -96,8 +366,144 @@ impl ModelObject {
         0
     }
 
+    /// Bytes of vertex and index data kept for the object.
+    pub fn memory_size(&self) -> usize {
+        let vertices = self
+            .vertices
+            .as_ref()
+            .map_or(0, |v| v.read().unwrap().len());
+        vertices * std::mem::size_of::<Vertex>()
+            + (self.get_indices_count() + self.edges.len()) * std::mem::size_of::<u32>()
+            + self.generated_normals.len()
+    }
+
     pub fn bind(&self) {
         unsafe { self.vbo.bind(&self.gl) };
         unsafe { self.vao.bind(&self.gl) };
     }
+
+    /// Draws the object placed by `world`, which is loaded into the `model_loc` model matrix
+    /// uniform.
+    pub fn draw(
+        &self,
+        uniforms: &MaterialUniforms,
+        model_loc: &WebGlUniformLocation,
+        world: &Matrix4<f32>,
+    ) {
+        if !self.loaded {
+            return;
+        }
+        self.gl
+            .uniform_matrix4fv_with_f32_array(Some(model_loc), false, &matrix4_to_array(world));
+        self.draw_geometry(uniforms);
+    }
+
+    /// Draws the points, the triangles, or their edges when in wireframe mode or when there are
+    /// only lines.
+    ///
+    /// Each submesh is drawn with its own material, opaque ones first so transparent ones blend
+    /// over them.
+    fn draw_geometry(&self, uniforms: &MaterialUniforms) {
+        self.bind();
+
+        let model_material = Material::with_color(self.color);
+        uniforms.set_vertex_colors(&self.gl, self.vertex_colors && !self.color_override);
+        if self.points {
+            uniforms.apply(&self.gl, &model_material, false, false);
+            uniforms.set_point_size(&self.gl, self.point_size);
+            let count = self
+                .vertices
+                .as_ref()
+                .map_or(0, |v| v.read().unwrap().len());
+            self.gl.draw_arrays(GL::POINTS, 0, count as i32);
+            return;
+        }
+
+        let triangle_count = self.get_indices_count();
+        if self.draw_wireframe || triangle_count == 0 {
+            uniforms.apply(&self.gl, &model_material, false, false);
+            unsafe { self.edge_ibo.bind(&self.gl) };
+            self.gl
+                .draw_elements_with_i32(GL::LINES, self.edges.len() as i32, GL::UNSIGNED_INT, 0);
+            return;
+        }
+
+        unsafe { self.ibo.bind(&self.gl) };
+        if self.submeshes.is_empty() {
+            self.draw_triangles(uniforms, &model_material, None, None, 0, triangle_count);
+            return;
+        }
+        let (opaque, transparent): (Vec<&Submesh>, Vec<&Submesh>) = self
+            .submeshes
+            .iter()
+            .partition(|s| s.material.as_ref().is_none_or(|m| m.opacity >= 1.0));
+        for submesh in opaque.into_iter().chain(transparent) {
+            match submesh.material {
+                Some(ref material) if !self.color_override => self.draw_triangles(
+                    uniforms,
+                    material,
+                    submesh.diffuse_map.as_deref(),
+                    submesh.bump_map.as_deref(),
+                    submesh.start,
+                    submesh.count,
+                ),
+                Some(ref material) => {
+                    let material = Material {
+                        diffuse: model_material.diffuse,
+                        ..material.clone()
+                    };
+                    let bump_map = submesh.bump_map.as_deref();
+                    self.draw_triangles(
+                        uniforms,
+                        &material,
+                        None,
+                        bump_map,
+                        submesh.start,
+                        submesh.count,
+                    );
+                }
+                None => self.draw_triangles(
+                    uniforms,
+                    &model_material,
+                    None,
+                    None,
+                    submesh.start,
+                    submesh.count,
+                ),
+            }
+        }
+    }
+
+    // The texture set with `set_texture` takes precedence over the material's diffuse map
+    fn draw_triangles(
+        &self,
+        uniforms: &MaterialUniforms,
+        material: &Material,
+        diffuse_map: Option<&Texture>,
+        bump_map: Option<&Texture>,
+        start: usize,
+        count: usize,
+    ) {
+        let diffuse_map = self.texture.as_deref().or(diffuse_map);
+        unsafe {
+            if let Some(texture) = diffuse_map {
+                texture.bind(&self.gl, DIFFUSE_MAP_UNIT);
+            }
+            if let Some(texture) = bump_map {
+                texture.bind(&self.gl, BUMP_MAP_UNIT);
+            }
+        }
+        uniforms.apply(
+            &self.gl,
+            material,
+            diffuse_map.is_some(),
+            bump_map.is_some(),
+        );
+        self.gl.draw_elements_with_i32(
+            GL::TRIANGLES,
+            count as i32,
+            GL::UNSIGNED_INT,
+            (start * std::mem::size_of::<u32>()) as i32,
+        );
+    }
 }
@@ -0,0 +1,80 @@
+use cgmath::Vector2;
+use wasm_bindgen::prelude::*;
+
+use crate::global::{CAMERA, CANVAS, SCENE};
+use crate::scene::NodeId;
+use crate::trigger_draw_event;
+
+/// The triangle under a point of the canvas.
+#[wasm_bindgen(getter_with_clone)]
+pub struct PickResult {
+    pub node_id: NodeId,
+    /// Index of the triangle in the node's mesh.
+    pub triangle: u32,
+    /// Index of the triangle corner closest to the hit, among the mesh's vertices.
+    pub vertex: u32,
+    /// Weights of the triangle's three corners at the hit.
+    pub barycentrics: Vec<f32>,
+    /// World space position of the hit.
+    pub position: Vec<f32>,
+    /// Distance from the near plane to the hit, in world units.
+    pub distance: f32,
+}
+
+/// Casts a ray through `mouse`, in pixels over a `viewport` sized canvas, against the visible
+/// nodes.
+pub fn pick_at(mouse: Vector2<f32>, viewport: Vector2<f32>) -> Option<PickResult> {
+    let ndc_x = 2.0 * mouse.x / viewport.x.max(1.0) - 1.0;
+    let ndc_y = 1.0 - 2.0 * mouse.y / viewport.y.max(1.0);
+    let (origin, direction) = CAMERA.with(|camera| camera.read().unwrap().ray(ndc_x, ndc_y));
+    SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        let scene = scene.as_ref()?;
+        let (node_id, hit, position) = scene.pick(origin, direction)?;
+        let corners = scene
+            .get(node_id)
+            .ok()?
+            .object
+            .triangle(hit.triangle as usize)?;
+        let nearest = (0..3)
+            .max_by(|a, b| hit.barycentrics[*a].total_cmp(&hit.barycentrics[*b]))
+            .unwrap_or(0);
+        Some(PickResult {
+            node_id,
+            triangle: hit.triangle,
+            vertex: corners[nearest],
+            barycentrics: hit.barycentrics.to_vec(),
+            position: vec![position.x, position.y, position.z],
+            distance: hit.distance,
+        })
+    })
+}
+
+/// Attaches the gizmo to the node under `mouse`, or hides it when there is none.
+pub fn select_at(mouse: Vector2<f32>, viewport: Vector2<f32>) {
+    let picked = pick_at(mouse, viewport).map(|pick| pick.node_id);
+    SCENE.with(|scene| {
+        if let Some(scene) = scene.write().unwrap().as_mut() {
+            scene.select(picked).ok();
+        }
+    });
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+}
+
+/// Finds the triangle under a point of the canvas, given in CSS pixels from its top left corner.
+///
+/// Points and lines are not picked.
+#[wasm_bindgen]
+pub fn pick(x: f32, y: f32) -> Option<PickResult> {
+    let viewport = CANVAS.with(|canvas| {
+        let canvas = canvas.read().unwrap();
+        let canvas = canvas.as_ref()?;
+        Some(Vector2::new(
+            canvas.client_width() as f32,
+            canvas.client_height() as f32,
+        ))
+    })?;
+    pick_at(Vector2::new(x, y), viewport)
+}
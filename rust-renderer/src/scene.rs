@@ -0,0 +1,725 @@
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use renderer_core::bounds::{BoundingBox, BoundingSphere};
+use renderer_core::bvh::BvhHit;
+use renderer_core::geometry::{regenerate_marked_normals, NormalMode};
+use renderer_core::import::ImportedNode;
+use renderer_core::render::DEFAULT_POINT_SIZE;
+use renderer_core::texture::TextureImage;
+use renderer_core::transform::Trs;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext as GL, WebGlUniformLocation};
+
+use crate::global::SCENE;
+use crate::history::{record_edit, Change, Edit};
+use crate::material::MaterialUniforms;
+use crate::model::ModelObject;
+use crate::texture::Texture;
+use crate::trigger_draw_event;
+
+/// Identifies a node for as long as it is in the scene. Ids are not reused.
+pub type NodeId = u32;
+
+/// An object in the scene, placed relative to its parent.
+pub struct SceneNode {
+    pub name: String,
+    pub transform: Trs,
+    pub parent: Option<NodeId>,
+    /// Hidden nodes are not drawn, and neither are their children.
+    pub visible: bool,
+    /// Empty for nodes that only group their children.
+    pub object: ModelObject,
+    children: Vec<NodeId>,
+}
+
+/// Where a node sits in the tree: its parent, its position among its siblings and its transform
+/// relative to the parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub parent: Option<NodeId>,
+    pub index: usize,
+    pub transform: Trs,
+}
+
+/// A node and its descendants taken out of the scene by `Scene::detach`, to be put back as they
+/// were by `Scene::attach`.
+pub struct DetachedNodes {
+    root: NodeId,
+    parent: Option<NodeId>,
+    index: usize,
+    nodes: Vec<(NodeId, SceneNode)>,
+}
+
+impl DetachedNodes {
+    /// Bytes of geometry held by the nodes.
+    pub fn memory_size(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|(_, node)| node.object.memory_size())
+            .sum()
+    }
+
+    /// Makes new GL objects for the nodes after a lost context, so they draw once put back.
+    pub fn restore(&mut self) {
+        for (_, node) in &mut self.nodes {
+            node.object.restore();
+        }
+    }
+}
+
+/// Every object that is drawn: the grid plus a tree of named nodes.
+///
+/// The display settings (color, wireframe, texture and point size) apply to all nodes, including
+/// ones added later.
+pub struct Scene {
+    gl: GL,
+    nodes: HashMap<NodeId, SceneNode>,
+    roots: Vec<NodeId>,
+    next_id: NodeId,
+    /// The node the transform gizmo is attached to.
+    selected: Option<NodeId>,
+    grid: ModelObject,
+    color: [f32; 4],
+    color_override: bool,
+    draw_wireframe: bool,
+    texture: Option<Rc<Texture>>,
+    point_size: f32,
+}
+
+impl Scene {
+    /// An empty scene with `grid` drawn beneath it.
+    pub fn new(gl: GL, grid: ModelObject) -> Self {
+        Self {
+            gl,
+            nodes: HashMap::new(),
+            roots: Vec::new(),
+            next_id: 1,
+            selected: None,
+            grid,
+            color: [0.75, 0.75, 0.75, 1.0],
+            color_override: false,
+            draw_wireframe: false,
+            texture: None,
+            point_size: DEFAULT_POINT_SIZE,
+        }
+    }
+
+    /// Adds an empty node under `parent`, or at the top level.
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        transform: Trs,
+    ) -> Result<NodeId, String> {
+        if let Some(parent) = parent {
+            self.get(parent)?;
+        }
+        let mut object = ModelObject::new(self.gl.clone());
+        self.apply_display_settings(&mut object);
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(
+            id,
+            SceneNode {
+                name: name.to_string(),
+                transform,
+                parent,
+                visible: true,
+                object,
+                children: Vec::new(),
+            },
+        );
+        self.siblings_mut(parent).push(id);
+        Ok(id)
+    }
+
+    /// Adds `node` and its children under `parent`, returning the id of `node`.
+    pub fn add_imported(
+        &mut self,
+        parent: Option<NodeId>,
+        node: ImportedNode,
+        textures: &HashMap<String, Rc<Texture>>,
+    ) -> Result<NodeId, String> {
+        let id = self.add_node(&node.name, parent, Trs::from_matrix(&node.transform))?;
+        if let Some(mesh) = node.mesh {
+            let object = &mut self.get_mut(id)?.object;
+            if mesh.indices.is_empty() {
+                object.update_points(mesh.vertices);
+            } else {
+                object.update_model(mesh.vertices, mesh.indices);
+            }
+            object.set_submeshes(&mesh.groups, textures);
+            object.set_generated_normals(mesh.generated_normals);
+            object.set_vertex_colors(mesh.vertex_colors);
+        }
+        for child in node.children {
+            self.add_imported(Some(id), child, textures)?;
+        }
+        Ok(id)
+    }
+
+    // Gives an object the display settings shared by every node
+    fn apply_display_settings(&self, object: &mut ModelObject) {
+        object.set_color(self.color);
+        object.set_color_override(self.color_override);
+        object.set_draw_wireframe(self.draw_wireframe);
+        object.set_texture(self.texture.clone());
+        object.set_point_size(self.point_size);
+    }
+
+    /// Takes a node along with all of its descendants out of the scene.
+    pub fn detach(&mut self, id: NodeId) -> Result<DetachedNodes, String> {
+        let Placement { parent, index, .. } = self.placement(id)?;
+        self.siblings_mut(parent).remove(index);
+        let mut nodes = Vec::new();
+        let mut removed = vec![id];
+        while let Some(id) = removed.pop() {
+            if let Some(node) = self.nodes.remove(&id) {
+                removed.extend_from_slice(&node.children);
+                nodes.push((id, node));
+            }
+        }
+        if self.selected.is_some_and(|s| !self.nodes.contains_key(&s)) {
+            self.selected = None;
+        }
+        Ok(DetachedNodes {
+            root: id,
+            parent,
+            index,
+            nodes,
+        })
+    }
+
+    /// Puts detached nodes back where they were, with the current display settings. When their
+    /// parent is gone the nodes are handed back with the error.
+    pub fn attach(&mut self, detached: DetachedNodes) -> Result<(), (DetachedNodes, String)> {
+        if let Some(parent) = detached.parent {
+            if let Err(e) = self.get(parent) {
+                return Err((detached, e));
+            }
+        }
+        for (id, mut node) in detached.nodes {
+            self.apply_display_settings(&mut node.object);
+            self.nodes.insert(id, node);
+        }
+        let siblings = self.siblings_mut(detached.parent);
+        siblings.insert(detached.index.min(siblings.len()), detached.root);
+        Ok(())
+    }
+
+    pub fn rename_node(&mut self, id: NodeId, name: &str) -> Result<(), String> {
+        self.get_mut(id)?.name = name.to_string();
+        Ok(())
+    }
+
+    /// Moves a node under `parent`, or to the top level, keeping where it is in the world.
+    ///
+    /// A node cannot be moved under itself or one of its descendants. Shear from a non-uniformly
+    /// scaled parent cannot be kept.
+    pub fn reparent_node(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), String> {
+        self.get(id)?;
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            if a == id {
+                return Err(format!("node {} cannot be moved under itself", id));
+            }
+            ancestor = self.get(a)?.parent;
+        }
+
+        let world = self.world_transform(id)?;
+        let parent_world = match parent {
+            Some(parent) => self.world_transform(parent)?,
+            None => Matrix4::identity(),
+        };
+        let mut transform = self.get(id)?.transform;
+        // A parent that flattens its children leaves nothing to keep, so the local transform stays
+        if let Some(inverse) = parent_world.invert() {
+            transform = Trs::from_matrix(&(inverse * world));
+        }
+        let index = self.siblings_mut(parent).len();
+        self.place(
+            id,
+            Placement {
+                parent,
+                index,
+                transform,
+            },
+        )
+    }
+
+    pub fn placement(&self, id: NodeId) -> Result<Placement, String> {
+        let node = self.get(id)?;
+        let siblings = match node.parent {
+            Some(parent) => self.children(parent),
+            None => &self.roots,
+        };
+        Ok(Placement {
+            parent: node.parent,
+            index: siblings.iter().position(|n| *n == id).unwrap_or(0),
+            transform: node.transform,
+        })
+    }
+
+    /// Moves a node to `placement` as given, without keeping where it is in the world or checking
+    /// that the parent is not one of its descendants.
+    pub fn place(&mut self, id: NodeId, placement: Placement) -> Result<(), String> {
+        let old_parent = self.get(id)?.parent;
+        if let Some(parent) = placement.parent {
+            self.get(parent)?;
+        }
+        self.siblings_mut(old_parent).retain(|n| *n != id);
+        let siblings = self.siblings_mut(placement.parent);
+        siblings.insert(placement.index.min(siblings.len()), id);
+        let node = self.get_mut(id)?;
+        node.parent = placement.parent;
+        node.transform = placement.transform;
+        Ok(())
+    }
+
+    /// Attaches the transform gizmo to a node, or removes it.
+    pub fn select(&mut self, id: Option<NodeId>) -> Result<(), String> {
+        if let Some(id) = id {
+            self.get(id)?;
+        }
+        self.selected = id;
+        Ok(())
+    }
+
+    pub fn selected(&self) -> Option<NodeId> {
+        self.selected
+    }
+
+    pub fn set_visible(&mut self, id: NodeId, visible: bool) -> Result<(), String> {
+        self.get_mut(id)?.visible = visible;
+        Ok(())
+    }
+
+    pub fn get(&self, id: NodeId) -> Result<&SceneNode, String> {
+        self.nodes
+            .get(&id)
+            .ok_or_else(|| format!("there is no node {}", id))
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Result<&mut SceneNode, String> {
+        self.nodes
+            .get_mut(&id)
+            .ok_or_else(|| format!("there is no node {}", id))
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.nodes.get(&id).map_or(&[], |n| &n.children)
+    }
+
+    /// The top-level nodes, in drawing order.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // The child list `parent`'s children live in, the top level for `None`
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent.and_then(|p| self.nodes.get_mut(&p)) {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        }
+    }
+
+    /// Node ids with parents before their children, in drawing order.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids = self.roots.clone();
+        let mut i = 0;
+        while i < ids.len() {
+            ids.extend_from_slice(self.children(ids[i]));
+            i += 1;
+        }
+        ids
+    }
+
+    /// Transform from the node's space to world space.
+    pub fn world_transform(&self, id: NodeId) -> Result<Matrix4<f32>, String> {
+        let node = self.get(id)?;
+        Ok(match node.parent {
+            Some(parent) => self.world_transform(parent)? * node.transform.matrix(),
+            None => node.transform.matrix(),
+        })
+    }
+
+    /// Calls `f` with every node and its world transform, parents first, skipping hidden nodes
+    /// and their children.
+    pub fn for_each_visible<'a>(&'a self, f: &mut dyn FnMut(NodeId, &'a SceneNode, &Matrix4<f32>)) {
+        fn visit<'a>(
+            scene: &'a Scene,
+            ids: &[NodeId],
+            parent: &Matrix4<f32>,
+            f: &mut dyn FnMut(NodeId, &'a SceneNode, &Matrix4<f32>),
+        ) {
+            for id in ids {
+                let node = &scene.nodes[id];
+                if !node.visible {
+                    continue;
+                }
+                let world = parent * node.transform.matrix();
+                f(*id, node, &world);
+                visit(scene, &node.children, &world, f);
+            }
+        }
+        visit(self, &self.roots, &Matrix4::identity(), f);
+    }
+
+    /// Calls `f` on the object of every node.
+    pub fn for_each_object_mut(&mut self, f: &mut dyn FnMut(&mut ModelObject)) {
+        for node in self.nodes.values_mut() {
+            f(&mut node.object);
+        }
+    }
+
+    /// Draws the grid and then every visible node.
+    pub fn draw(&self, uniforms: &MaterialUniforms, model_loc: &WebGlUniformLocation) {
+        self.grid.draw(uniforms, model_loc, &Matrix4::identity());
+        self.for_each_visible(&mut |_, node, world| {
+            node.object.draw(uniforms, model_loc, world);
+        });
+    }
+
+    /// Sphere enclosing the visible nodes in world space, or `None` when nothing is loaded.
+    ///
+    /// A lone untransformed object gets its own tight sphere, otherwise the sphere encloses the
+    /// combined bounding box.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let mut placed: Vec<(&ModelObject, Matrix4<f32>)> = Vec::new();
+        self.for_each_visible(&mut |_, node, world| {
+            if node.object.get_bounding_box().is_some() {
+                placed.push((&node.object, *world));
+            }
+        });
+        if let [(object, world)] = placed[..] {
+            if world == Matrix4::identity() {
+                return object.get_bounding_sphere();
+            }
+        }
+        placed
+            .iter()
+            .filter_map(|(object, world)| Some(object.get_bounding_box()?.transformed(world)))
+            .reduce(|a, b| a.union(&b))
+            .map(|bounds: BoundingBox| BoundingSphere::from_box(&bounds))
+    }
+
+    /// The closest visible triangle along a world space ray, with its node and the world position
+    /// of the hit. The hit's distance is in units of `direction`'s length.
+    pub fn pick(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<(NodeId, BvhHit, Point3<f32>)> {
+        let mut closest: Option<(NodeId, BvhHit)> = None;
+        self.for_each_visible(&mut |id, node, world| {
+            // Affine transforms keep the ray parameter, so hits in object space compare directly
+            let Some(inverse) = world.invert() else {
+                return;
+            };
+            let hit = node.object.intersect(
+                inverse.transform_point(origin),
+                inverse.transform_vector(direction),
+            );
+            if let Some(hit) = hit {
+                if closest.is_none_or(|(_, c)| hit.distance < c.distance) {
+                    closest = Some((id, hit));
+                }
+            }
+        });
+        closest.map(|(id, hit)| (id, hit, origin + direction * hit.distance))
+    }
+
+    /// The visible nodes as imported nodes, for the exporters. See `ModelObject::to_mesh` for
+    /// `as_drawn`.
+    pub fn to_imported(&self, as_drawn: bool) -> Vec<ImportedNode> {
+        fn convert(scene: &Scene, ids: &[NodeId], as_drawn: bool) -> Vec<ImportedNode> {
+            ids.iter()
+                .map(|id| &scene.nodes[id])
+                .filter(|node| node.visible)
+                .map(|node| ImportedNode {
+                    name: node.name.clone(),
+                    transform: node.transform.matrix(),
+                    mesh: node.object.to_mesh(as_drawn),
+                    children: convert(scene, &node.children, as_drawn),
+                })
+                .collect()
+        }
+        convert(self, &self.roots, as_drawn)
+    }
+
+    /// The images behind every texture map, by the names the exported materials use.
+    pub fn texture_images(&self) -> HashMap<String, TextureImage> {
+        let mut images = HashMap::new();
+        for node in self.nodes.values() {
+            node.object.collect_texture_images(&mut images);
+        }
+        images
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+        self.for_each_object_mut(&mut |o| o.set_color(color));
+    }
+
+    pub fn color_override(&self) -> bool {
+        self.color_override
+    }
+
+    /// Draws the materials in the scene color instead of their own diffuse colors.
+    pub fn set_color_override(&mut self, color_override: bool) {
+        self.color_override = color_override;
+        self.for_each_object_mut(&mut |o| o.set_color_override(color_override));
+    }
+
+    pub fn set_draw_wireframe(&mut self, draw_wireframe: bool) {
+        self.draw_wireframe = draw_wireframe;
+        self.for_each_object_mut(&mut |o| o.set_draw_wireframe(draw_wireframe));
+    }
+
+    pub fn get_draw_wireframe(&self) -> bool {
+        self.draw_wireframe
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.point_size = point_size;
+        self.for_each_object_mut(&mut |o| o.set_point_size(point_size));
+    }
+
+    /// Uploads `image` for use with `set_texture`.
+    pub fn create_texture(&self, image: TextureImage) -> Rc<Texture> {
+        let mut texture = unsafe { Texture::new(&self.gl) };
+        unsafe { texture.set_image(&self.gl, image) };
+        Rc::new(texture)
+    }
+
+    pub fn texture(&self) -> Option<Rc<Texture>> {
+        self.texture.clone()
+    }
+
+    /// Draws `texture` on every object, or goes back to their own materials with `None`.
+    pub fn set_texture(&mut self, texture: Option<Rc<Texture>>) {
+        self.for_each_object_mut(&mut |o| o.set_texture(texture.clone()));
+        // The old texture is deleted once every object and the history have let go of it
+        self.texture = texture;
+    }
+
+    /// Makes new GL objects for the grid and every node after the context was lost and restored.
+    pub fn restore(&mut self) {
+        self.grid.restore();
+        self.for_each_object_mut(&mut |o| o.restore());
+        if let Some(ref texture) = self.texture {
+            unsafe { texture.restore() };
+        }
+    }
+
+    /// Rebuilds the normals that were generated rather than imported, leaving those from the file.
+    pub fn regenerate_normals(&mut self, mode: NormalMode) {
+        self.for_each_object_mut(&mut |object| {
+            if !object.get_generated_normals().is_empty() {
+                let (vertices, indices, generated) = regenerate_marked_normals(
+                    &object.get_vertices(),
+                    &object.get_indices(),
+                    object.get_generated_normals(),
+                    mode,
+                );
+                object.update_model(vertices, indices);
+                object.set_generated_normals(generated);
+            }
+        });
+    }
+}
+
+/// A node as seen from JavaScript.
+#[wasm_bindgen(getter_with_clone)]
+pub struct NodeInfo {
+    pub id: NodeId,
+    pub name: String,
+    pub parent: Option<NodeId>,
+    pub visible: bool,
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// X, Y and Z relative to the parent.
+    pub translation: Vec<f32>,
+    /// Degrees about X, then Y, then Z.
+    pub rotation: Vec<f32>,
+    pub scale: Vec<f32>,
+}
+
+// Runs `f` on the scene, then redraws
+fn edit_scene<T>(f: impl FnOnce(&mut Scene) -> Result<T, String>) -> Result<T, JsValue> {
+    let result = SCENE.with(|scene| {
+        let mut scene = scene.write().unwrap();
+        let scene = scene
+            .as_mut()
+            .ok_or_else(|| "renderer is not initialized".to_string())?;
+        f(scene)
+    });
+
+    // Trigger a custom event to redraw the scene
+    trigger_draw_event();
+
+    result.map_err(|e| JsValue::from_str(&e))
+}
+
+/// Lists every node, parents before their children.
+#[wasm_bindgen]
+pub fn list_nodes() -> Vec<NodeInfo> {
+    SCENE.with(|scene| {
+        let scene = scene.read().unwrap();
+        let Some(scene) = scene.as_ref() else {
+            return vec![];
+        };
+        scene
+            .node_ids()
+            .into_iter()
+            .map(|id| {
+                let node = &scene.nodes[&id];
+                let Trs {
+                    translation: t,
+                    scale,
+                    ..
+                } = node.transform;
+                NodeInfo {
+                    id,
+                    name: node.name.clone(),
+                    parent: node.parent,
+                    visible: node.visible,
+                    vertex_count: node.object.get_vertices().len(),
+                    triangle_count: node.object.get_indices_count() / 3,
+                    translation: vec![t.x, t.y, t.z],
+                    rotation: node.transform.euler_degrees().to_vec(),
+                    scale: vec![scale.x, scale.y, scale.z],
+                }
+            })
+            .collect()
+    })
+}
+
+/// Adds an empty node, for grouping other nodes under.
+#[wasm_bindgen]
+pub fn add_node(name: &str, parent: Option<NodeId>) -> Result<NodeId, JsValue> {
+    let mut id = 0;
+    record_edit(false, |scene| {
+        id = scene.add_node(name, parent, Trs::default())?;
+        Ok(Edit::Add(id, None))
+    })?;
+    Ok(id)
+}
+
+/// Removes a node and everything under it.
+#[wasm_bindgen]
+pub fn remove_node(id: NodeId) -> Result<(), JsValue> {
+    record_edit(false, |scene| Ok(Edit::Remove(id, Some(scene.detach(id)?))))
+}
+
+#[wasm_bindgen]
+pub fn rename_node(id: NodeId, name: &str) -> Result<(), JsValue> {
+    record_edit(false, |scene| {
+        let before = scene.get(id)?.name.clone();
+        scene.rename_node(id, name)?;
+        Ok(Edit::Rename(
+            id,
+            Change {
+                before,
+                after: name.to_string(),
+            },
+        ))
+    })
+}
+
+/// Moves a node under `parent`, or to the top level when it is not given, without moving it in
+/// the world.
+#[wasm_bindgen]
+pub fn reparent_node(id: NodeId, parent: Option<NodeId>) -> Result<(), JsValue> {
+    record_edit(false, |scene| {
+        let before = scene.placement(id)?;
+        scene.reparent_node(id, parent)?;
+        let after = scene.placement(id)?;
+        Ok(Edit::Reparent(id, Change { before, after }))
+    })
+}
+
+#[wasm_bindgen]
+pub fn set_node_visible(id: NodeId, visible: bool) -> Result<(), JsValue> {
+    record_edit(false, |scene| {
+        let before = scene.get(id)?.visible;
+        scene.set_visible(id, visible)?;
+        Ok(Edit::Visibility(
+            id,
+            Change {
+                before,
+                after: visible,
+            },
+        ))
+    })
+}
+
+/// Places a node relative to its parent, with the rotation given in degrees about X, then Y, then
+/// Z. Each argument holds three values.
+#[wasm_bindgen]
+pub fn set_node_transform(
+    id: NodeId,
+    translation: &[f32],
+    rotation: &[f32],
+    scale: &[f32],
+) -> Result<(), JsValue> {
+    let [translation, rotation, scale] = [translation, rotation, scale].map(<[f32; 3]>::try_from);
+    let (Ok(translation), Ok(rotation), Ok(scale)) = (translation, rotation, scale) else {
+        return Err(JsValue::from_str(
+            "translation, rotation and scale need three values each",
+        ));
+    };
+    record_edit(false, |scene| {
+        let transform = &mut scene.get_mut(id)?.transform;
+        let before = *transform;
+        transform.translation = translation.into();
+        transform.set_euler_degrees(rotation);
+        transform.scale = scale.into();
+        Ok(Edit::Transform(
+            id,
+            Change {
+                before,
+                after: *transform,
+            },
+        ))
+    })
+}
+
+/// Attaches the transform gizmo to a node, or hides it when no node is given.
+#[wasm_bindgen]
+pub fn select_node(id: Option<NodeId>) -> Result<(), JsValue> {
+    edit_scene(|scene| scene.select(id))
+}
+
+#[wasm_bindgen]
+pub fn selected_node() -> Option<NodeId> {
+    SCENE.with(|scene| scene.read().unwrap().as_ref()?.selected())
+}
+
+/// Removes every node from the scene and goes back to drawing the materials' own colors.
+#[wasm_bindgen]
+pub fn clear_scene() -> Result<(), JsValue> {
+    record_edit(false, |scene| {
+        let mut edits = Vec::new();
+        for id in scene.roots.clone() {
+            edits.push(Edit::Remove(id, Some(scene.detach(id)?)));
+        }
+        let color = scene.color();
+        edits.push(Edit::Color(Change {
+            before: (color, scene.color_override()),
+            after: (color, false),
+        }));
+        scene.set_color_override(false);
+        Ok(Edit::Batch("Clear", edits))
+    })
+}
@@ -0,0 +1,94 @@
+use renderer_core::texture::TextureImage;
+use std::cell::RefCell;
+use web_sys::{WebGl2RenderingContext as GL, WebGlTexture};
+
+use crate::gpu_memory::track;
+
+pub struct Texture {
+    gl: GL,
+    // Replaced by `restore` after a lost context, while the texture may be shared
+    obj: RefCell<Option<WebGlTexture>>,
+    /// The last image uploaded, kept for the exporters and for uploading again.
+    pub image: Option<TextureImage>,
+}
+
+impl Texture {
+    pub unsafe fn new(gl: &GL) -> Self {
+        let texture = gl.create_texture();
+        track(|memory| memory.textures += 1);
+        Self {
+            gl: gl.clone(),
+            obj: RefCell::new(texture),
+            image: None,
+        }
+    }
+
+    /// Binds the texture to the given texture unit.
+    pub unsafe fn bind(&self, gl: &GL, unit: u32) {
+        gl.active_texture(GL::TEXTURE0 + unit);
+        gl.bind_texture(GL::TEXTURE_2D, self.obj.borrow().as_ref());
+    }
+
+    /// Uploads `image` with mipmaps and repeat wrapping, and keeps it.
+    ///
+    /// Rows are flipped on upload so texture coordinates follow the OBJ convention of `v = 0` at
+    /// the bottom of the image.
+    pub unsafe fn set_image(&mut self, gl: &GL, image: TextureImage) {
+        self.upload(gl, &image);
+        let old_size = self.image.as_ref().map_or(0, |i| i.pixels.len());
+        track(|memory| memory.texture_bytes = memory.texture_bytes - old_size + image.pixels.len());
+        self.image = Some(image);
+    }
+
+    /// Makes a new texture from the kept image when the context lost the old one.
+    ///
+    /// Textures still valid are left alone, so a texture shared by several objects is uploaded
+    /// once however often it is restored.
+    pub unsafe fn restore(&self) {
+        if self.gl.is_texture(self.obj.borrow().as_ref()) {
+            return;
+        }
+        *self.obj.borrow_mut() = self.gl.create_texture();
+        if let Some(ref image) = self.image {
+            self.upload(&self.gl, image);
+        }
+    }
+
+    unsafe fn upload(&self, gl: &GL, image: &TextureImage) {
+        self.bind(gl, 0);
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 1);
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            image.width as i32,
+            image.height as i32,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&image.pixels),
+        )
+        .unwrap();
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.generate_mipmap(GL::TEXTURE_2D);
+        gl.tex_parameteri(
+            GL::TEXTURE_2D,
+            GL::TEXTURE_MIN_FILTER,
+            GL::LINEAR_MIPMAP_LINEAR as i32,
+        );
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::REPEAT as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::REPEAT as i32);
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        self.gl.delete_texture(self.obj.get_mut().as_ref());
+        let size = self.image.as_ref().map_or(0, |i| i.pixels.len());
+        track(|memory| {
+            memory.textures -= 1;
+            memory.texture_bytes -= size;
+        });
+    }
+}
@@ -1,5 +1,10 @@
 use web_sys::{WebGl2RenderingContext as GL, WebGlVertexArrayObject};
+
+use crate::gpu_memory::track;
+
 pub struct VertexArray {
+    gl: GL,
+    // None while the context is lost, the owner makes a new one once it is restored
     pub obj: Option<WebGlVertexArrayObject>,
 }
 
@@ -11,23 +16,25 @@ impl VertexArray {
 
 impl VertexArray {
     pub unsafe fn new(gl: &GL) -> Self {
-        let vao = gl
-            .create_vertex_array()
-            .ok_or("ERROR: could not create VAO")
-            .unwrap();
-        Self { obj: Some(vao) }
+        let vao = gl.create_vertex_array();
+        track(|memory| memory.vertex_arrays += 1);
+        Self {
+            gl: gl.clone(),
+            obj: vao,
+        }
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
-        // TODO
+        self.gl.delete_vertex_array(self.obj.as_ref());
+        track(|memory| memory.vertex_arrays -= 1);
     }
 }
 
 impl VertexArray {
     pub unsafe fn bind(&self, gl: &GL) {
-        gl.bind_vertex_array(Some(self.obj.as_ref().unwrap().as_ref()));
+        gl.bind_vertex_array(self.obj.as_ref());
     }
 }
 
@@ -1,34 +1,52 @@
 use js_sys::Uint8Array;
 use web_sys::{WebGl2RenderingContext as GL, WebGlBuffer};
+
+use crate::gpu_memory::track;
+
 pub struct Buffer {
-    pub obj: WebGlBuffer,
+    gl: GL,
+    // None while the context is lost, the owner makes new buffers once it is restored
+    pub obj: Option<WebGlBuffer>,
     target: u32,
+    // Bytes of the last upload, which replaced any before it
+    size: usize,
 }
 
 impl Buffer {
     pub unsafe fn new(gl: &GL, target: u32) -> Self {
-        let vbo = gl
-            .create_buffer()
-            .ok_or("ERROR: could not create VBO")
-            .unwrap();
-        Self { obj: vbo, target }
+        let vbo = gl.create_buffer();
+        track(|memory| memory.buffers += 1);
+        Self {
+            gl: gl.clone(),
+            obj: vbo,
+            target,
+            size: 0,
+        }
     }
 
     pub unsafe fn bind(&self, gl: &GL) {
-        gl.bind_buffer(self.target, Some(&self.obj));
+        gl.bind_buffer(self.target, self.obj.as_ref());
     }
 
-    pub unsafe fn set_data<D>(&self, gl: &GL, data: Vec<D>, usage: u32) {
+    pub unsafe fn set_data<D>(&mut self, gl: &GL, data: Vec<D>, usage: u32) {
         self.bind(gl);
         let (_, data_bytes, _) = data.align_to::<u8>();
         let js_array = Uint8Array::view(data_bytes);
         let js_object: js_sys::Object = js_array.into();
         gl.buffer_data_with_array_buffer_view(self.target, &js_object, usage);
+
+        let size = data_bytes.len();
+        track(|memory| memory.buffer_bytes = memory.buffer_bytes - self.size + size);
+        self.size = size;
     }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
-        // TODO
+        self.gl.delete_buffer(self.obj.as_ref());
+        track(|memory| {
+            memory.buffers -= 1;
+            memory.buffer_bytes -= self.size;
+        });
     }
 }